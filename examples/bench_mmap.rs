@@ -0,0 +1,59 @@
+//! `file_parse` against `file_parse_mmap` on a generated file.
+//!
+//! `cargo run --release --example bench_mmap --features mmap,fast-parser [repeats]`
+
+use std::time::{Duration, Instant};
+
+use sexpr_ir::syntax::{mexpr, sexpr};
+
+const SEXPR_ITEM: &str = "(define (fact n) ; factorial
+    (if (<= n 1u) 1 (* n (fact (- n 1)))))
+(config [(name . \"hoshino tented\") (age . 512) (ratio . -0.75)] '(a b . c))
+";
+
+const MEXPR_ITEM: &str = "if[cond; then; else]
+assert(henghengheng, 114514)
+{1, 2, 3}
+";
+
+fn time<T>(f: impl Fn() -> T) -> Duration {
+    // best of a few runs, so the page cache is warm for both
+    (0..5)
+        .map(|_| {
+            let now = Instant::now();
+            f();
+            now.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let repeats: usize = std::env::args()
+        .nth(1)
+        .map_or(500, |x| x.parse().expect("repeats"));
+    let dir = std::env::temp_dir().join(format!("bench_mmap_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("bench.sexpr");
+    std::fs::write(&path, SEXPR_ITEM.repeat(repeats)).unwrap();
+    let path = path.to_str().unwrap();
+    println!("sexpr: {} bytes", std::fs::metadata(path).unwrap().len());
+    println!("  read: {:?}", time(|| sexpr::file_parse(path).unwrap()));
+    println!(
+        "  mmap: {:?}",
+        time(|| sexpr::file_parse_mmap(path).unwrap())
+    );
+
+    let path = dir.join("bench.mexpr");
+    std::fs::write(&path, MEXPR_ITEM.repeat(repeats)).unwrap();
+    let path = path.to_str().unwrap();
+    println!("mexpr: {} bytes", std::fs::metadata(path).unwrap().len());
+    println!("  read: {:?}", time(|| mexpr::file_parse(path).unwrap()));
+    println!(
+        "  mmap: {:?}",
+        time(|| mexpr::file_parse_mmap(path).unwrap())
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
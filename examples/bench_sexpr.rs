@@ -0,0 +1,50 @@
+use std::time::Instant;
+
+use sexpr_ir::gast::arena::GAstArena;
+use sexpr_ir::gast::Handle;
+use sexpr_ir::syntax::sexpr::{fast, pest_parse};
+
+const ITEM: &str = "(define (fact n) ; factorial
+    (if (<= n 1u) 1 (* n (fact (- n 1)))))
+(config [(name . \"hoshino tented\") (age . 512) (ratio . -0.75)] '(a b . c) `(+ ,a ,@b))
+";
+
+fn main() {
+    let input = ITEM.repeat(500);
+    let path = Handle::new("<bench>".to_string());
+    println!("input: {} bytes", input.len());
+
+    let now = Instant::now();
+    let a = pest_parse(&input, path.clone()).unwrap();
+    let pest_time = now.elapsed();
+    println!("pest: {:?}", pest_time);
+
+    let now = Instant::now();
+    let b = fast::parse(&input, path.clone()).unwrap();
+    let fast_time = now.elapsed();
+    println!("fast: {:?}", fast_time);
+    assert_eq!(a, b);
+
+    let now = Instant::now();
+    let c = fast::parse_borrowed(&input, path.clone()).unwrap();
+    println!("fast, borrowed: {:?}", now.elapsed());
+    assert_eq!(c.len(), b.len());
+
+    let now = Instant::now();
+    let mut arena = GAstArena::new();
+    let d = fast::parse_into(&input, path, &mut arena).unwrap();
+    println!("fast, arena: {:?}", now.elapsed());
+    assert_eq!(d.len(), b.len());
+
+    let now = Instant::now();
+    drop(b);
+    println!("drop: {:?}", now.elapsed());
+    let now = Instant::now();
+    drop(arena);
+    println!("drop arena: {:?}", now.elapsed());
+
+    println!(
+        "speedup: {:.2}x",
+        pest_time.as_secs_f64() / fast_time.as_secs_f64()
+    );
+}
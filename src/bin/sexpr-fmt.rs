@@ -0,0 +1,126 @@
+//! Formats sexpr and mexpr files in place.
+//!
+//! `sexpr-fmt [--check] [--config FILE] FILE...` reads `.mexpr` files as
+//! mexpr and everything else as sexpr. Each file is formatted in the style
+//! of `--config` or else of the closest `.sexprfmt` above it. With
+//! `--check` files are only compared with their formatted text. Exits with
+//! 1 if a file is not formatted under `--check`, 2 if a file or style
+//! cannot be read or parsed.
+
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use sexpr_ir::diagnostics::Report;
+use sexpr_ir::gast::Handle;
+use sexpr_ir::syntax::format::{find_style, format_mexpr, format_sexpr, Style};
+use sexpr_ir::syntax::Syntax;
+
+const USAGE: &str = "usage: sexpr-fmt [--check] [--config FILE] FILE...";
+
+struct Options {
+    check: bool,
+    config: Option<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+fn options() -> Result<Options, String> {
+    let mut r = Options {
+        check: false,
+        config: None,
+        files: vec![],
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(x) = args.next() {
+        match x.as_str() {
+            "--check" => r.check = true,
+            "--config" => match args.next() {
+                Some(x) => r.config = Some(PathBuf::from(x)),
+                None => return Err("`--config` needs a file".to_string()),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            }
+            x if x.starts_with('-') => return Err(format!("unknown option `{}`", x)),
+            _ => r.files.push(PathBuf::from(x)),
+        }
+    }
+    if r.files.is_empty() {
+        return Err("no files given".to_string());
+    }
+    Ok(r)
+}
+
+fn read_style(path: &Path) -> Result<Style, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read `{}`: {}", path.display(), e))?;
+    let name = Handle::new(path.display().to_string());
+    Style::parse(&text, name).map_err(|e| format!("invalid style: {}", e))
+}
+
+/// The formatted text of a file, or the error to print.
+fn format_file(path: &Path, style: &Style) -> Result<(String, String), String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read `{}`: {}", path.display(), e))?;
+    let name = path.display().to_string();
+    let handle = Handle::new(name.clone());
+    let formatted = match Syntax::of_path(&name) {
+        Syntax::Sexpr => {
+            format_sexpr(&text, handle, style).map_err(|e| Report::from(&e.with_path(&name)))
+        }
+        Syntax::Mexpr => {
+            format_mexpr(&text, handle, style).map_err(|e| Report::from(&e.with_path(&name)))
+        }
+    };
+    let color = std::io::IsTerminal::is_terminal(&std::io::stderr());
+    match formatted {
+        Ok(x) => Ok((text, x)),
+        Err(e) => Err(e.render(&text, color).trim_end().to_string()),
+    }
+}
+
+fn main() {
+    let options = options().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        exit(2)
+    });
+    let fixed = options.config.as_deref().map(read_style).transpose();
+    let fixed = fixed.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2)
+    });
+
+    let mut errors = false;
+    let mut unformatted = false;
+    for path in &options.files {
+        let style = match &fixed {
+            Some(x) => Ok(x.clone()),
+            None => find_style(path).map_or(Ok(Style::default()), |x| read_style(&x)),
+        };
+        let result = style.and_then(|style| format_file(path, &style));
+        let (text, formatted) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("{}", e);
+                errors = true;
+                continue;
+            }
+        };
+        if text == formatted {
+            continue;
+        }
+        if options.check {
+            println!("{}", path.display());
+            unformatted = true;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("cannot write `{}`: {}", path.display(), e);
+            errors = true;
+        }
+    }
+    if errors {
+        exit(2)
+    }
+    if unformatted {
+        exit(1)
+    }
+}
@@ -0,0 +1,216 @@
+//! Checks sexpr and mexpr files against the lint rules.
+//!
+//! `sexpr-lint [--format human|json] [--config FILE] [--list] FILE...` reads
+//! `.mexpr` files as mexpr and everything else as sexpr. Each file is
+//! checked with the rules of `--config` or else of the closest `.sexprlint`
+//! above it. Lints are printed as diagnostics, or with `--format json` as
+//! one JSON array of all of them. There a file that does not parse is an
+//! entry of rule `parse-error`, and a file that cannot be read or a config
+//! that cannot be used one of rule `read-error` without location. `--list`
+//! prints the rules. Exits with 1 if a lint is an error, 2 if a file or
+//! config cannot be read or parsed.
+
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use serde::Serialize;
+use sexpr_ir::diagnostics::{Report, Severity};
+use sexpr_ir::gast::Handle;
+use sexpr_ir::lint::{find_config, Config, Lint, Linter};
+use sexpr_ir::syntax::Syntax;
+
+const USAGE: &str = "usage: sexpr-lint [--format human|json] [--config FILE] [--list] FILE...";
+
+struct Options {
+    json: bool,
+    list: bool,
+    config: Option<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+fn options() -> Result<Options, String> {
+    let mut r = Options {
+        json: false,
+        list: false,
+        config: None,
+        files: vec![],
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(x) = args.next() {
+        match x.as_str() {
+            "--format" => match args.next().as_deref() {
+                Some("human") => r.json = false,
+                Some("json") => r.json = true,
+                _ => return Err("`--format` needs `human` or `json`".to_string()),
+            },
+            "--config" => match args.next() {
+                Some(x) => r.config = Some(PathBuf::from(x)),
+                None => return Err("`--config` needs a file".to_string()),
+            },
+            "--list" => r.list = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            }
+            x if x.starts_with('-') => return Err(format!("unknown option `{}`", x)),
+            _ => r.files.push(PathBuf::from(x)),
+        }
+    }
+    if r.files.is_empty() && !r.list {
+        return Err("no files given".to_string());
+    }
+    Ok(r)
+}
+
+fn read_config(path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read `{}`: {}", path.display(), e))?;
+    let name = Handle::new(path.display().to_string());
+    Config::parse(&text, name).map_err(|e| format!("invalid config: {}", e))
+}
+
+/// A linter for the config, warning about rules it does not know.
+fn linter_for(config: Config) -> Linter {
+    let linter = Linter::new(config.clone());
+    for name in config.severities.keys() {
+        if !linter.rules().any(|x| x.name() == name) {
+            eprintln!("warning: unknown rule `{}` in config", name);
+        }
+    }
+    linter
+}
+
+/// Why a file was not checked.
+enum Failure {
+    Read(String),
+    /// the rendered error and its lint
+    Parse(String, Box<Lint>),
+}
+
+/// A `--format json` entry.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Entry {
+    Lint(Lint),
+    Read {
+        rule: &'static str,
+        severity: Severity,
+        message: String,
+        location: Option<()>,
+        span: Option<()>,
+        notes: [(); 0],
+    },
+}
+
+impl From<Failure> for Entry {
+    fn from(x: Failure) -> Self {
+        match x {
+            Failure::Read(message) => Entry::Read {
+                rule: "read-error",
+                severity: Severity::Error,
+                message,
+                location: None,
+                span: None,
+                notes: [],
+            },
+            Failure::Parse(_, lint) => Entry::Lint(*lint),
+        }
+    }
+}
+
+/// The lints of a file and its text.
+fn lint_file(path: &Path, linter: &Linter) -> Result<(String, Vec<Lint>), Failure> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Failure::Read(format!("cannot read `{}`: {}", path.display(), e)))?;
+    let name = path.display().to_string();
+    let handle = Handle::new(name.clone());
+    let color = std::io::IsTerminal::is_terminal(&std::io::stderr());
+    let failure = |report: Report, lint| {
+        Failure::Parse(report.render(&text, color).trim_end().to_string(), lint)
+    };
+    let lints = match Syntax::of_path(&name) {
+        Syntax::Sexpr => linter.check_sexpr(&text, handle.clone()).map_err(|e| {
+            failure(
+                Report::from(&e.clone().with_path(&name)),
+                Box::new(Lint::parse_error(&e, handle)),
+            )
+        }),
+        Syntax::Mexpr => linter.check_mexpr(&text, handle.clone()).map_err(|e| {
+            failure(
+                Report::from(&e.clone().with_path(&name)),
+                Box::new(Lint::parse_error(&e, handle)),
+            )
+        }),
+    };
+    lints.map(|x| (text, x))
+}
+
+fn main() {
+    let options = options().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        exit(2)
+    });
+    let fixed = options.config.as_deref().map(read_config).transpose();
+    let fixed = fixed.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2)
+    });
+    let fixed = fixed.map(linter_for);
+
+    if options.list {
+        let linter = Linter::new(Config::default());
+        let linter = fixed.as_ref().unwrap_or(&linter);
+        for rule in linter.rules() {
+            let severity = linter
+                .severity(rule)
+                .map_or("allow".to_string(), |x| x.to_string());
+            println!("{:<18} {:<8} {}", rule.name(), severity, rule.description());
+        }
+        return;
+    }
+
+    let color = std::io::IsTerminal::is_terminal(&std::io::stdout());
+    let mut errors = false;
+    let mut failed = false;
+    let mut all = vec![];
+    for path in &options.files {
+        let mut found = None;
+        let linter = match &fixed {
+            Some(x) => Ok(x),
+            None => find_config(path)
+                .map_or(Ok(Config::default()), |x| read_config(&x))
+                .map(|x| &*found.insert(linter_for(x))),
+        };
+        let result = linter
+            .map_err(Failure::Read)
+            .and_then(|linter| lint_file(path, linter));
+        let (text, lints) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                errors = true;
+                match e {
+                    _ if options.json => all.push(Entry::from(e)),
+                    Failure::Read(x) | Failure::Parse(x, _) => eprintln!("{}", x),
+                }
+                continue;
+            }
+        };
+        failed |= lints.iter().any(|x| x.severity == Severity::Error);
+        if options.json {
+            all.extend(lints.into_iter().map(Entry::Lint));
+        } else {
+            for x in &lints {
+                println!("{}", Report::from(x).render(&text, color));
+            }
+        }
+    }
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&all).unwrap());
+    }
+    if errors {
+        exit(2)
+    }
+    if failed {
+        exit(1)
+    }
+}
@@ -0,0 +1,9 @@
+//! Language server for sexpr and mexpr files over stdio.
+
+use std::io;
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let code = sexpr_ir::lsp::run(stdin.lock(), io::stdout())?;
+    std::process::exit(code)
+}
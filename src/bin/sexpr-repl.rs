@@ -0,0 +1,143 @@
+//! Reads sexpr or mexpr input and prints what it parses to.
+//!
+//! Input is read until its brackets balance and its strings are closed, so
+//! a form can span several lines; anything else that does not parse is
+//! reported right away. Lines starting with `:` are commands, see `:help`.
+
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use sexpr_ir::diagnostics::Report;
+use sexpr_ir::gast::{GAst, Handle};
+use sexpr_ir::syntax::{mexpr, sexpr, Syntax};
+
+const HELP: &str = "\
+:sexpr     read sexpr syntax
+:mexpr     read mexpr syntax
+:display   print results with `Display`
+:debug     print results with `Debug`
+:history   list the input history
+:help      show this message
+:quit      leave (or press Ctrl-D)";
+
+struct Repl {
+    syntax: Syntax,
+    debug: bool,
+    color: bool,
+}
+
+impl Repl {
+    fn prompt(&self, continued: bool) -> &'static str {
+        match (continued, self.syntax) {
+            (true, _) => "   ...> ",
+            (false, Syntax::Sexpr) => "sexpr> ",
+            (false, Syntax::Mexpr) => "mexpr> ",
+        }
+    }
+
+    /// Runs a `:` command, `false` to quit.
+    fn command(&mut self, line: &str, editor: &DefaultEditor) -> bool {
+        match line {
+            ":sexpr" => self.syntax = Syntax::Sexpr,
+            ":mexpr" => self.syntax = Syntax::Mexpr,
+            ":display" => self.debug = false,
+            ":debug" => self.debug = true,
+            ":history" => {
+                for (i, x) in editor.history().iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, x);
+                }
+            }
+            ":help" => println!("{}", HELP),
+            ":quit" | ":q" => return false,
+            _ => println!("unknown command `{}`, try :help", line),
+        }
+        true
+    }
+
+    fn eval(&self, input: &str) {
+        let path = "<stdin>";
+        let handle = Handle::new(path.to_string());
+        let forms = match self.syntax {
+            Syntax::Sexpr => {
+                sexpr::parse(input, handle).map_err(|e| Report::from(&e.with_path(path)))
+            }
+            Syntax::Mexpr => {
+                mexpr::parse(input, handle).map_err(|e| Report::from(&e.with_path(path)))
+            }
+        };
+        match forms {
+            Ok(forms) => forms.iter().for_each(|x| self.print(x)),
+            Err(e) => eprint!("{}", e.render(input, self.color)),
+        }
+    }
+
+    fn print(&self, ast: &GAst) {
+        if self.debug {
+            println!("{:#?}", ast);
+        } else {
+            println!("{}", ast);
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".sexpr_repl_history"))
+}
+
+fn main() -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(x) = &history {
+        // missing on the first run
+        editor.load_history(x).ok();
+    }
+    let mut repl = Repl {
+        syntax: Syntax::Sexpr,
+        debug: false,
+        color: std::io::stderr().is_terminal(),
+    };
+    let mut buffer = String::new();
+    loop {
+        let line = match editor.readline(repl.prompt(!buffer.is_empty())) {
+            Ok(x) => x,
+            // Ctrl-C drops the pending input
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            editor.add_history_entry(line.as_str())?;
+            if !repl.command(line.trim(), &editor) {
+                break;
+            }
+            continue;
+        }
+        if buffer.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+        let balance = match repl.syntax {
+            Syntax::Sexpr => sexpr::balance(&buffer),
+            Syntax::Mexpr => mexpr::balance(&buffer),
+        };
+        if balance.is_open() {
+            continue;
+        }
+        editor.add_history_entry(buffer.trim_end())?;
+        repl.eval(&buffer);
+        buffer.clear();
+    }
+    if let Some(x) = &history {
+        if let Err(e) = editor.save_history(x) {
+            eprintln!("cannot save history to `{}`: {}", x.display(), e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,327 @@
+//! Rendering errors with source snippets.
+//!
+//! A `Report` is a message with labelled byte spans of the source and notes.
+//! `render` prints it the way rustc does: the location of the first primary
+//! label, the source lines the labels are on with the spans underlined
+//! (`^` for primary labels, `-` for secondary ones), then the notes. Parse
+//! errors, recovered diagnostics and schema violations convert to reports;
+//! other errors can build their own from a `Location`.
+
+use std::fmt::Display;
+use std::ops::Range;
+
+use pest::error::{Error, InputLocation};
+use pest::RuleType;
+use serde::Serialize;
+
+use crate::gast::schema::Violation;
+use crate::gast::symbol::Location;
+use crate::syntax::sexpr::recover::Diagnostic;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const CYAN: &str = "\x1b[1;36m";
+const BLUE: &str = "\x1b[1;34m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN,
+            Severity::Help => CYAN,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// byte range in the source; an empty range marks one column
+    pub span: Range<usize>,
+    pub message: String,
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: Range<usize>, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(span: Range<usize>, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+            primary: false,
+        }
+    }
+
+    /// A primary label on `len` bytes from a location.
+    pub fn at(loc: &Location, len: usize, message: impl Into<String>) -> Self {
+        Self::primary(loc.pos..loc.pos + len, message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub severity: Severity,
+    pub message: String,
+    pub path: Option<String>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Report {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Report {
+            severity,
+            message: message.into(),
+            path: None,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the report against the text the spans point into, with ANSI
+    /// colors if `color`.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |s: &str, code: &str| {
+            if color {
+                format!("{}{}{}", code, s, RESET)
+            } else {
+                s.to_string()
+            }
+        };
+        let mut out = format!(
+            "{}{}\n",
+            paint(&self.severity.to_string(), self.severity.color()),
+            paint(&format!(": {}", self.message), BOLD)
+        );
+
+        // spans widened to whole chars, so slicing cannot panic
+        let mut labels: Vec<(Line, &Label, Range<usize>)> = self
+            .labels
+            .iter()
+            .map(|x| {
+                let start = floor_char_boundary(source, x.span.start);
+                let end = x.span.end.clamp(start, source.len());
+                let end = (end..).find(|&x| source.is_char_boundary(x)).unwrap();
+                (Line::of(source, start), x, start..end)
+            })
+            .collect();
+        labels.sort_by_key(|(line, _, span)| (line.number, span.start));
+        let width = labels
+            .last()
+            .map_or(1, |(x, _, _)| x.number.to_string().len());
+        let gutter = paint(&format!("{} |", " ".repeat(width)), BLUE);
+
+        let path = self.path.as_deref().unwrap_or("<input>");
+        let main = labels
+            .iter()
+            .find(|(_, x, _)| x.primary)
+            .or_else(|| labels.first());
+        match main {
+            Some((line, _, span)) => {
+                let colum = source[line.start..span.start.min(line.end)].chars().count() + 1;
+                let arrow = paint("-->", BLUE);
+                out += &format!(
+                    "{}{} {}:{}:{}\n",
+                    " ".repeat(width),
+                    arrow,
+                    path,
+                    line.number,
+                    colum
+                );
+            }
+            None => out += &format!("{}{} {}\n", " ".repeat(width), paint("-->", BLUE), path),
+        }
+
+        if !labels.is_empty() {
+            out += &format!("{}\n", gutter);
+        }
+        let mut prev = None;
+        for (i, (line, label, span)) in labels.iter().enumerate() {
+            if prev != Some(line.number) {
+                if prev.is_some_and(|x| x + 1 < line.number) {
+                    out += &format!("{}\n", paint("...", BLUE));
+                }
+                let number = paint(&format!("{:>w$} |", line.number, w = width), BLUE);
+                out += &format!(
+                    "{} {}\n",
+                    number,
+                    expand_tabs(&source[line.start..line.end])
+                );
+                prev = Some(line.number);
+            }
+            let start = span.start.clamp(line.start, line.end);
+            let end = span.end.clamp(start, line.end);
+            let pad = display_width(&source[line.start..start]);
+            let len = display_width(&source[start..end]).max(1);
+            let (mark, code) = if label.primary {
+                ("^", self.severity.color())
+            } else {
+                ("-", BLUE)
+            };
+            let mut underline = mark.repeat(len);
+            if !label.message.is_empty() {
+                underline = format!("{} {}", underline, label.message);
+            }
+            out += &format!(
+                "{} {}{}\n",
+                gutter,
+                " ".repeat(pad),
+                paint(&underline, code)
+            );
+            if i + 1 == labels.len() && !self.notes.is_empty() {
+                out += &format!("{}\n", gutter);
+            }
+        }
+        for note in &self.notes {
+            out += &format!(
+                "{} {} {}\n",
+                " ".repeat(width),
+                paint("=", BLUE),
+                paint_note(note, color)
+            );
+        }
+        out
+    }
+}
+
+/// `note: ...`, with the `note` bold.
+fn paint_note(note: &str, color: bool) -> String {
+    if color {
+        format!("{}note{}: {}", BOLD, RESET, note)
+    } else {
+        format!("note: {}", note)
+    }
+}
+
+/// A line of the source: number and byte range without the line break.
+struct Line {
+    number: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Line {
+    fn of(source: &str, pos: usize) -> Self {
+        let pos = floor_char_boundary(source, pos);
+        let start = source[..pos].rfind('\n').map_or(0, |x| x + 1);
+        let end = source[pos..].find('\n').map_or(source.len(), |x| pos + x);
+        let end = if source[..end].ends_with('\r') {
+            end - 1
+        } else {
+            end
+        };
+        Line {
+            number: source[..start].matches('\n').count() + 1,
+            start,
+            end: end.max(start),
+        }
+    }
+}
+
+/// `pos` clamped to `source` and moved back to the start of its char.
+pub(crate) fn floor_char_boundary(source: &str, pos: usize) -> usize {
+    let mut pos = pos.min(source.len());
+    while !source.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+const TAB_WIDTH: usize = 4;
+
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+impl<R: RuleType> From<&Error<R>> for Report {
+    fn from(e: &Error<R>) -> Self {
+        let span = match e.location {
+            InputLocation::Pos(x) => x..x,
+            InputLocation::Span((a, b)) => a..b,
+        };
+        let report = Report::error(e.variant.message()).with_label(Label::primary(span, ""));
+        match e.path() {
+            Some(path) => report.with_path(path),
+            None => report,
+        }
+    }
+}
+
+impl From<&Diagnostic> for Report {
+    fn from(d: &Diagnostic) -> Self {
+        Report::error(&d.message)
+            .with_path(d.location.path.as_str())
+            .with_label(Label::primary(d.span.clone(), ""))
+    }
+}
+
+impl From<&Violation> for Report {
+    fn from(v: &Violation) -> Self {
+        let report = Report::error(&v.message);
+        match &v.location {
+            Some(loc) => report
+                .with_path(loc.path.as_str())
+                .with_label(Label::at(loc, 0, "")),
+            None => report,
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+
 use crate::gast::symbol::Symbol;
 use crate::gast::Handle;
 
@@ -19,7 +23,8 @@ pub enum CompilerError<T> {
     RepeatedModule(Handle<Symbol>),
     RepeatedMacro(Handle<Symbol>),
     RepeatedFunction(Handle<Symbol>),
-    FileOpenError(Handle<String>),
+    /// the path as given or canonicalized, and why it could not be read
+    FileOpenError(Handle<String>, io::Error),
 }
 
 #[derive(Debug)]
@@ -32,3 +37,99 @@ pub enum RuntimeError {
     FunctionCallIsEmpty,
     CalleeIsNotCallable,
 }
+
+/// `` `name` at path:line:colum ``
+fn at(f: &mut Formatter<'_>, sym: &Symbol) -> Result {
+    write!(f, "`{}` at {}", sym, sym.1)
+}
+
+impl Display for SyntaxMatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::MatchError => f.write_str("syntax does not match"),
+            Self::MatchListSizeError => f.write_str("list has the wrong number of elements"),
+            Self::SyntaxRuleIsNotExist => f.write_str("no such syntax rule"),
+            Self::ExtendInMiddleError(x) => {
+                f.write_str("repetition in the middle of a pattern: ")?;
+                at(f, x)
+            }
+            Self::RepeatedSymbol(x) => {
+                f.write_str("symbol bound twice in a pattern: ")?;
+                at(f, x)
+            }
+            Self::SExprTypeCheckError(x) => {
+                f.write_str("wrong kind of expression: ")?;
+                at(f, x)
+            }
+            Self::SyntaxMatchError(x) => {
+                f.write_str("invalid syntax for ")?;
+                at(f, x)
+            }
+        }
+    }
+}
+
+impl Error for SyntaxMatchError {}
+
+impl<T: Display> Display for CompilerError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::ParseError(x) => write!(f, "parse error: {}", x),
+            Self::SyntaxMatchError(x) => x.fmt(f),
+            Self::RepeatedModule(x) => {
+                f.write_str("module defined twice: ")?;
+                at(f, x)
+            }
+            Self::RepeatedMacro(x) => {
+                f.write_str("macro defined twice: ")?;
+                at(f, x)
+            }
+            Self::RepeatedFunction(x) => {
+                f.write_str("function defined twice: ")?;
+                at(f, x)
+            }
+            Self::FileOpenError(path, _) => write!(f, "cannot read `{}`", path),
+        }
+    }
+}
+
+/// Wrapped errors are part of the message, so `source` goes on with theirs.
+impl<T: Error + 'static> Error for CompilerError<T> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ParseError(x) => x.source(),
+            Self::SyntaxMatchError(x) => x.source(),
+            Self::FileOpenError(_, x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::SymbolNotFound(x) => {
+                f.write_str("symbol not found: ")?;
+                at(f, x)
+            }
+            Self::SyntaxError(x) => x.fmt(f),
+            Self::CondIsNotBoolean(x) => {
+                f.write_str("condition is not a boolean: ")?;
+                at(f, x)
+            }
+            Self::FrameStackIsEmpty => f.write_str("frame stack is empty"),
+            Self::ModuleIsNotValue => f.write_str("a module is not a value"),
+            Self::FunctionCallIsEmpty => f.write_str("empty function call"),
+            Self::CalleeIsNotCallable => f.write_str("callee is not callable"),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::SyntaxError(x) => x.source(),
+            _ => None,
+        }
+    }
+}
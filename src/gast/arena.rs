@@ -0,0 +1,213 @@
+//! Arena-backed trees.
+//!
+//! All nodes, list children and strings of a `GAstArena` live in a few flat
+//! buffers and refer to each other by index, so filling and dropping a large
+//! tree costs a handful of allocations instead of one (with a reference
+//! count) per node.
+
+use std::ops::Index;
+
+use super::constant::Constant;
+use super::list::List;
+use super::symbol::{Location, Symbol};
+use super::{GAst, Handle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+/// A string in the arena's text buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StrId {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArenaLocation {
+    /// index into `GAstArena::paths`
+    pub path: u32,
+    pub line: usize,
+    pub colum: usize,
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArenaNode {
+    Nil,
+    Bool(bool),
+    Char(char),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Str(StrId),
+    Sym(StrId, ArenaLocation),
+    /// `len` children starting at `start` in the children buffer
+    List {
+        start: usize,
+        len: usize,
+        pair_right: Option<NodeId>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GAstArena {
+    nodes: Vec<ArenaNode>,
+    children: Vec<NodeId>,
+    text: String,
+    paths: Vec<Handle<String>>,
+}
+
+impl Index<NodeId> for GAstArena {
+    type Output = ArenaNode;
+
+    fn index(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0 as usize]
+    }
+}
+
+impl GAstArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Drops every node while keeping the buffers for reuse.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.children.clear();
+        self.text.clear();
+        self.paths.clear();
+    }
+
+    pub fn str(&self, id: StrId) -> &str {
+        &self.text[id.start..id.end]
+    }
+
+    pub fn paths(&self) -> &[Handle<String>] {
+        &self.paths
+    }
+
+    pub fn location(&self, loc: &ArenaLocation) -> Location {
+        let path = self.paths[loc.path as usize].clone();
+        Location::new(path, loc.line, loc.colum, loc.pos)
+    }
+
+    /// The elements of a list node, empty for any other node.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        match self[id] {
+            ArenaNode::List { start, len, .. } => &self.children[start..start + len],
+            _ => &[],
+        }
+    }
+
+    pub fn pair_right(&self, id: NodeId) -> Option<NodeId> {
+        match self[id] {
+            ArenaNode::List { pair_right, .. } => pair_right,
+            _ => None,
+        }
+    }
+
+    pub fn push(&mut self, node: ArenaNode) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn push_str(&mut self, s: &str) -> StrId {
+        let start = self.text.len();
+        self.text.push_str(s);
+        StrId {
+            start,
+            end: self.text.len(),
+        }
+    }
+
+    pub fn push_list(&mut self, list: &[NodeId], pair_right: Option<NodeId>) -> NodeId {
+        let start = self.children.len();
+        self.children.extend_from_slice(list);
+        self.push(ArenaNode::List {
+            start,
+            len: list.len(),
+            pair_right,
+        })
+    }
+
+    /// The index of `path` in `paths`, adding it if needed.
+    pub fn path_id(&mut self, path: &Handle<String>) -> u32 {
+        match self.paths.iter().position(|x| x == path) {
+            Some(x) => x as u32,
+            None => {
+                self.paths.push(path.clone());
+                (self.paths.len() - 1) as u32
+            }
+        }
+    }
+
+    pub fn push_location(&mut self, loc: &Location) -> ArenaLocation {
+        ArenaLocation {
+            path: self.path_id(&loc.path),
+            line: loc.line,
+            colum: loc.colum,
+            pos: loc.pos,
+        }
+    }
+
+    /// Copies a `GAst` into the arena.
+    pub fn insert(&mut self, ast: &GAst) -> NodeId {
+        match ast {
+            GAst::Const(x) => self.insert_constant(x),
+            GAst::List(x) => {
+                let list: Vec<_> = x.0.iter().map(|x| self.insert(x)).collect();
+                let pair_right = x.1.as_ref().map(|x| self.insert(x));
+                self.push_list(&list, pair_right)
+            }
+        }
+    }
+
+    fn insert_constant(&mut self, c: &Constant) -> NodeId {
+        let node = match c {
+            Constant::Nil => ArenaNode::Nil,
+            Constant::Bool(x) => ArenaNode::Bool(*x),
+            Constant::Char(x) => ArenaNode::Char(*x),
+            Constant::Int(x) => ArenaNode::Int(*x),
+            Constant::Uint(x) => ArenaNode::Uint(*x),
+            Constant::Float(x) => ArenaNode::Float(*x),
+            Constant::Str(x) => ArenaNode::Str(self.push_str(x)),
+            Constant::Sym(x) => {
+                let name = self.push_str(&x.0);
+                ArenaNode::Sym(name, self.push_location(&x.1))
+            }
+        };
+        self.push(node)
+    }
+
+    /// Builds the `Handle`-based tree of a node.
+    pub fn to_gast(&self, id: NodeId) -> GAst {
+        let c = match self[id] {
+            ArenaNode::Nil => Constant::Nil,
+            ArenaNode::Bool(x) => Constant::Bool(x),
+            ArenaNode::Char(x) => Constant::Char(x),
+            ArenaNode::Int(x) => Constant::Int(x),
+            ArenaNode::Uint(x) => Constant::Uint(x),
+            ArenaNode::Float(x) => Constant::Float(x),
+            ArenaNode::Str(x) => Constant::Str(Handle::new(self.str(x).to_owned())),
+            ArenaNode::Sym(x, loc) => {
+                let sym = Symbol(Handle::new(self.str(x).to_owned()), self.location(&loc));
+                Constant::Sym(Handle::new(sym))
+            }
+            ArenaNode::List { pair_right, .. } => {
+                let list = self.children(id).iter().map(|&x| self.to_gast(x)).collect();
+                let pair_right = pair_right.map(|x| self.to_gast(x));
+                return GAst::List(Handle::new(List::new(list, pair_right)));
+            }
+        };
+        GAst::Const(c)
+    }
+}
@@ -0,0 +1,412 @@
+//! Compact binary encoding of `GAst`s.
+//!
+//! Layout, all integers LEB128 varints unless noted:
+//!
+//! - header: `b"GAST"`, version byte, flags byte (bit 0: locations present)
+//! - string table: count, then length and UTF-8 bytes of each string
+//! - symbol table: count, then for each symbol its name (string index) and,
+//!   with locations, path (string index), line, colum and pos
+//! - forms: count, then each tree in prefix order as a tag byte and payload
+//!
+//! Strings and symbols are stored once; decoding hands out one shared
+//! `Handle` per table entry. A list `Handle` met again is written as a
+//! reference to the first one, so shared lists stay shared. Lists nested
+//! deeper than `MAX_DEPTH` are neither written nor read.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+use super::constant::Constant;
+use super::list::List;
+use super::symbol::{Location, Symbol};
+use super::{GAst, Handle};
+
+const MAGIC: &[u8; 4] = b"GAST";
+pub const VERSION: u8 = 2;
+/// The deepest nesting of lists `encode` writes and `decode` reads.
+pub const MAX_DEPTH: usize = 256;
+const FLAG_LOCATIONS: u8 = 1;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_CHAR: u8 = 3;
+const TAG_INT: u8 = 4;
+const TAG_UINT: u8 = 5;
+/// payload: 8 bytes little endian
+const TAG_FLOAT: u8 = 6;
+const TAG_STR: u8 = 7;
+const TAG_SYM: u8 = 8;
+/// payload: length, elements
+const TAG_LIST: u8 = 9;
+/// payload: length, elements, tail
+const TAG_PAIR_LIST: u8 = 10;
+/// payload: index of an earlier list, counting lists as they end
+const TAG_REF: u8 = 11;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidTag(u8),
+    InvalidIndex(u64),
+    InvalidUtf8,
+    InvalidChar(u64),
+    VarintOverflow,
+    TrailingBytes,
+    TooDeep,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not a binary tree encoding"),
+            Self::UnsupportedVersion(x) => write!(f, "unsupported version {}", x),
+            Self::UnexpectedEnd => f.write_str("unexpected end of input"),
+            Self::InvalidTag(x) => write!(f, "invalid tag {}", x),
+            Self::InvalidIndex(x) => write!(f, "invalid table index {}", x),
+            Self::InvalidUtf8 => f.write_str("invalid UTF-8 in a string"),
+            Self::InvalidChar(x) => write!(f, "invalid char {:#x}", x),
+            Self::VarintOverflow => f.write_str("varint overflows 64 bits"),
+            Self::TrailingBytes => f.write_str("trailing bytes"),
+            Self::TooDeep => f.write_str("lists nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    TooDeep,
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooDeep => f.write_str("lists nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Encodes `forms`; symbol locations are dropped unless `locations`.
+pub fn encode(forms: &[GAst], locations: bool) -> Result<Vec<u8>, EncodeError> {
+    let mut e = Encoder {
+        locations,
+        strings: vec![],
+        string_ids: HashMap::new(),
+        symbols: vec![],
+        symbol_ids: HashMap::new(),
+        list_ids: HashMap::new(),
+        depth: 0,
+        body: vec![],
+    };
+    write_varint(&mut e.body, forms.len() as u64);
+    for x in forms {
+        e.gast(x)?;
+    }
+
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(if locations { FLAG_LOCATIONS } else { 0 });
+    write_varint(&mut out, e.strings.len() as u64);
+    for s in &e.strings {
+        write_varint(&mut out, s.len() as u64);
+        out.extend_from_slice(s.as_bytes());
+    }
+    write_varint(&mut out, e.symbols.len() as u64);
+    for s in &e.symbols {
+        for &x in s {
+            write_varint(&mut out, x);
+        }
+    }
+    out.extend_from_slice(&e.body);
+    Ok(out)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<GAst>, DecodeError> {
+    let mut d = Decoder {
+        bytes,
+        pos: 0,
+        depth: 0,
+        lists: vec![],
+    };
+    if d.take(4)? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let version = d.byte()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let locations = d.byte()? & FLAG_LOCATIONS != 0;
+
+    let count = d.varint()?;
+    let mut strings = vec![];
+    for _ in 0..count {
+        let len = d.varint()?;
+        let len = usize::try_from(len).map_err(|_| DecodeError::UnexpectedEnd)?;
+        let s = std::str::from_utf8(d.take(len)?).map_err(|_| DecodeError::InvalidUtf8)?;
+        strings.push(Handle::new(s.to_owned()));
+    }
+    let count = d.varint()?;
+    let mut symbols = vec![];
+    for _ in 0..count {
+        let name = d.index(&strings)?.clone();
+        let location = if locations {
+            let path = d.index(&strings)?.clone();
+            let line = d.usize()?;
+            let colum = d.usize()?;
+            let pos = d.usize()?;
+            Location::new(path, line, colum, pos)
+        } else {
+            Location::default()
+        };
+        symbols.push(Handle::new(Symbol(name, location)));
+    }
+
+    let count = d.varint()?;
+    let mut forms = vec![];
+    for _ in 0..count {
+        forms.push(d.gast(&strings, &symbols)?);
+    }
+    if d.pos != bytes.len() {
+        return Err(DecodeError::TrailingBytes);
+    }
+    Ok(forms)
+}
+
+struct Encoder<'a> {
+    locations: bool,
+    strings: Vec<&'a str>,
+    string_ids: HashMap<&'a str, u64>,
+    /// name, then path, line, colum and pos with locations
+    symbols: Vec<Vec<u64>>,
+    symbol_ids: HashMap<Vec<u64>, u64>,
+    /// by the address of the list
+    list_ids: HashMap<usize, u64>,
+    /// lists open around the current node
+    depth: usize,
+    body: Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    fn string(&mut self, s: &'a str) -> u64 {
+        if let Some(&x) = self.string_ids.get(s) {
+            return x;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.push(s);
+        self.string_ids.insert(s, id);
+        id
+    }
+
+    fn symbol(&mut self, s: &'a Symbol) -> u64 {
+        let mut key = vec![self.string(&s.0)];
+        if self.locations {
+            let loc = &s.1;
+            key.push(self.string(&loc.path));
+            key.extend([loc.line, loc.colum, loc.pos].iter().map(|&x| x as u64));
+        }
+        if let Some(&x) = self.symbol_ids.get(&key) {
+            return x;
+        }
+        let id = self.symbols.len() as u64;
+        self.symbols.push(key.clone());
+        self.symbol_ids.insert(key, id);
+        id
+    }
+
+    fn gast(&mut self, ast: &'a GAst) -> Result<(), EncodeError> {
+        match ast {
+            GAst::Const(x) => self.constant(x),
+            GAst::List(x) => {
+                let address = Handle::as_ptr(x) as usize;
+                if let Some(&id) = self.list_ids.get(&address) {
+                    self.body.push(TAG_REF);
+                    write_varint(&mut self.body, id);
+                    return Ok(());
+                }
+                if self.depth == MAX_DEPTH {
+                    return Err(EncodeError::TooDeep);
+                }
+                self.depth += 1;
+                let tag = if x.1.is_some() {
+                    TAG_PAIR_LIST
+                } else {
+                    TAG_LIST
+                };
+                self.body.push(tag);
+                write_varint(&mut self.body, x.0.len() as u64);
+                for x in &x.0 {
+                    self.gast(x)?;
+                }
+                if let Some(x) = &x.1 {
+                    self.gast(x)?;
+                }
+                self.depth -= 1;
+                let id = self.list_ids.len() as u64;
+                self.list_ids.insert(address, id);
+            }
+        }
+        Ok(())
+    }
+
+    fn constant(&mut self, c: &'a Constant) {
+        match c {
+            Constant::Nil => self.body.push(TAG_NIL),
+            Constant::Bool(false) => self.body.push(TAG_FALSE),
+            Constant::Bool(true) => self.body.push(TAG_TRUE),
+            Constant::Char(x) => {
+                self.body.push(TAG_CHAR);
+                write_varint(&mut self.body, *x as u64);
+            }
+            Constant::Int(x) => {
+                self.body.push(TAG_INT);
+                // zigzag, so small negative numbers stay short
+                write_varint(&mut self.body, ((x << 1) ^ (x >> 63)) as u64);
+            }
+            Constant::Uint(x) => {
+                self.body.push(TAG_UINT);
+                write_varint(&mut self.body, *x);
+            }
+            Constant::Float(x) => {
+                self.body.push(TAG_FLOAT);
+                self.body.extend_from_slice(&x.to_le_bytes());
+            }
+            Constant::Str(x) => {
+                let id = self.string(x);
+                self.body.push(TAG_STR);
+                write_varint(&mut self.body, id);
+            }
+            Constant::Sym(x) => {
+                let id = self.symbol(x);
+                self.body.push(TAG_SYM);
+                write_varint(&mut self.body, id);
+            }
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// lists open around `pos`
+    depth: usize,
+    /// every list read so far, for references
+    lists: Vec<Handle<List>>,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut r = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            if shift == 63 && b > 1 {
+                return Err(DecodeError::VarintOverflow);
+            }
+            r |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(r);
+            }
+        }
+        Err(DecodeError::VarintOverflow)
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        usize::try_from(self.varint()?).map_err(|_| DecodeError::VarintOverflow)
+    }
+
+    fn index<'t, T>(&mut self, table: &'t [T]) -> Result<&'t T, DecodeError> {
+        let i = self.varint()?;
+        usize::try_from(i)
+            .ok()
+            .and_then(|x| table.get(x))
+            .ok_or(DecodeError::InvalidIndex(i))
+    }
+
+    fn gast(
+        &mut self,
+        strings: &[Handle<String>],
+        symbols: &[Handle<Symbol>],
+    ) -> Result<GAst, DecodeError> {
+        let c = match self.byte()? {
+            TAG_NIL => Constant::Nil,
+            TAG_FALSE => Constant::Bool(false),
+            TAG_TRUE => Constant::Bool(true),
+            TAG_CHAR => {
+                let x = self.varint()?;
+                let c = u32::try_from(x).ok().and_then(std::char::from_u32);
+                Constant::Char(c.ok_or(DecodeError::InvalidChar(x))?)
+            }
+            TAG_INT => {
+                let x = self.varint()?;
+                Constant::Int((x >> 1) as i64 ^ -((x & 1) as i64))
+            }
+            TAG_UINT => Constant::Uint(self.varint()?),
+            TAG_FLOAT => {
+                let mut x = [0; 8];
+                x.copy_from_slice(self.take(8)?);
+                Constant::Float(f64::from_le_bytes(x))
+            }
+            TAG_STR => Constant::Str(self.index(strings)?.clone()),
+            TAG_SYM => Constant::Sym(self.index(symbols)?.clone()),
+            TAG_REF => {
+                let i = self.varint()?;
+                let list = usize::try_from(i).ok().and_then(|x| self.lists.get(x));
+                return Ok(GAst::List(
+                    list.ok_or(DecodeError::InvalidIndex(i))?.clone(),
+                ));
+            }
+            tag @ TAG_LIST | tag @ TAG_PAIR_LIST => {
+                if self.depth == MAX_DEPTH {
+                    return Err(DecodeError::TooDeep);
+                }
+                self.depth += 1;
+                let len = self.usize()?;
+                // every element takes at least one byte
+                if len > self.bytes.len() - self.pos {
+                    return Err(DecodeError::UnexpectedEnd);
+                }
+                let mut list = Vec::with_capacity(len);
+                for _ in 0..len {
+                    list.push(self.gast(strings, symbols)?);
+                }
+                let pair_right = if tag == TAG_PAIR_LIST {
+                    Some(self.gast(strings, symbols)?)
+                } else {
+                    None
+                };
+                self.depth -= 1;
+                let list = Handle::new(List::new(list, pair_right));
+                self.lists.push(list.clone());
+                return Ok(GAst::List(list));
+            }
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        Ok(GAst::Const(c))
+    }
+}
@@ -0,0 +1,131 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use super::constant::Constant;
+use super::list::List;
+use super::symbol::{Location, Symbol};
+use super::{GAst, Handle};
+
+/// A `GAst` borrowing its symbols and strings from the parsed text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GAstRef<'src> {
+    Const(ConstantRef<'src>),
+    List(ListRef<'src>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantRef<'src> {
+    Nil,
+    Bool(bool),
+    Char(char),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    /// borrowed unless the literal had escapes
+    Str(Cow<'src, str>),
+    Sym(SymbolRef<'src>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListRef<'src>(pub Vec<GAstRef<'src>>, pub Option<Box<GAstRef<'src>>>);
+
+#[derive(Debug, Clone, Eq)]
+pub struct SymbolRef<'src>(pub &'src str, pub Location);
+
+impl PartialEq for SymbolRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'src> GAstRef<'src> {
+    impl_is_type!(is_const, Const);
+    impl_is_type!(is_list, List);
+
+    pub fn get_const(&self) -> Option<&ConstantRef<'src>> {
+        if let Self::Const(x) = self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_list(&self) -> Option<&ListRef<'src>> {
+        if let Self::List(x) = self {
+            Some(x)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_owned(&self) -> GAst {
+        match self {
+            Self::Const(x) => GAst::Const(x.to_owned()),
+            Self::List(x) => GAst::List(Handle::new(x.to_owned())),
+        }
+    }
+}
+
+impl ConstantRef<'_> {
+    pub fn to_owned(&self) -> Constant {
+        match self {
+            Self::Nil => Constant::Nil,
+            Self::Bool(x) => Constant::Bool(*x),
+            Self::Char(x) => Constant::Char(*x),
+            Self::Int(x) => Constant::Int(*x),
+            Self::Uint(x) => Constant::Uint(*x),
+            Self::Float(x) => Constant::Float(*x),
+            Self::Str(x) => Constant::Str(Handle::new(x.to_string())),
+            Self::Sym(x) => Constant::Sym(Handle::new(x.to_owned())),
+        }
+    }
+}
+
+impl ListRef<'_> {
+    pub fn to_owned(&self) -> List {
+        let list = self.0.iter().map(GAstRef::to_owned).collect();
+        List::new(list, self.1.as_ref().map(|x| x.as_ref().to_owned()))
+    }
+}
+
+impl SymbolRef<'_> {
+    pub fn to_owned(&self) -> Symbol {
+        Symbol(Handle::new(self.0.to_owned()), self.1.clone())
+    }
+}
+
+impl Display for GAstRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Const(x) => x.fmt(f),
+            Self::List(x) => x.fmt(f),
+        }
+    }
+}
+
+impl Display for ConstantRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nil => f.write_str("nil"),
+            Self::Bool(x) => x.fmt(f),
+            Self::Char(x) => x.fmt(f),
+            Self::Int(x) => x.fmt(f),
+            Self::Uint(x) => x.fmt(f),
+            Self::Float(x) => x.fmt(f),
+            Self::Str(x) => x.fmt(f),
+            Self::Sym(x) => x.0.fmt(f),
+        }
+    }
+}
+
+impl Display for ListRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str_list: Vec<String> = self.0.iter().map(GAstRef::to_string).collect();
+        let str_pair_right = if let Some(x) = &self.1 {
+            format!(" . {}", x)
+        } else {
+            "".to_string()
+        };
+        write!(f, "({}{})", str_list.join(" "), str_pair_right)
+    }
+}
@@ -1,10 +1,14 @@
+use std::cmp::Ordering;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
 use serde::{Deserialize, Serialize};
 
 use super::{symbol::Symbol, Handle};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Floats compare and hash by bit pattern (ordered by `total_cmp`), so
+/// `NaN == NaN` and `0.0 != -0.0`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Constant {
     Nil,
     Bool(bool),
@@ -16,6 +20,68 @@ pub enum Constant {
     Sym(Handle<Symbol>),
 }
 
+impl Constant {
+    /// Position of the variant, for ordering constants of different types.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Nil => 0,
+            Self::Bool(_) => 1,
+            Self::Char(_) => 2,
+            Self::Int(_) => 3,
+            Self::Uint(_) => 4,
+            Self::Float(_) => 5,
+            Self::Str(_) => 6,
+            Self::Sym(_) => 7,
+        }
+    }
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Constant {}
+
+impl PartialOrd for Constant {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Constant {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => Ordering::Equal,
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Char(a), Self::Char(b)) => a.cmp(b),
+            (Self::Int(a), Self::Int(b)) => a.cmp(b),
+            (Self::Uint(a), Self::Uint(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+            (Self::Str(a), Self::Str(b)) => a.cmp(b),
+            (Self::Sym(a), Self::Sym(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u8(self.rank());
+        match self {
+            Self::Nil => {}
+            Self::Bool(x) => x.hash(state),
+            Self::Char(x) => x.hash(state),
+            Self::Int(x) => x.hash(state),
+            Self::Uint(x) => x.hash(state),
+            Self::Float(x) => x.to_bits().hash(state),
+            Self::Str(x) => x.hash(state),
+            Self::Sym(x) => x.hash(state),
+        }
+    }
+}
+
 impl Display for Constant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Self::Nil = self {
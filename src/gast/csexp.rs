@@ -0,0 +1,337 @@
+//! Rivest canonical S-expressions.
+//!
+//! Every atom is written as `<length>:<bytes>` and lists as `(...)` with no
+//! whitespace, so each tree has exactly one encoding. Symbols are plain
+//! atoms; the other constants carry a display hint naming their type:
+//!
+//! | constant      | encoding                                 |
+//! |---------------|------------------------------------------|
+//! | `foo`         | `3:foo`                                  |
+//! | `"foo"`       | `[3:str]3:foo`                           |
+//! | `-12`         | `[3:int]3:-12` (decimal, no leading `0`) |
+//! | `12u`         | `[4:uint]2:12`                           |
+//! | `1.5`         | `[5:float]8:...` (IEEE 754 bits, big endian) |
+//! | `true`        | `[4:bool]4:true`                         |
+//! | `nil`         | `[3:nil]0:`                              |
+//! | char          | `[4:char]1:a` (UTF-8)                    |
+//! | `(a . b)`     | `(1:a[3:dot]0:1:b)`                      |
+//!
+//! Symbol locations are not encoded. The reader only accepts canonical
+//! input with the hints above, so decoding and re-encoding gives back the
+//! same bytes. Lists nested deeper than `MAX_DEPTH` are neither written nor
+//! read. The transport form is the canonical one in base64 between `{` and
+//! `}`.
+
+use std::fmt::Display;
+
+use super::constant::Constant;
+use super::list::List;
+use super::symbol::Symbol;
+use super::{GAst, Handle};
+
+/// The deepest nesting of lists written and read.
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsexpError {
+    UnexpectedEnd,
+    /// byte offset of the unexpected byte
+    UnexpectedByte(usize),
+    /// a length with a leading zero, at this offset
+    NonCanonicalLength(usize),
+    /// an atom not valid for its hint or with an unknown hint, at this offset
+    InvalidAtom(usize),
+    /// a list nested deeper than `MAX_DEPTH`, at this offset
+    TooDeep(usize),
+    InvalidBase64,
+    TrailingBytes(usize),
+}
+
+impl Display for CsexpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEnd => f.write_str("unexpected end of input"),
+            Self::UnexpectedByte(x) => write!(f, "unexpected byte at {}", x),
+            Self::NonCanonicalLength(x) => write!(f, "length with a leading zero at {}", x),
+            Self::InvalidAtom(x) => write!(f, "atom not valid for its hint at {}", x),
+            Self::TooDeep(x) => write!(f, "lists nested too deeply at {}", x),
+            Self::InvalidBase64 => f.write_str("invalid base64"),
+            Self::TrailingBytes(x) => write!(f, "trailing bytes at {}", x),
+        }
+    }
+}
+
+impl std::error::Error for CsexpError {}
+
+pub fn to_canonical(ast: &GAst) -> Result<Vec<u8>, CsexpError> {
+    let mut out = vec![];
+    write_canonical(ast, &mut out)?;
+    Ok(out)
+}
+
+/// Appends the encoding to `out`; `TooDeep` gives the offset in `out`.
+pub fn write_canonical(ast: &GAst, out: &mut Vec<u8>) -> Result<(), CsexpError> {
+    write_nested(ast, 0, out)
+}
+
+fn write_nested(ast: &GAst, depth: usize, out: &mut Vec<u8>) -> Result<(), CsexpError> {
+    match ast {
+        GAst::Const(c) => write_constant(c, out),
+        GAst::List(x) => {
+            if depth == MAX_DEPTH {
+                return Err(CsexpError::TooDeep(out.len()));
+            }
+            out.push(b'(');
+            for x in &x.0 {
+                write_nested(x, depth + 1, out)?;
+            }
+            if let Some(x) = &x.1 {
+                write_hinted("dot", b"", out);
+                write_nested(x, depth + 1, out)?;
+            }
+            out.push(b')');
+        }
+    }
+    Ok(())
+}
+
+fn write_atom(atom: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(atom.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(atom);
+}
+
+fn write_hinted(hint: &str, atom: &[u8], out: &mut Vec<u8>) {
+    out.push(b'[');
+    write_atom(hint.as_bytes(), out);
+    out.push(b']');
+    write_atom(atom, out);
+}
+
+fn write_constant(c: &Constant, out: &mut Vec<u8>) {
+    match c {
+        Constant::Nil => write_hinted("nil", b"", out),
+        Constant::Bool(x) => write_hinted("bool", x.to_string().as_bytes(), out),
+        Constant::Char(x) => write_hinted("char", x.to_string().as_bytes(), out),
+        Constant::Int(x) => write_hinted("int", x.to_string().as_bytes(), out),
+        Constant::Uint(x) => write_hinted("uint", x.to_string().as_bytes(), out),
+        Constant::Float(x) => write_hinted("float", &x.to_bits().to_be_bytes(), out),
+        Constant::Str(x) => write_hinted("str", x.as_bytes(), out),
+        Constant::Sym(x) => write_atom(x.0.as_bytes(), out),
+    }
+}
+
+pub fn from_canonical(bytes: &[u8]) -> Result<GAst, CsexpError> {
+    let mut r = Reader {
+        bytes,
+        pos: 0,
+        depth: 0,
+    };
+    let ast = match r.item()? {
+        Item::Ast(x) => x,
+        Item::Dot(pos) => return Err(CsexpError::UnexpectedByte(pos)),
+    };
+    if r.pos != bytes.len() {
+        return Err(CsexpError::TrailingBytes(r.pos));
+    }
+    Ok(ast)
+}
+
+/// The canonical encoding in base64 between braces.
+pub fn to_transport(ast: &GAst) -> Result<String, CsexpError> {
+    Ok(format!("{{{}}}", base64_encode(&to_canonical(ast)?)))
+}
+
+/// Reads the transport form; whitespace inside the braces is ignored.
+pub fn from_transport(input: &str) -> Result<GAst, CsexpError> {
+    let input = input.trim();
+    let inner = input
+        .strip_prefix('{')
+        .and_then(|x| x.strip_suffix('}'))
+        .ok_or(CsexpError::InvalidBase64)?;
+    from_canonical(&base64_decode(inner)?)
+}
+
+enum Item {
+    Ast(GAst),
+    /// the `[3:dot]0:` marker, at this offset
+    Dot(usize),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// lists open around `pos`
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Result<u8, CsexpError> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or(CsexpError::UnexpectedEnd)
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), CsexpError> {
+        if self.peek()? != b {
+            return Err(CsexpError::UnexpectedByte(self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<&'a [u8], CsexpError> {
+        let start = self.pos;
+        let mut len: usize = 0;
+        loop {
+            let b = self.peek()?;
+            match b {
+                b'0'..=b'9' => {
+                    if self.pos > start && self.bytes[start] == b'0' {
+                        return Err(CsexpError::NonCanonicalLength(start));
+                    }
+                    len = len
+                        .checked_mul(10)
+                        .and_then(|x| x.checked_add((b - b'0') as usize))
+                        .ok_or(CsexpError::UnexpectedEnd)?;
+                    self.pos += 1;
+                }
+                b':' if self.pos > start => break,
+                _ => return Err(CsexpError::UnexpectedByte(self.pos)),
+            }
+        }
+        self.pos += 1;
+        if self.bytes.len() - self.pos < len {
+            return Err(CsexpError::UnexpectedEnd);
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn item(&mut self) -> Result<Item, CsexpError> {
+        let start = self.pos;
+        match self.peek()? {
+            b'(' => {
+                if self.depth == MAX_DEPTH {
+                    return Err(CsexpError::TooDeep(start));
+                }
+                self.depth += 1;
+                self.pos += 1;
+                let mut list = vec![];
+                let mut pair_right = None;
+                while self.peek()? != b')' {
+                    match self.item()? {
+                        Item::Ast(x) => list.push(x),
+                        Item::Dot(pos) => {
+                            match self.item()? {
+                                Item::Ast(x) => pair_right = Some(x),
+                                Item::Dot(pos) => return Err(CsexpError::UnexpectedByte(pos)),
+                            }
+                            if self.peek()? != b')' || list.is_empty() {
+                                return Err(CsexpError::UnexpectedByte(pos));
+                            }
+                        }
+                    }
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(Item::Ast(GAst::List(Handle::new(List::new(
+                    list, pair_right,
+                )))))
+            }
+            b'[' => {
+                self.pos += 1;
+                let hint = self.atom()?;
+                self.expect(b']')?;
+                let atom_pos = self.pos;
+                let atom = self.atom()?;
+                let invalid = CsexpError::InvalidAtom(atom_pos);
+                let text = std::str::from_utf8(atom).map_err(|_| invalid.clone());
+                let c = match hint {
+                    b"dot" if atom.is_empty() => return Ok(Item::Dot(start)),
+                    b"nil" if atom.is_empty() => Constant::Nil,
+                    b"bool" if atom == b"true" => Constant::Bool(true),
+                    b"bool" if atom == b"false" => Constant::Bool(false),
+                    b"str" => Constant::Str(Handle::new(text?.to_owned())),
+                    b"char" => {
+                        let mut chars = text?.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Constant::Char(c),
+                            _ => return Err(invalid),
+                        }
+                    }
+                    b"int" => Constant::Int(canonical_number(text?).ok_or(invalid)?),
+                    b"uint" => Constant::Uint(canonical_number(text?).ok_or(invalid)?),
+                    b"float" if atom.len() == 8 => {
+                        let mut x = [0; 8];
+                        x.copy_from_slice(atom);
+                        Constant::Float(f64::from_bits(u64::from_be_bytes(x)))
+                    }
+                    // also hints not naming a type, such as MIME types
+                    _ => return Err(invalid),
+                };
+                Ok(Item::Ast(GAst::Const(c)))
+            }
+            _ => {
+                let atom = self.atom()?;
+                let name = std::str::from_utf8(atom).map_err(|_| CsexpError::InvalidAtom(start))?;
+                let sym = Handle::new(Symbol::new(name));
+                Ok(Item::Ast(GAst::Const(Constant::Sym(sym))))
+            }
+        }
+    }
+}
+
+/// Parses `text` if it is exactly how the number would be written back.
+fn canonical_number<T: std::str::FromStr + ToString>(text: &str) -> Option<T> {
+    text.parse().ok().filter(|x: &T| x.to_string() == text)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(input: &str) -> Result<Vec<u8>, CsexpError> {
+    let digits: Vec<u8> = input.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    // `is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    if digits.len() % 4 != 0 {
+        return Err(CsexpError::InvalidBase64);
+    }
+    let mut out = vec![];
+    for (i, chunk) in digits.chunks(4).enumerate() {
+        let last = i == digits.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || padding > 0 && !last {
+            return Err(CsexpError::InvalidBase64);
+        }
+        let mut n = 0u32;
+        for &b in &chunk[..4 - padding] {
+            let d = BASE64
+                .iter()
+                .position(|&x| x == b)
+                .ok_or(CsexpError::InvalidBase64)?;
+            n = n << 6 | d as u32;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(out)
+}
@@ -0,0 +1,478 @@
+//! Structural diff and patch.
+//!
+//! `diff` aligns the elements of lists by their longest common subsequence,
+//! turns elements that only changed position into moves, and recurses into
+//! elements replaced at the same place in the alignment. Comparisons are
+//! structural, so symbol locations are ignored.
+//!
+//! A `Patch` is a sequence of edits applied in order; every edit addresses
+//! the tree as left by the edits before it.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::constant::Constant;
+use super::list::List;
+use super::{GAst, Handle};
+
+/// Lists larger than this (elements of old times new) are only matched by
+/// common prefix and suffix.
+const LCS_LIMIT: usize = 1 << 22;
+
+/// Document order: elements in order, then the tail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Step {
+    Elem(usize),
+    /// the tail of a dotted list
+    Tail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edit {
+    Replace {
+        path: Vec<Step>,
+        old: GAst,
+        new: GAst,
+    },
+    Insert {
+        list: Vec<Step>,
+        index: usize,
+        value: GAst,
+    },
+    Delete {
+        list: Vec<Step>,
+        index: usize,
+        old: GAst,
+    },
+    /// removes the element at `from` and inserts it back at `to`
+    Move {
+        list: Vec<Step>,
+        from: usize,
+        to: usize,
+    },
+    /// adds or removes the tail of a list
+    SetTail {
+        list: Vec<Step>,
+        old: Option<GAst>,
+        new: Option<GAst>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Patch {
+    pub edits: Vec<Edit>,
+}
+
+/// Index of the failing edit and why it failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    NoSuchNode(usize),
+    NotAList(usize),
+    IndexOutOfRange(usize),
+    /// the tree does not hold the value the edit expects to remove
+    Mismatch(usize),
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchNode(x) => write!(f, "edit {}: no node at its path", x),
+            Self::NotAList(x) => write!(f, "edit {}: the node is not a list", x),
+            Self::IndexOutOfRange(x) => write!(f, "edit {}: index out of range", x),
+            Self::Mismatch(x) => write!(f, "edit {}: the tree does not hold the old value", x),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl Patch {
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Cost of the edits: inserted, deleted and replaced subtrees count
+    /// their nodes, moves count one.
+    pub fn cost(&self) -> usize {
+        self.edits
+            .iter()
+            .map(|e| match e {
+                Edit::Replace { old, new, .. } => size(old).max(size(new)),
+                Edit::Insert { value, .. } => size(value),
+                Edit::Delete { old, .. } => size(old),
+                Edit::Move { .. } => 1,
+                Edit::SetTail { old, new, .. } => old.iter().chain(new.iter()).map(size).sum(),
+            })
+            .sum()
+    }
+}
+
+pub fn diff(old: &GAst, new: &GAst) -> Patch {
+    let mut edits = vec![];
+    diff_node(old, new, &mut vec![], &mut edits);
+    Patch { edits }
+}
+
+/// The tree edit distance of Zhang and Shasha: the fewest node insertions,
+/// deletions and relabelings that turn `old` into `new`. Lists are labeled
+/// as lists and a dotted tail as a `.` node above it. Takes memory the
+/// product of the sizes of the trees and time up to its square.
+pub fn edit_distance(old: &GAst, new: &GAst) -> usize {
+    let (a, b) = (Postorder::new(old), Postorder::new(new));
+    let (n, m) = (a.labels.len(), b.labels.len());
+    // tree[i * m + j]: distance between the subtrees at i and j
+    let mut tree = vec![0usize; n * m];
+    // forest[x * cols + y]: distance between the first x nodes of the
+    // subtree at i and the first y nodes of the subtree at j
+    let mut forest = vec![0usize; (n + 1) * (m + 1)];
+    for &i in &a.keyroots {
+        for &j in &b.keyroots {
+            let (li, lj) = (a.leftmost[i], b.leftmost[j]);
+            let (rows, cols) = (i - li + 2, j - lj + 2);
+            for x in 0..rows {
+                forest[x * cols] = x;
+            }
+            for (y, x) in forest[..cols].iter_mut().enumerate() {
+                *x = y;
+            }
+            for x in 1..rows {
+                for y in 1..cols {
+                    let (i1, j1) = (li + x - 1, lj + y - 1);
+                    let edit = (forest[(x - 1) * cols + y] + 1).min(forest[x * cols + y - 1] + 1);
+                    forest[x * cols + y] = if a.leftmost[i1] == li && b.leftmost[j1] == lj {
+                        let relabel = usize::from(a.labels[i1] != b.labels[j1]);
+                        let d = edit.min(forest[(x - 1) * cols + y - 1] + relabel);
+                        tree[i1 * m + j1] = d;
+                        d
+                    } else {
+                        let (x1, y1) = (a.leftmost[i1] - li, b.leftmost[j1] - lj);
+                        edit.min(forest[x1 * cols + y1] + tree[i1 * m + j1])
+                    };
+                }
+            }
+        }
+    }
+    tree[n * m - 1]
+}
+
+#[derive(PartialEq)]
+enum Label<'a> {
+    List,
+    Dot,
+    Const(&'a Constant),
+}
+
+/// A tree in postorder, with the leftmost leaf below each node and the
+/// keyroots: the root and the nodes with a left sibling.
+struct Postorder<'a> {
+    labels: Vec<Label<'a>>,
+    leftmost: Vec<usize>,
+    keyroots: Vec<usize>,
+}
+
+impl<'a> Postorder<'a> {
+    fn new(ast: &'a GAst) -> Self {
+        let mut r = Postorder {
+            labels: vec![],
+            leftmost: vec![],
+            keyroots: vec![],
+        };
+        r.push(ast);
+        let mut last = HashMap::new();
+        for (i, &l) in r.leftmost.iter().enumerate() {
+            last.insert(l, i);
+        }
+        r.keyroots = last.into_values().collect();
+        r.keyroots.sort_unstable();
+        r
+    }
+
+    /// Adds the subtree and returns its leftmost leaf.
+    fn push(&mut self, ast: &'a GAst) -> usize {
+        let mut first = None;
+        let label = match ast {
+            GAst::Const(x) => Label::Const(x),
+            GAst::List(x) => {
+                for x in &x.0 {
+                    let l = self.push(x);
+                    first.get_or_insert(l);
+                }
+                if let Some(x) = &x.1 {
+                    let l = self.push(x);
+                    self.labels.push(Label::Dot);
+                    self.leftmost.push(l);
+                    first.get_or_insert(l);
+                }
+                Label::List
+            }
+        };
+        let first = first.unwrap_or(self.labels.len());
+        self.labels.push(label);
+        self.leftmost.push(first);
+        first
+    }
+}
+
+pub fn apply_patch(ast: &GAst, patch: &Patch) -> Result<GAst, PatchError> {
+    let mut root = ast.clone();
+    for (n, e) in patch.edits.iter().enumerate() {
+        match e {
+            Edit::Replace { path, old, new } => update(&mut root, path, n, &mut |x| {
+                if x != old {
+                    return Err(PatchError::Mismatch(n));
+                }
+                *x = new.clone();
+                Ok(())
+            })?,
+            Edit::Insert { list, index, value } => update_list(&mut root, list, n, |x| {
+                if *index > x.0.len() {
+                    return Err(PatchError::IndexOutOfRange(n));
+                }
+                x.0.insert(*index, value.clone());
+                Ok(())
+            })?,
+            Edit::Delete { list, index, old } => update_list(&mut root, list, n, |x| {
+                match x.0.get(*index) {
+                    None => return Err(PatchError::IndexOutOfRange(n)),
+                    Some(x) if x != old => return Err(PatchError::Mismatch(n)),
+                    Some(_) => {}
+                }
+                x.0.remove(*index);
+                Ok(())
+            })?,
+            Edit::Move { list, from, to } => update_list(&mut root, list, n, |x| {
+                if *from >= x.0.len() || *to >= x.0.len() {
+                    return Err(PatchError::IndexOutOfRange(n));
+                }
+                let e = x.0.remove(*from);
+                x.0.insert(*to, e);
+                Ok(())
+            })?,
+            Edit::SetTail { list, old, new } => update_list(&mut root, list, n, |x| {
+                if &x.1 != old {
+                    return Err(PatchError::Mismatch(n));
+                }
+                x.1 = new.clone();
+                Ok(())
+            })?,
+        }
+    }
+    Ok(root)
+}
+
+/// Runs `f` on the node at `path`, copying the lists on the way down.
+fn update(
+    node: &mut GAst,
+    path: &[Step],
+    n: usize,
+    f: &mut dyn FnMut(&mut GAst) -> Result<(), PatchError>,
+) -> Result<(), PatchError> {
+    let (step, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return f(node),
+    };
+    let handle = match node {
+        GAst::List(x) => x,
+        GAst::Const(_) => return Err(PatchError::NoSuchNode(n)),
+    };
+    let mut list = List::clone(handle);
+    let child = match step {
+        Step::Elem(i) => list.0.get_mut(*i),
+        Step::Tail => list.1.as_mut(),
+    };
+    update(child.ok_or(PatchError::NoSuchNode(n))?, rest, n, f)?;
+    *handle = Handle::new(list);
+    Ok(())
+}
+
+fn update_list(
+    node: &mut GAst,
+    path: &[Step],
+    n: usize,
+    mut f: impl FnMut(&mut List) -> Result<(), PatchError>,
+) -> Result<(), PatchError> {
+    update(node, path, n, &mut |x| match x {
+        GAst::List(handle) => {
+            let mut list = List::clone(handle);
+            f(&mut list)?;
+            *handle = Handle::new(list);
+            Ok(())
+        }
+        GAst::Const(_) => Err(PatchError::NotAList(n)),
+    })
+}
+
+fn size(ast: &GAst) -> usize {
+    match ast {
+        GAst::Const(_) => 1,
+        GAst::List(x) => 1 + x.0.iter().chain(x.1.iter()).map(size).sum::<usize>(),
+    }
+}
+
+fn diff_node(old: &GAst, new: &GAst, path: &mut Vec<Step>, edits: &mut Vec<Edit>) {
+    if old == new {
+        return;
+    }
+    match (old, new) {
+        (GAst::List(a), GAst::List(b)) => {
+            diff_list(&a.0, &b.0, path, edits);
+            match (&a.1, &b.1) {
+                (Some(x), Some(y)) => {
+                    path.push(Step::Tail);
+                    diff_node(x, y, path, edits);
+                    path.pop();
+                }
+                (None, None) => {}
+                (x, y) => edits.push(Edit::SetTail {
+                    list: path.clone(),
+                    old: x.clone(),
+                    new: y.clone(),
+                }),
+            }
+        }
+        _ => edits.push(Edit::Replace {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+fn diff_list(olds: &[GAst], news: &[GAst], path: &mut Vec<Step>, edits: &mut Vec<Edit>) {
+    let anchors = lcs(olds, news);
+    // for each new element, the old one it comes from
+    let mut source: Vec<Option<usize>> = vec![None; news.len()];
+    let mut used = vec![false; olds.len()];
+    for &(i, j) in &anchors {
+        source[j] = Some(i);
+        used[i] = true;
+    }
+
+    // equal elements at different places in the alignment are moves
+    let mut free: HashMap<&GAst, Vec<usize>> = HashMap::new();
+    for i in (0..olds.len()).rev().filter(|&i| !used[i]) {
+        free.entry(&olds[i]).or_default().push(i);
+    }
+    for j in 0..news.len() {
+        if source[j].is_none() {
+            if let Some(i) = free.get_mut(&news[j]).and_then(Vec::pop) {
+                source[j] = Some(i);
+                used[i] = true;
+            }
+        }
+    }
+
+    // what is left between two anchors is changed in place, pairwise
+    let mut modified = vec![];
+    let mut prev = (0, 0);
+    let ends = anchors
+        .iter()
+        .copied()
+        .chain(Some((olds.len(), news.len())));
+    for (i, j) in ends {
+        let gap_olds = (prev.0..i).filter(|&i| !used[i]);
+        let gap_news = (prev.1..j).filter(|&j| source[j].is_none());
+        for (i, j) in gap_olds.zip(gap_news).collect::<Vec<_>>() {
+            source[j] = Some(i);
+            used[i] = true;
+            modified.push(j);
+        }
+        prev = (i + 1, j + 1);
+    }
+
+    // old indices of the current elements, `None` for inserted ones
+    let mut current: Vec<Option<usize>> = (0..olds.len()).map(Some).collect();
+    for i in (0..olds.len()).rev().filter(|&i| !used[i]) {
+        edits.push(Edit::Delete {
+            list: path.clone(),
+            index: i,
+            old: olds[i].clone(),
+        });
+        current.remove(i);
+    }
+    for (j, source) in source.iter().enumerate() {
+        match source {
+            None => {
+                edits.push(Edit::Insert {
+                    list: path.clone(),
+                    index: j,
+                    value: news[j].clone(),
+                });
+                current.insert(j, None);
+            }
+            Some(_) => {
+                let from = current.iter().position(|x| x == source).unwrap();
+                if from != j {
+                    edits.push(Edit::Move {
+                        list: path.clone(),
+                        from,
+                        to: j,
+                    });
+                    let x = current.remove(from);
+                    current.insert(j, x);
+                }
+            }
+        }
+    }
+    for j in modified {
+        path.push(Step::Elem(j));
+        diff_node(&olds[source[j].unwrap()], &news[j], path, edits);
+        path.pop();
+    }
+}
+
+/// Index pairs of a longest common subsequence.
+fn lcs(a: &[GAst], b: &[GAst]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (n, m) = (a.len() - prefix - suffix, b.len() - prefix - suffix);
+    let mut r: Vec<_> = (0..prefix).map(|i| (i, i)).collect();
+    if n != 0 && m != 0 && n * m <= LCS_LIMIT {
+        let (a, b) = (&a[prefix..prefix + n], &b[prefix..prefix + m]);
+        // table[i][j]: length of an LCS of a[i..] and b[j..]
+        let mut table = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                table[i * (m + 1) + j] = if a[i] == b[j] {
+                    table[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    table[(i + 1) * (m + 1) + j].max(table[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if a[i] == b[j] {
+                r.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if table[(i + 1) * (m + 1) + j] >= table[i * (m + 1) + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    r.extend((0..suffix).map(|k| (a_end + k, b_end + k)));
+    r
+}
+
+pub(crate) fn fmt_path(path: &[Step]) -> String {
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.iter()
+        .map(|x| match x {
+            Step::Elem(i) => format!("/{}", i),
+            Step::Tail => "/.".to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,94 @@
+//! Hash-consing: one shared `Handle<List>` per distinct list.
+//!
+//! Lists are compared structurally (symbol locations ignored, see `GAst`'s
+//! `Eq`), so equal subtrees from different places collapse into the first
+//! one interned, locations included. Lists cache their structural hash, so
+//! interning a list only hashes its new parts.
+
+use std::collections::HashMap;
+
+use super::list::List;
+use super::{GAst, Handle};
+
+#[derive(Debug, Default)]
+pub struct HashConsTable {
+    /// interned lists by structural hash
+    lists: HashMap<u64, Vec<Handle<List>>>,
+    len: usize,
+}
+
+impl HashConsTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Builds a list, reusing an equal one if the table has it. The
+    /// elements are used as they are; intern them first for full sharing.
+    pub fn list(&mut self, list: Vec<GAst>, pair_right: Option<GAst>) -> Handle<List> {
+        self.intern_list(Handle::new(List::new(list, pair_right)))
+    }
+
+    /// The table's list equal to `list`, adding `list` if there is none.
+    pub fn intern_list(&mut self, list: Handle<List>) -> Handle<List> {
+        let bucket = self.lists.entry(list.structural_hash()).or_default();
+        if let Some(x) = bucket.iter().find(|x| **x == list) {
+            return x.clone();
+        }
+        bucket.push(list.clone());
+        self.len += 1;
+        list
+    }
+
+    fn contains(&self, list: &Handle<List>) -> bool {
+        self.lists
+            .get(&list.structural_hash())
+            .is_some_and(|x| x.iter().any(|x| Handle::ptr_eq(x, list)))
+    }
+
+    /// Shares every list in `ast` with equal ones interned before, bottom
+    /// up. A list whose elements come back unchanged is kept instead of
+    /// being rebuilt.
+    pub fn intern(&mut self, ast: &GAst) -> GAst {
+        match ast {
+            GAst::Const(_) => ast.clone(),
+            GAst::List(x) => {
+                if self.contains(x) {
+                    return ast.clone();
+                }
+                let list: Vec<_> = x.0.iter().map(|x| self.intern(x)).collect();
+                let pair_right = x.1.as_ref().map(|x| self.intern(x));
+                let unchanged = list.iter().zip(x.0.iter()).all(|(a, b)| same_node(a, b))
+                    && match (&pair_right, &x.1) {
+                        (Some(a), Some(b)) => same_node(a, b),
+                        _ => true,
+                    };
+                let list = if unchanged {
+                    x.clone()
+                } else {
+                    Handle::new(List::new(list, pair_right))
+                };
+                GAst::List(self.intern_list(list))
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lists.clear();
+        self.len = 0;
+    }
+}
+
+fn same_node(a: &GAst, b: &GAst) -> bool {
+    match (a, b) {
+        (GAst::List(a), GAst::List(b)) => Handle::ptr_eq(a, b),
+        _ => true,
+    }
+}
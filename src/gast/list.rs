@@ -1,10 +1,77 @@
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
 use super::GAst;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct List(pub Vec<GAst>, pub Option<GAst>);
+/// Elements, the tail of a dotted list and a cached structural hash.
+///
+/// The hash is computed on first use and takes no part in comparisons or
+/// `Hash`. Mutating a list in place after hashing it leaves a stale hash;
+/// mutate a clone instead, which starts without one.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct List(pub Vec<GAst>, pub Option<GAst>, #[serde(skip)] HashCache);
+
+#[derive(Default)]
+struct HashCache(OnceLock<u64>);
+
+impl List {
+    pub fn new(list: Vec<GAst>, pair_right: Option<GAst>) -> Self {
+        List(list, pair_right, HashCache::default())
+    }
+
+    /// Hash of the elements and tail, ignoring symbol locations; cached.
+    pub fn structural_hash(&self) -> u64 {
+        *self.2 .0.get_or_init(|| {
+            let mut h = DefaultHasher::new();
+            self.0.len().hash(&mut h);
+            for x in &self.0 {
+                h.write_u64(x.structural_hash());
+            }
+            self.1.as_ref().map(GAst::structural_hash).hash(&mut h);
+            h.finish()
+        })
+    }
+}
+
+impl Debug for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("List").field(&self.0).field(&self.1).finish()
+    }
+}
+
+impl Clone for HashCache {
+    fn clone(&self) -> Self {
+        HashCache::default()
+    }
+}
+
+impl PartialEq for HashCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for HashCache {}
+
+impl Hash for HashCache {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+impl PartialOrd for HashCache {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HashCache {
+    fn cmp(&self, _: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
 
 impl Display for List {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
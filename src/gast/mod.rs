@@ -1,19 +1,35 @@
+pub mod arena;
+pub mod binary;
+pub mod borrowed;
 pub mod constant;
+pub mod csexp;
+pub mod diff;
+pub mod hashcons;
 pub mod list;
+pub mod query;
+pub mod schema;
 pub mod symbol;
+pub mod visit;
+pub mod zipper;
 
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap},
     fmt::{Debug, Display},
-    sync::Arc,
+    hash::{Hash, Hasher},
 };
 
 use self::{constant::Constant, list::List, symbol::Symbol};
 
-pub type Handle<T> = Arc<T>;
+/// Shared pointer used throughout the tree: `Arc` with the `sync` feature
+/// (the default), `Rc` without it.
+#[cfg(feature = "sync")]
+pub type Handle<T> = std::sync::Arc<T>;
+#[cfg(not(feature = "sync"))]
+pub type Handle<T> = std::rc::Rc<T>;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Compared, ordered and hashed structurally; symbol locations are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum GAst {
     Const(Constant),
     List(Handle<List>),
@@ -35,6 +51,28 @@ impl GAst {
     impl_is_type!(is_list, List);
 }
 
+impl GAst {
+    /// Hash of the tree, ignoring symbol locations; cached on lists.
+    pub fn structural_hash(&self) -> u64 {
+        match self {
+            GAst::List(x) => x.structural_hash(),
+            GAst::Const(x) => {
+                let mut h = DefaultHasher::new();
+                x.hash(&mut h);
+                h.finish()
+            }
+        }
+    }
+}
+
+impl GAst {
+    /// Nodes matching a path query such as `//server/port/#1`; see
+    /// `query` for the syntax.
+    pub fn query(&self, query: &str) -> Result<Vec<query::Match<'_>>, query::QueryError> {
+        Ok(query::Query::parse(query)?.run(self))
+    }
+}
+
 impl GAst {
     impl_get_item!(get_const, Const, Constant);
     impl_get_item!(get_list, List, Handle<List>);
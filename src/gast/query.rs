@@ -0,0 +1,424 @@
+//! Path queries.
+//!
+//! A query is a sequence of steps, each `/` (children of the current nodes)
+//! or `//` (descendants at any depth) followed by a selector and optional
+//! predicates. The root is the only child of a virtual document node, so
+//! `/define` matches a root `(define ...)`.
+//!
+//! Selectors:
+//! - `name`: a list whose first element is the symbol `name`
+//! - `*`: any node
+//! - `#2`, `#-1`: the element at an index, negative from the end
+//! - `@list`, `@const`, `@sym`, `@str`, `@int`, `@uint`, `@float`, `@bool`,
+//!   `@char`, `@nil`: nodes of a type
+//!
+//! Predicates compare a constant with a literal in sexpr syntax, either the
+//! node itself (`[= 80]`) or one of its elements (`[#1 >= 1024]`). The
+//! operators are `=`, `!=`, `<`, `<=`, `>` and `>=`; the ordering ones
+//! compare strings or symbols by text, and all of them compare numbers by
+//! value, so `[= 1]` also matches `1.0` and `1u`.
+//!
+//! `//server/port/#1` selects the value of every `(port ...)` entry of every
+//! `(server ...)` form. Matches come in document order without duplicates.
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use pest::error::ErrorVariant;
+
+use super::constant::Constant;
+use super::diff::Step;
+use super::GAst;
+use crate::syntax::{sexpr, UnitError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub node: &'a GAst,
+    pub path: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    /// byte offset in the query
+    pub pos: usize,
+    pub message: String,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message, self.pos)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone)]
+pub struct Query {
+    steps: Vec<QueryStep>,
+}
+
+#[derive(Debug, Clone)]
+struct QueryStep {
+    descendant: bool,
+    selector: Selector,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Any,
+    Head(String),
+    Index(i64),
+    Type(String),
+}
+
+#[derive(Debug, Clone)]
+struct Predicate {
+    index: Option<i64>,
+    op: Op,
+    value: GAst,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+const TYPES: &[&str] = &[
+    "list", "const", "sym", "str", "int", "uint", "float", "bool", "char", "nil",
+];
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut p = QueryParser { query, pos: 0 };
+        let mut steps = vec![];
+        p.skip_ws();
+        while p.pos < query.len() {
+            steps.push(p.step()?);
+            p.skip_ws();
+        }
+        if steps.is_empty() {
+            return Err(p.error("empty query"));
+        }
+        Ok(Query { steps })
+    }
+
+    pub fn run<'a>(&self, root: &'a GAst) -> Vec<Match<'a>> {
+        let mut current: Vec<Match<'a>> = vec![];
+        for (n, step) in self.steps.iter().enumerate() {
+            let mut candidates = vec![];
+            if n == 0 {
+                // the root is the only child of the document
+                candidates.push((None, root, vec![]));
+                if step.descendant {
+                    children(root, &mut vec![], &mut candidates, true);
+                }
+            }
+            for m in &current {
+                children(
+                    m.node,
+                    &mut m.path.clone(),
+                    &mut candidates,
+                    step.descendant,
+                );
+            }
+            let mut next: Vec<_> = candidates
+                .into_iter()
+                .filter(|(index, node, _)| step.matches(*index, node))
+                .map(|(_, node, path)| Match { node, path })
+                .collect();
+            next.sort_by(|a, b| a.path.cmp(&b.path));
+            next.dedup_by(|a, b| a.path == b.path);
+            current = next;
+        }
+        current
+    }
+}
+
+/// A node below a context: its position and the length of its list if it
+/// is an element, the node and its path.
+type Candidate<'a> = (Option<(usize, usize)>, &'a GAst, Vec<Step>);
+
+fn children<'a>(node: &'a GAst, path: &mut Vec<Step>, out: &mut Vec<Candidate<'a>>, deep: bool) {
+    let list = match node {
+        GAst::List(x) => x,
+        GAst::Const(_) => return,
+    };
+    let len = list.0.len();
+    let elems = list.0.iter().enumerate();
+    let elems = elems.map(|(i, x)| (Some((i, len)), Step::Elem(i), x));
+    let tail = list.1.iter().map(|x| (None, Step::Tail, x));
+    for (index, step, x) in elems.chain(tail) {
+        path.push(step);
+        out.push((index, x, path.clone()));
+        if deep {
+            children(x, path, out, true);
+        }
+        path.pop();
+    }
+}
+
+impl QueryStep {
+    fn matches(&self, index: Option<(usize, usize)>, node: &GAst) -> bool {
+        let selected = match &self.selector {
+            Selector::Any => true,
+            Selector::Head(name) => head(node) == Some(name.as_str()),
+            Selector::Index(i) => match index {
+                Some((pos, len)) => resolve(*i, len) == Some(pos),
+                None => false,
+            },
+            Selector::Type(t) => t == type_name(node) || t == "const" && node.is_const(),
+        };
+        selected && self.predicates.iter().all(|p| p.matches(node))
+    }
+}
+
+impl Predicate {
+    fn matches(&self, node: &GAst) -> bool {
+        let target = match (self.index, node) {
+            (None, _) => node,
+            (Some(i), GAst::List(x)) => match resolve(i, x.0.len()) {
+                Some(i) => &x.0[i],
+                None => return false,
+            },
+            (Some(_), GAst::Const(_)) => return false,
+        };
+        let ord = compare(target, &self.value);
+        match self.op {
+            Op::Eq => equal(target, &self.value),
+            Op::Ne => !equal(target, &self.value),
+            Op::Lt => ord == Some(Ordering::Less),
+            Op::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ord == Some(Ordering::Greater),
+            Op::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/// Numbers by value, strings and symbols by text.
+fn compare(a: &GAst, b: &GAst) -> Option<Ordering> {
+    let (a, b) = match (a, b) {
+        (GAst::Const(a), GAst::Const(b)) => (a, b),
+        _ => return None,
+    };
+    match (a, b) {
+        (Constant::Str(a), Constant::Str(b)) => Some(a.cmp(b)),
+        (Constant::Sym(a), Constant::Sym(b)) => Some(a.0.cmp(&b.0)),
+        (Constant::Int(a), Constant::Int(b)) => Some(a.cmp(b)),
+        (Constant::Uint(a), Constant::Uint(b)) => Some(a.cmp(b)),
+        _ => number(a)?.partial_cmp(&number(b)?),
+    }
+}
+
+/// Numbers by value, anything else structurally.
+fn equal(a: &GAst, b: &GAst) -> bool {
+    match (a, b) {
+        (GAst::Const(x), GAst::Const(y)) if number(x).is_some() && number(y).is_some() => {
+            compare(a, b) == Some(Ordering::Equal)
+        }
+        _ => a == b,
+    }
+}
+
+fn number(c: &Constant) -> Option<f64> {
+    match c {
+        Constant::Int(x) => Some(*x as f64),
+        Constant::Uint(x) => Some(*x as f64),
+        Constant::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn head(node: &GAst) -> Option<&str> {
+    match node {
+        GAst::List(x) => match x.0.first() {
+            Some(GAst::Const(Constant::Sym(s))) => Some(&s.0),
+            _ => None,
+        },
+        GAst::Const(_) => None,
+    }
+}
+
+fn type_name(node: &GAst) -> &'static str {
+    match node {
+        GAst::List(_) => "list",
+        GAst::Const(c) => match c {
+            Constant::Nil => "nil",
+            Constant::Bool(_) => "bool",
+            Constant::Char(_) => "char",
+            Constant::Int(_) => "int",
+            Constant::Uint(_) => "uint",
+            Constant::Float(_) => "float",
+            Constant::Str(_) => "str",
+            Constant::Sym(_) => "sym",
+        },
+    }
+}
+
+/// An index counting from the end if negative.
+fn resolve(i: i64, len: usize) -> Option<usize> {
+    let i = if i < 0 { len as i64 + i } else { i };
+    if 0 <= i && (i as usize) < len {
+        Some(i as usize)
+    } else {
+        None
+    }
+}
+
+struct QueryParser<'a> {
+    query: &'a str,
+    pos: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn error(&self, message: &str) -> QueryError {
+        QueryError {
+            pos: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.query[self.pos..]
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        self.pos = self.query.len() - self.rest().trim_start().len();
+    }
+
+    fn step(&mut self) -> Result<QueryStep, QueryError> {
+        let descendant = if self.eat("//") {
+            true
+        } else if self.eat("/") {
+            false
+        } else {
+            return Err(self.error("expected `/` or `//`"));
+        };
+        let selector = self.selector()?;
+        let mut predicates = vec![];
+        self.skip_ws();
+        while self.eat("[") {
+            predicates.push(self.predicate()?);
+            self.skip_ws();
+        }
+        Ok(QueryStep {
+            descendant,
+            selector,
+            predicates,
+        })
+    }
+
+    fn selector(&mut self) -> Result<Selector, QueryError> {
+        if self.eat("*") {
+            return Ok(Selector::Any);
+        }
+        if self.eat("#") {
+            return Ok(Selector::Index(self.index()?));
+        }
+        let start = self.pos;
+        let typed = self.eat("@");
+        let len = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || "/[]".contains(c))
+            .unwrap_or_else(|| self.rest().len());
+        let name = &self.rest()[..len];
+        if name.is_empty() {
+            return Err(self.error("expected a selector"));
+        }
+        if typed && !TYPES.contains(&name) {
+            self.pos = start;
+            return Err(self.error("unknown type"));
+        }
+        self.pos += len;
+        Ok(if typed {
+            Selector::Type(name.to_string())
+        } else {
+            Selector::Head(name.to_string())
+        })
+    }
+
+    fn index(&mut self) -> Result<i64, QueryError> {
+        let len = self
+            .rest()
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || i == 0 && c == '-'))
+            .map_or(self.rest().len(), |(i, _)| i);
+        let r = self.rest()[..len]
+            .parse()
+            .map_err(|_| self.error("expected an index"))?;
+        self.pos += len;
+        Ok(r)
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, QueryError> {
+        self.skip_ws();
+        let index = if self.eat("#") {
+            let i = self.index()?;
+            self.skip_ws();
+            Some(i)
+        } else {
+            None
+        };
+        let ops = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let op = match ops.iter().find(|(s, _)| self.rest().starts_with(s)) {
+            Some(&(s, op)) => {
+                self.pos += s.len();
+                op
+            }
+            None => return Err(self.error("expected a comparison")),
+        };
+        self.skip_ws();
+        let start = self.pos;
+        let end = self
+            .literal_end()
+            .ok_or_else(|| self.error("unclosed `[`"))?;
+        let literal = self.query[start..end].trim_end();
+        let value = match sexpr::one_unit_parse(literal, "<query>") {
+            Ok(x) if x.is_const() => x,
+            // such as an out of range number
+            Err(UnitError::Syntax(e)) if matches!(e.variant, ErrorVariant::CustomError { .. }) => {
+                return Err(self.error(&e.variant.message()))
+            }
+            _ => return Err(self.error("expected a constant")),
+        };
+        self.pos = end + 1;
+        Ok(Predicate { index, op, value })
+    }
+
+    /// Offset of the `]` closing a predicate, skipping string literals.
+    fn literal_end(&self) -> Option<usize> {
+        let mut in_str = false;
+        let mut escaped = false;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_str => escaped = true,
+                '"' => in_str = !in_str,
+                ']' if !in_str => return Some(self.pos + i),
+                _ => {}
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,692 @@
+//! Schemas for S-expression documents.
+//!
+//! A schema is itself read as S-expressions: `(define name type)` forms
+//! naming types and one `(root type)` form giving the type of every
+//! top-level datum of a document.
+//!
+//! Types:
+//! - `any`, `nil`, `bool`, `char`, `int`, `uint`, `float`, `number`, `str`,
+//!   `sym`, `list` (any list), or the name of a `define`
+//! - `(form head part...)`: a list starting with the symbol `head`, then
+//!   elements matching the parts
+//! - `(list part...)`: a list of elements matching the parts
+//! - `(or type...)`, `(enum constant...)`
+//!
+//! A dotted `(form head part... . type)` or `(list part... . type)` also
+//! requires a tail of that type; without it tails are not allowed. Parts
+//! are a type (one element), `(? type)`, `(* type)`, `(+ type)`, or, as the
+//! last part, `(alist entry...)`, which takes the remaining elements as
+//! entries `(key value...)` or `(key . value)`. An entry spec is
+//! `(key part...)`, or `(? key part...)` when the key may be left out.
+//!
+//! ```text
+//! (define server (form server (alist (name str) (port int) (? tags (* sym)))))
+//! (root (form config (* server)))
+//! ```
+//!
+//! The validator reports every violation it finds. Only symbols carry
+//! locations, so a violation is located at the first symbol in the offending
+//! node, or else in its nearest ancestor that has one.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use super::constant::Constant;
+use super::diff::Step;
+use super::list::List;
+use super::symbol::Location;
+use super::{GAst, Handle};
+use crate::syntax::error_location;
+use crate::syntax::sexpr::{self, printer::to_source};
+
+const BUILTINS: &[&str] = &[
+    "any", "nil", "bool", "char", "int", "uint", "float", "number", "str", "sym", "list",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(x) => write!(f, "{} at {}", self.message, x),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub message: String,
+    pub location: Option<Location>,
+    pub path: Vec<Step>,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(loc) = &self.location {
+            write!(f, "{}: ", loc)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Schema {
+    defs: Vec<Type>,
+    root: Type,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Builtin(&'static str),
+    Ref(usize),
+    Seq(Box<Seq>),
+    Or(Vec<Type>, GAst),
+    Enum(Vec<GAst>, GAst),
+}
+
+/// `form` and `list` types.
+#[derive(Debug, Clone)]
+struct Seq {
+    head: Option<String>,
+    parts: Vec<Part>,
+    tail: Option<Type>,
+    source: GAst,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    One(Type),
+    Optional(Type),
+    Many(Type),
+    Some(Type),
+    Alist(Vec<Entry>),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    key: String,
+    optional: bool,
+    parts: Vec<Part>,
+}
+
+/// Which part an element was matched with.
+enum Assign<'s> {
+    Type(&'s Type),
+    Alist(&'s [Entry]),
+}
+
+impl Schema {
+    pub fn parse(input: &str, path: Handle<String>) -> Result<Self, SchemaError> {
+        let forms = sexpr::parse(input, path.clone()).map_err(|e| SchemaError {
+            message: e.variant.message().to_string(),
+            location: Some(error_location(&e, path)),
+        })?;
+        Self::from_forms(&forms)
+    }
+
+    pub fn from_forms(forms: &[GAst]) -> Result<Self, SchemaError> {
+        let mut names = HashMap::new();
+        let mut bodies = vec![];
+        let mut root = None;
+        for form in forms {
+            let (head, args) = split_head(form).ok_or_else(|| error("expected a form", form))?;
+            match (head, args) {
+                ("define", [GAst::Const(Constant::Sym(name)), body]) => {
+                    if BUILTINS.contains(&name.0.as_str()) || names.contains_key(name.0.as_str()) {
+                        return Err(error("type defined twice", form));
+                    }
+                    names.insert(name.0.to_string(), bodies.len());
+                    bodies.push(body);
+                }
+                ("root", [body]) if root.is_none() => root = Some(body),
+                ("root", [_]) => return Err(error("more than one root", form)),
+                _ => return Err(error("expected (define name type) or (root type)", form)),
+            }
+        }
+        let c = Compiler { names: &names };
+        let root = c.ty(root.ok_or(SchemaError {
+            message: "missing root".to_string(),
+            location: None,
+        })?)?;
+        let defs = bodies.iter().map(|x| c.ty(x)).collect::<Result<_, _>>()?;
+        let schema = Schema { defs, root };
+        for (i, body) in bodies.iter().enumerate() {
+            if schema.unguarded(&schema.defs[i], &mut vec![i]) {
+                return Err(error("type refers to itself outside a list", body));
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Checks one datum against the root type.
+    pub fn validate(&self, ast: &GAst) -> Vec<Violation> {
+        let mut out = vec![];
+        self.report(&self.root, ast, &mut vec![], &mut out);
+        for v in out.iter_mut().filter(|v| v.location.is_none()) {
+            v.location = locate(ast, &v.path);
+        }
+        out
+    }
+
+    /// Checks every top-level datum; paths start with its index.
+    pub fn validate_unit(&self, forms: &[GAst]) -> Vec<Violation> {
+        let mut out = vec![];
+        for (i, x) in forms.iter().enumerate() {
+            let start = out.len();
+            self.report(&self.root, x, &mut vec![Step::Elem(i)], &mut out);
+            for v in out[start..].iter_mut().filter(|v| v.location.is_none()) {
+                v.location = locate(x, &v.path[1..]);
+            }
+        }
+        out
+    }
+
+    /// Whether `ty` can reach a def in `seen` without going into a list.
+    fn unguarded(&self, ty: &Type, seen: &mut Vec<usize>) -> bool {
+        match ty {
+            Type::Ref(i) if seen.contains(i) => true,
+            Type::Ref(i) => {
+                seen.push(*i);
+                let r = self.unguarded(&self.defs[*i], seen);
+                seen.pop();
+                r
+            }
+            Type::Or(x, _) => x.iter().any(|x| self.unguarded(x, seen)),
+            _ => false,
+        }
+    }
+
+    fn check(&self, ty: &Type, node: &GAst) -> bool {
+        match ty {
+            Type::Builtin(name) => builtin(name, node),
+            Type::Ref(i) => self.check(&self.defs[*i], node),
+            Type::Or(x, _) => x.iter().any(|x| self.check(x, node)),
+            Type::Enum(x, _) => x.contains(node),
+            Type::Seq(seq) => {
+                let elems = match seq_elems(seq, node) {
+                    Some(x) => x,
+                    None => return false,
+                };
+                let tail_ok = match (&seq.tail, &list_of(node).unwrap().1) {
+                    (Some(t), Some(x)) => self.check(t, x),
+                    (None, None) => true,
+                    _ => false,
+                };
+                tail_ok && self.align(&seq.parts, &elems, true).is_some()
+            }
+        }
+    }
+
+    /// A loose check telling which part an element was meant for: constants
+    /// fit any constant type and lists any list type with the right head.
+    fn fits(&self, ty: &Type, node: &GAst) -> bool {
+        match ty {
+            Type::Builtin("any") => true,
+            Type::Builtin("list") => node.is_list(),
+            Type::Builtin(_) | Type::Enum(..) => node.is_const(),
+            Type::Ref(i) => self.fits(&self.defs[*i], node),
+            Type::Or(x, _) => x.iter().any(|x| self.fits(x, node)),
+            Type::Seq(seq) => seq_elems(seq, node).is_some(),
+        }
+    }
+
+    /// Matches `elems` against `parts`, fully or loosely.
+    fn align<'s>(
+        &'s self,
+        parts: &'s [Part],
+        elems: &[&GAst],
+        full: bool,
+    ) -> Option<Vec<Assign<'s>>> {
+        let mut out = vec![];
+        let mut failed = HashSet::new();
+        if self.align_from(parts, elems, full, &mut out, &mut failed) {
+            Some(out)
+        } else {
+            None
+        }
+    }
+
+    /// `failed` holds the numbers of parts and elements left for which
+    /// there was no match, so each suffix pair is only tried once.
+    fn align_from<'s>(
+        &'s self,
+        parts: &'s [Part],
+        elems: &[&GAst],
+        full: bool,
+        out: &mut Vec<Assign<'s>>,
+        failed: &mut HashSet<(usize, usize)>,
+    ) -> bool {
+        if failed.contains(&(parts.len(), elems.len())) {
+            return false;
+        }
+        let ok = |ty: &Type, x: &GAst| {
+            if full {
+                self.check(ty, x)
+            } else {
+                self.fits(ty, x)
+            }
+        };
+        let (part, rest) = match parts.split_first() {
+            Some(x) => x,
+            None => return elems.is_empty(),
+        };
+        let (ty, min, max) = match part {
+            Part::One(t) => (t, 1, 1),
+            Part::Optional(t) => (t, 0, 1),
+            Part::Many(t) => (t, 0, usize::MAX),
+            Part::Some(t) => (t, 1, usize::MAX),
+            Part::Alist(entries) => {
+                let valid = !full || self.alist_ok(entries, elems);
+                if valid {
+                    out.extend(elems.iter().map(|_| Assign::Alist(entries)));
+                }
+                return valid;
+            }
+        };
+        let mut n = 0;
+        while n < max && n < elems.len() && ok(ty, elems[n]) {
+            n += 1;
+        }
+        // longest first
+        for n in (min..=n).rev() {
+            let len = out.len();
+            out.extend((0..n).map(|_| Assign::Type(ty)));
+            if self.align_from(rest, &elems[n..], full, out, failed) {
+                return true;
+            }
+            out.truncate(len);
+        }
+        failed.insert((parts.len(), elems.len()));
+        false
+    }
+
+    fn alist_ok(&self, entries: &[Entry], elems: &[&GAst]) -> bool {
+        let mut out = vec![];
+        self.report_alist(entries, elems, None, &mut vec![], 0, &mut out);
+        out.is_empty()
+    }
+
+    fn report(&self, ty: &Type, node: &GAst, path: &mut Vec<Step>, out: &mut Vec<Violation>) {
+        match ty {
+            Type::Builtin(name) => {
+                if !builtin(name, node) {
+                    out.push(violation(
+                        format!("expected {}, found {}", name, show(node)),
+                        node,
+                        path,
+                    ));
+                }
+            }
+            Type::Ref(i) => self.report(&self.defs[*i], node, path, out),
+            Type::Enum(x, source) => {
+                if !x.contains(node) {
+                    let message = format!("expected {}, found {}", to_source(source), show(node));
+                    out.push(violation(message, node, path));
+                }
+            }
+            Type::Or(x, source) => {
+                if x.iter().any(|x| self.check(x, node)) {
+                    return;
+                }
+                let fitting: Vec<_> = x.iter().filter(|x| self.fits(x, node)).collect();
+                if let [ty] = fitting.as_slice() {
+                    self.report(ty, node, path, out);
+                } else {
+                    let message = format!("expected {}, found {}", to_source(source), show(node));
+                    out.push(violation(message, node, path));
+                }
+            }
+            Type::Seq(seq) => self.report_seq(seq, node, path, out),
+        }
+    }
+
+    fn report_seq(&self, seq: &Seq, node: &GAst, path: &mut Vec<Step>, out: &mut Vec<Violation>) {
+        let elems = match seq_elems(seq, node) {
+            Some(x) => x,
+            None => {
+                let expected = match &seq.head {
+                    Some(head) => format!("({} ...)", head),
+                    None => "a list".to_string(),
+                };
+                out.push(violation(
+                    format!("expected {}, found {}", expected, show(node)),
+                    node,
+                    path,
+                ));
+                return;
+            }
+        };
+        let list = list_of(node).unwrap();
+        let offset = list.0.len() - elems.len();
+        self.report_parts(&seq.parts, &elems, node, offset, &seq.source, path, out);
+        match (&seq.tail, &list.1) {
+            (Some(t), Some(x)) => {
+                path.push(Step::Tail);
+                self.report(t, x, path, out);
+                path.pop();
+            }
+            (Some(_), None) => out.push(violation(
+                format!("missing dotted tail in {}", show(node)),
+                node,
+                path,
+            )),
+            (None, Some(x)) => {
+                path.push(Step::Tail);
+                out.push(violation(
+                    format!("unexpected dotted tail {}", show(x)),
+                    x,
+                    path,
+                ));
+                path.pop();
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Reports on `elems`, which start at `offset` in the list `node`.
+    #[allow(clippy::too_many_arguments)]
+    fn report_parts(
+        &self,
+        parts: &[Part],
+        elems: &[&GAst],
+        node: &GAst,
+        offset: usize,
+        source: &GAst,
+        path: &mut Vec<Step>,
+        out: &mut Vec<Violation>,
+    ) {
+        if self.align(parts, elems, true).is_some() {
+            return;
+        }
+        let assigns = match self.align(parts, elems, false) {
+            Some(x) => x,
+            None => {
+                let message = format!("{} does not match {}", show(node), to_source(source));
+                out.push(violation(message, node, path));
+                return;
+            }
+        };
+        let mut i = 0;
+        while i < elems.len() {
+            match assigns[i] {
+                Assign::Type(ty) => {
+                    path.push(Step::Elem(offset + i));
+                    self.report(ty, elems[i], path, out);
+                    path.pop();
+                    i += 1;
+                }
+                Assign::Alist(entries) => {
+                    self.report_alist(entries, &elems[i..], Some(node), path, offset + i, out);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn report_alist(
+        &self,
+        entries: &[Entry],
+        elems: &[&GAst],
+        node: Option<&GAst>,
+        path: &mut Vec<Step>,
+        offset: usize,
+        out: &mut Vec<Violation>,
+    ) {
+        let mut seen = HashSet::new();
+        for (i, x) in elems.iter().enumerate() {
+            path.push(Step::Elem(offset + i));
+            let (key, _) = match split_head(x) {
+                Some(k) => k,
+                None => {
+                    out.push(violation(
+                        format!("expected an entry (key ...), found {}", show(x)),
+                        x,
+                        path,
+                    ));
+                    path.pop();
+                    continue;
+                }
+            };
+            match entries.iter().find(|e| e.key == key) {
+                None => out.push(violation(format!("unknown key {}", key), x, path)),
+                Some(_) if !seen.insert(key) => {
+                    out.push(violation(format!("duplicate key {}", key), x, path))
+                }
+                Some(e) => {
+                    let list = list_of(x).unwrap();
+                    match &list.1 {
+                        Some(v) if list.0.len() == 1 => {
+                            path.push(Step::Tail);
+                            self.report_parts(&e.parts, &[v], v, 0, v, path, out);
+                            path.pop();
+                        }
+                        _ => {
+                            let values: Vec<_> = list.0[1..].iter().collect();
+                            self.report_parts(&e.parts, &values, x, 1, x, path, out);
+                        }
+                    }
+                }
+            }
+            path.pop();
+        }
+        for e in entries
+            .iter()
+            .filter(|e| !e.optional && !seen.contains(e.key.as_str()))
+        {
+            let message = format!("missing required key {}", e.key);
+            out.push(match node {
+                Some(node) => violation(message, node, path),
+                None => Violation {
+                    message,
+                    location: None,
+                    path: path.clone(),
+                },
+            });
+        }
+    }
+}
+
+struct Compiler<'a> {
+    names: &'a HashMap<String, usize>,
+}
+
+impl Compiler<'_> {
+    fn ty(&self, x: &GAst) -> Result<Type, SchemaError> {
+        if let GAst::Const(Constant::Sym(name)) = x {
+            if let Some(b) = BUILTINS.iter().find(|b| **b == name.0.as_str()) {
+                return Ok(Type::Builtin(b));
+            }
+            return match self.names.get(name.0.as_str()) {
+                Some(&i) => Ok(Type::Ref(i)),
+                None => Err(error("unknown type", x)),
+            };
+        }
+        let (head, args) = split_head(x).ok_or_else(|| error("expected a type", x))?;
+        let tail = list_of(x).unwrap().1.as_ref();
+        match head {
+            "form" => match args.split_first() {
+                Some((GAst::Const(Constant::Sym(h)), parts)) => {
+                    self.seq(Some(h.0.to_string()), parts, tail, x)
+                }
+                _ => Err(error("expected (form head part...)", x)),
+            },
+            "list" => self.seq(None, args, tail, x),
+            "or" if tail.is_none() && !args.is_empty() => {
+                let alternatives = args.iter().map(|x| self.ty(x)).collect::<Result<_, _>>()?;
+                Ok(Type::Or(alternatives, x.clone()))
+            }
+            "enum" if tail.is_none() && args.iter().all(GAst::is_const) => {
+                Ok(Type::Enum(args.to_vec(), x.clone()))
+            }
+            _ => Err(error("expected a type", x)),
+        }
+    }
+
+    fn seq(
+        &self,
+        head: Option<String>,
+        parts: &[GAst],
+        tail: Option<&GAst>,
+        source: &GAst,
+    ) -> Result<Type, SchemaError> {
+        let parts = self.parts(parts)?;
+        let tail = tail.map(|x| self.ty(x)).transpose()?;
+        Ok(Type::Seq(Box::new(Seq {
+            head,
+            parts,
+            tail,
+            source: source.clone(),
+        })))
+    }
+
+    fn parts(&self, parts: &[GAst]) -> Result<Vec<Part>, SchemaError> {
+        let mut r = vec![];
+        for (i, x) in parts.iter().enumerate() {
+            let part = match split_head(x) {
+                Some(("?", [t])) => Part::Optional(self.ty(t)?),
+                Some(("*", [t])) => Part::Many(self.ty(t)?),
+                Some(("+", [t])) => Part::Some(self.ty(t)?),
+                Some(("alist", entries)) if i + 1 == parts.len() => Part::Alist(
+                    entries
+                        .iter()
+                        .map(|x| self.entry(x))
+                        .collect::<Result<_, _>>()?,
+                ),
+                Some(("alist", _)) => return Err(error("alist must be the last part", x)),
+                _ => Part::One(self.ty(x)?),
+            };
+            r.push(part);
+        }
+        Ok(r)
+    }
+
+    fn entry(&self, x: &GAst) -> Result<Entry, SchemaError> {
+        let (optional, spec) = match split_head(x) {
+            Some(("?", spec)) => (true, spec),
+            Some(_) => (false, &list_of(x).unwrap().0[..]),
+            None => return Err(error("expected (key part...)", x)),
+        };
+        match spec.split_first() {
+            Some((GAst::Const(Constant::Sym(key)), parts)) => Ok(Entry {
+                key: key.0.to_string(),
+                optional,
+                parts: self.parts(parts)?,
+            }),
+            _ => Err(error("expected (key part...)", x)),
+        }
+    }
+}
+
+fn builtin(name: &str, node: &GAst) -> bool {
+    match (name, node) {
+        ("any", _) => true,
+        ("list", GAst::List(_)) => true,
+        (_, GAst::Const(c)) => matches!(
+            (name, c),
+            ("nil", Constant::Nil)
+                | ("bool", Constant::Bool(_))
+                | ("char", Constant::Char(_))
+                | ("int", Constant::Int(_))
+                | ("uint", Constant::Uint(_))
+                | ("float", Constant::Float(_))
+                | (
+                    "number",
+                    Constant::Int(_) | Constant::Uint(_) | Constant::Float(_)
+                )
+                | ("str", Constant::Str(_))
+                | ("sym", Constant::Sym(_))
+        ),
+        _ => false,
+    }
+}
+
+/// The elements after the head if `node` is a list of the right shape.
+fn seq_elems<'a>(seq: &Seq, node: &'a GAst) -> Option<Vec<&'a GAst>> {
+    let list = list_of(node)?;
+    match &seq.head {
+        None => Some(list.0.iter().collect()),
+        Some(head) => match split_head(node) {
+            Some((h, rest)) if h == head => Some(rest.iter().collect()),
+            _ => None,
+        },
+    }
+}
+
+fn list_of(node: &GAst) -> Option<&List> {
+    match node {
+        GAst::List(x) => Some(x),
+        GAst::Const(_) => None,
+    }
+}
+
+/// The head symbol and the other elements of a list.
+fn split_head(node: &GAst) -> Option<(&str, &[GAst])> {
+    match node {
+        GAst::List(x) => match x.0.split_first() {
+            Some((GAst::Const(Constant::Sym(h)), rest)) => Some((&h.0, rest)),
+            _ => None,
+        },
+        GAst::Const(_) => None,
+    }
+}
+
+/// The location of the node at `path` or, if it has none, of the nearest
+/// ancestor that has one.
+fn locate(root: &GAst, path: &[Step]) -> Option<Location> {
+    let mut nodes = vec![root];
+    for step in path {
+        let list = list_of(nodes.last().unwrap())?;
+        let next = match step {
+            Step::Elem(i) => list.0.get(*i),
+            Step::Tail => list.1.as_ref(),
+        };
+        nodes.push(next?);
+    }
+    nodes.iter().rev().find_map(|x| location_of(x))
+}
+
+/// The location of the first symbol in `node`.
+fn location_of(node: &GAst) -> Option<Location> {
+    match node {
+        GAst::Const(Constant::Sym(x)) => Some(x.1.clone()),
+        GAst::Const(_) => None,
+        GAst::List(x) => x.0.iter().chain(x.1.iter()).find_map(location_of),
+    }
+}
+
+/// A short rendering of a node for messages.
+fn show(node: &GAst) -> String {
+    match split_head(node) {
+        Some((head, _)) => format!("({} ...)", head),
+        None => {
+            let s = to_source(node);
+            if s.chars().count() > 40 {
+                format!("{}...", s.chars().take(37).collect::<String>())
+            } else {
+                s
+            }
+        }
+    }
+}
+
+fn violation(message: String, node: &GAst, path: &[Step]) -> Violation {
+    Violation {
+        message,
+        location: location_of(node),
+        path: path.to_vec(),
+    }
+}
+
+fn error(message: &str, node: &GAst) -> SchemaError {
+    SchemaError {
+        message: message.to_string(),
+        location: location_of(node),
+    }
+}
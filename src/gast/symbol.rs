@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::{fmt::Display, hash::Hash};
+use std::{cmp::Ordering, fmt::Display, hash::Hash};
 
 use super::Handle;
 // use crate::utils::string_intern;
@@ -23,6 +23,12 @@ impl Location {
     }
 }
 
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.colum)
+    }
+}
+
 #[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct Symbol(pub Handle<String>, pub Location);
 
@@ -42,6 +48,18 @@ impl PartialEq for Symbol {
     }
 }
 
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
 impl Display for Symbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Display::fmt(&self.0, f)
@@ -53,6 +71,3 @@ impl Hash for Symbol {
         self.0.hash(state);
     }
 }
-
-unsafe impl Sync for Symbol {}
-unsafe impl Send for Symbol {}
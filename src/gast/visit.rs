@@ -0,0 +1,253 @@
+//! Generic traversals.
+//!
+//! `Visit` walks a tree read-only, `VisitMut` walks it allowing nodes to be
+//! replaced, and `Fold` computes a value bottom-up. Hooks get the path from
+//! the root to the node. `rewrite_bottom_up` and `rewrite_top_down` map a
+//! function over a tree. Mutating walks and rewrites only rebuild the lists
+//! on the way to a changed node; untouched subtrees keep their `Handle`.
+
+use super::constant::Constant;
+use super::diff::Step;
+use super::list::List;
+use super::{GAst, Handle};
+
+/// What to do after a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// do not walk the children of this node (from an `enter_` hook)
+    Skip,
+    /// end the walk
+    Stop,
+}
+
+pub trait Visit {
+    fn enter_const(&mut self, _c: &Constant, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    fn enter_list(&mut self, _list: &List, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    fn leave_list(&mut self, _list: &List, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    /// Walks `ast`; `Flow::Stop` if a hook stopped the walk.
+    fn visit(&mut self, ast: &GAst) -> Flow
+    where
+        Self: Sized,
+    {
+        walk(self, ast, &mut vec![])
+    }
+}
+
+pub fn walk<V: Visit + ?Sized>(v: &mut V, ast: &GAst, path: &mut Vec<Step>) -> Flow {
+    let list = match ast {
+        GAst::Const(c) => return stop_or_continue(v.enter_const(c, path)),
+        GAst::List(x) => x,
+    };
+    match v.enter_list(list, path) {
+        Flow::Continue => {}
+        Flow::Skip => return Flow::Continue,
+        Flow::Stop => return Flow::Stop,
+    }
+    for (step, x) in children(list) {
+        path.push(step);
+        let flow = walk(v, x, path);
+        path.pop();
+        if flow == Flow::Stop {
+            return Flow::Stop;
+        }
+    }
+    stop_or_continue(v.leave_list(list, path))
+}
+
+/// Hooks get the node itself and may replace it with any other node; the
+/// children walked are those of the replacement.
+pub trait VisitMut {
+    /// Called on constant nodes.
+    fn enter_const(&mut self, _node: &mut GAst, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called on list nodes before their children.
+    fn enter_list(&mut self, _node: &mut GAst, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called on list nodes after their children.
+    fn leave_list(&mut self, _node: &mut GAst, _path: &[Step]) -> Flow {
+        Flow::Continue
+    }
+
+    fn visit_mut(&mut self, ast: &mut GAst) -> Flow
+    where
+        Self: Sized,
+    {
+        walk_mut(self, ast, &mut vec![])
+    }
+}
+
+pub fn walk_mut<V: VisitMut + ?Sized>(v: &mut V, ast: &mut GAst, path: &mut Vec<Step>) -> Flow {
+    let flow = if ast.is_list() {
+        v.enter_list(ast, path)
+    } else {
+        v.enter_const(ast, path)
+    };
+    match flow {
+        Flow::Continue => {}
+        Flow::Skip => return Flow::Continue,
+        Flow::Stop => return Flow::Stop,
+    }
+    let handle = match ast {
+        GAst::List(x) => x,
+        GAst::Const(_) => return Flow::Continue,
+    };
+    let mut flow = Flow::Continue;
+    let mut changed: Option<List> = None;
+    let old: Vec<_> = children(handle).map(|(s, x)| (s, x.clone())).collect();
+    for (step, x) in old {
+        let mut new = x.clone();
+        path.push(step);
+        flow = walk_mut(v, &mut new, path);
+        path.pop();
+        if !same(&x, &new) {
+            set_child(
+                changed.get_or_insert_with(|| List::clone(handle)),
+                step,
+                new,
+            );
+        }
+        if flow == Flow::Stop {
+            break;
+        }
+    }
+    if let Some(x) = changed {
+        *handle = Handle::new(x);
+    }
+    if flow == Flow::Stop {
+        return Flow::Stop;
+    }
+    stop_or_continue(v.leave_list(ast, path))
+}
+
+/// Computes a value per node from the values of its children.
+pub trait Fold {
+    type Output;
+
+    fn fold_const(&mut self, c: &Constant, path: &[Step]) -> Self::Output;
+
+    fn fold_list(
+        &mut self,
+        list: &List,
+        elems: Vec<Self::Output>,
+        tail: Option<Self::Output>,
+        path: &[Step],
+    ) -> Self::Output;
+
+    /// Called before the children of a list; a value returned here is used
+    /// for the list without folding its children.
+    fn enter_list(&mut self, _list: &List, _path: &[Step]) -> Option<Self::Output> {
+        None
+    }
+
+    fn fold(&mut self, ast: &GAst) -> Self::Output
+    where
+        Self: Sized,
+    {
+        fold_with(self, ast, &mut vec![])
+    }
+}
+
+pub fn fold_with<F: Fold + ?Sized>(f: &mut F, ast: &GAst, path: &mut Vec<Step>) -> F::Output {
+    let list = match ast {
+        GAst::Const(c) => return f.fold_const(c, path),
+        GAst::List(x) => x,
+    };
+    if let Some(x) = f.enter_list(list, path) {
+        return x;
+    }
+    let mut elems = Vec::with_capacity(list.0.len());
+    let mut tail = None;
+    for (step, x) in children(list) {
+        path.push(step);
+        let r = fold_with(f, x, path);
+        path.pop();
+        match step {
+            Step::Elem(_) => elems.push(r),
+            Step::Tail => tail = Some(r),
+        }
+    }
+    f.fold_list(list, elems, tail, path)
+}
+
+/// Rewrites children first, then offers the node (with its new children) to
+/// `f`, which returns a replacement or `None` to keep it.
+pub fn rewrite_bottom_up(ast: &GAst, f: &mut impl FnMut(&GAst) -> Option<GAst>) -> GAst {
+    let ast = map_children(ast, |x| rewrite_bottom_up(x, f));
+    f(&ast).unwrap_or(ast)
+}
+
+/// Offers each node to `f` before its children. A replacement is used as it
+/// is, without rewriting inside it; otherwise the children are rewritten.
+pub fn rewrite_top_down(ast: &GAst, f: &mut impl FnMut(&GAst) -> Option<GAst>) -> GAst {
+    match f(ast) {
+        Some(x) => x,
+        None => map_children(ast, |x| rewrite_top_down(x, f)),
+    }
+}
+
+/// `ast` with `f` applied to its children, sharing it if none changed.
+fn map_children(ast: &GAst, mut f: impl FnMut(&GAst) -> GAst) -> GAst {
+    let handle = match ast {
+        GAst::List(x) => x,
+        GAst::Const(_) => return ast.clone(),
+    };
+    let mut changed: Option<List> = None;
+    for (step, x) in children(handle) {
+        let new = f(x);
+        if !same(x, &new) {
+            set_child(
+                changed.get_or_insert_with(|| List::clone(handle)),
+                step,
+                new,
+            );
+        }
+    }
+    match changed {
+        Some(x) => GAst::List(Handle::new(x)),
+        None => ast.clone(),
+    }
+}
+
+fn children(list: &List) -> impl Iterator<Item = (Step, &GAst)> {
+    let elems = list.0.iter().enumerate().map(|(i, x)| (Step::Elem(i), x));
+    elems.chain(list.1.iter().map(|x| (Step::Tail, x)))
+}
+
+fn set_child(list: &mut List, step: Step, value: GAst) {
+    match step {
+        Step::Elem(i) => list.0[i] = value,
+        Step::Tail => list.1 = Some(value),
+    }
+}
+
+/// The same node: the same list `Handle`, an equal symbol at the same
+/// location or an equal constant.
+pub(crate) fn same(a: &GAst, b: &GAst) -> bool {
+    match (a, b) {
+        (GAst::List(a), GAst::List(b)) => Handle::ptr_eq(a, b),
+        (GAst::Const(Constant::Sym(a)), GAst::Const(Constant::Sym(b))) => a == b && a.1 == b.1,
+        (GAst::Const(a), GAst::Const(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn stop_or_continue(flow: Flow) -> Flow {
+    match flow {
+        Flow::Stop => Flow::Stop,
+        _ => Flow::Continue,
+    }
+}
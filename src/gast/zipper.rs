@@ -0,0 +1,292 @@
+//! A cursor for structural editing.
+//!
+//! A `Zipper` focuses one node of a tree and keeps the lists above it.
+//! Moving and editing only touch those lists; the ancestors of an edited
+//! node are rebuilt as the cursor moves up (at the latest on `root`), and
+//! everything not edited keeps its `Handle`. Moves return `false` and leave
+//! the cursor where it was when there is nowhere to go.
+
+use super::diff::Step;
+use super::list::List;
+use super::visit::same;
+use super::{GAst, Handle};
+
+#[derive(Debug, Clone)]
+pub struct Zipper {
+    focus: GAst,
+    /// the lists above the focus, innermost last
+    crumbs: Vec<Crumb>,
+}
+
+#[derive(Debug, Clone)]
+struct Crumb {
+    original: Handle<List>,
+    /// a copy of `original`, made on the first edit
+    edited: Option<List>,
+    /// where the focus (or the next crumb) is in this list
+    step: Step,
+}
+
+impl Crumb {
+    fn list(&self) -> &List {
+        self.edited.as_ref().unwrap_or(&self.original)
+    }
+
+    fn list_mut(&mut self) -> &mut List {
+        let original = &self.original;
+        self.edited.get_or_insert_with(|| List::clone(original))
+    }
+
+    fn child(&self, step: Step) -> Option<&GAst> {
+        match step {
+            Step::Elem(i) => self.list().0.get(i),
+            Step::Tail => self.list().1.as_ref(),
+        }
+    }
+
+    fn into_gast(self) -> GAst {
+        match self.edited {
+            Some(x) => GAst::List(Handle::new(x)),
+            None => GAst::List(self.original),
+        }
+    }
+}
+
+impl Zipper {
+    pub fn new(root: GAst) -> Self {
+        Zipper {
+            focus: root,
+            crumbs: vec![],
+        }
+    }
+
+    pub fn focus(&self) -> &GAst {
+        &self.focus
+    }
+
+    /// Steps from the root to the focus.
+    pub fn path(&self) -> Vec<Step> {
+        self.crumbs.iter().map(|x| x.step).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.crumbs.is_empty()
+    }
+
+    /// The whole tree with all edits.
+    pub fn root(mut self) -> GAst {
+        while self.up() {}
+        self.focus
+    }
+
+    /// Moves to the first element (or the tail) of the focused list.
+    pub fn down(&mut self) -> bool {
+        let list = match &self.focus {
+            GAst::List(x) => x.clone(),
+            GAst::Const(_) => return false,
+        };
+        let step = if !list.0.is_empty() {
+            Step::Elem(0)
+        } else if list.1.is_some() {
+            Step::Tail
+        } else {
+            return false;
+        };
+        self.crumbs.push(Crumb {
+            original: list,
+            edited: None,
+            step,
+        });
+        self.focus = self.crumbs.last().unwrap().child(step).unwrap().clone();
+        true
+    }
+
+    pub fn up(&mut self) -> bool {
+        self.store();
+        match self.crumbs.pop() {
+            Some(x) => {
+                self.focus = x.into_gast();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the next element, or from the last element to the tail.
+    pub fn right(&mut self) -> bool {
+        let step = match self.crumbs.last() {
+            Some(c) => match c.step {
+                Step::Elem(i) if i + 1 < c.list().0.len() => Step::Elem(i + 1),
+                Step::Elem(_) if c.list().1.is_some() => Step::Tail,
+                _ => return false,
+            },
+            None => return false,
+        };
+        self.move_to(step);
+        true
+    }
+
+    /// Moves to the previous element, or from the tail to the last element.
+    pub fn left(&mut self) -> bool {
+        let step = match self.crumbs.last() {
+            Some(c) => match c.step {
+                Step::Elem(i) if i > 0 => Step::Elem(i - 1),
+                Step::Tail if !c.list().0.is_empty() => Step::Elem(c.list().0.len() - 1),
+                _ => return false,
+            },
+            None => return false,
+        };
+        self.move_to(step);
+        true
+    }
+
+    pub fn replace(&mut self, node: GAst) -> GAst {
+        std::mem::replace(&mut self.focus, node)
+    }
+
+    /// Inserts an element in front of the focus; fails at the root or tail.
+    pub fn insert_left(&mut self, node: GAst) -> bool {
+        let c = match self.crumbs.last_mut() {
+            Some(x) => x,
+            None => return false,
+        };
+        match c.step {
+            Step::Elem(i) => {
+                c.list_mut().0.insert(i, node);
+                c.step = Step::Elem(i + 1);
+                true
+            }
+            Step::Tail => false,
+        }
+    }
+
+    /// Inserts an element after the focus; fails at the root or tail.
+    pub fn insert_right(&mut self, node: GAst) -> bool {
+        let c = match self.crumbs.last_mut() {
+            Some(x) => x,
+            None => return false,
+        };
+        match c.step {
+            Step::Elem(i) => {
+                c.list_mut().0.insert(i + 1, node);
+                true
+            }
+            Step::Tail => false,
+        }
+    }
+
+    /// Removes the focused node and moves to the next element, else the
+    /// previous one, else the parent. `None` at the root.
+    pub fn remove(&mut self) -> Option<GAst> {
+        self.splice_with(vec![])
+    }
+
+    /// Replaces the focused element with `nodes` and focuses the first of
+    /// them; with no nodes this is `remove`. Fails at the root or tail.
+    pub fn splice(&mut self, nodes: Vec<GAst>) -> bool {
+        match self.crumbs.last() {
+            Some(c) if c.step != Step::Tail || nodes.is_empty() => {
+                self.splice_with(nodes);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves the element after the focused list into it, at its end.
+    pub fn slurp_forward(&mut self) -> bool {
+        let list = match &self.focus {
+            GAst::List(x) if x.1.is_none() => x,
+            _ => return false,
+        };
+        let c = match self.crumbs.last_mut() {
+            Some(x) => x,
+            None => return false,
+        };
+        let i = match c.step {
+            Step::Elem(i) if i + 1 < c.list().0.len() => i,
+            _ => return false,
+        };
+        let next = c.list_mut().0.remove(i + 1);
+        let mut list = List::clone(list);
+        list.0.push(next);
+        self.focus = GAst::List(Handle::new(list));
+        true
+    }
+
+    /// Moves the last element of the focused list out, after the list.
+    pub fn barf_forward(&mut self) -> bool {
+        let list = match &self.focus {
+            GAst::List(x) if x.1.is_none() && !x.0.is_empty() => x,
+            _ => return false,
+        };
+        let c = match self.crumbs.last_mut() {
+            Some(x) => x,
+            None => return false,
+        };
+        let i = match c.step {
+            Step::Elem(i) => i,
+            Step::Tail => return false,
+        };
+        let mut list = List::clone(list);
+        let last = list.0.pop().unwrap();
+        c.list_mut().0.insert(i + 1, last);
+        self.focus = GAst::List(Handle::new(list));
+        true
+    }
+
+    /// Writes the focus back into its parent if it changed.
+    fn store(&mut self) {
+        if let Some(c) = self.crumbs.last_mut() {
+            if !same(c.child(c.step).unwrap(), &self.focus) {
+                let focus = self.focus.clone();
+                match c.step {
+                    Step::Elem(i) => c.list_mut().0[i] = focus,
+                    Step::Tail => c.list_mut().1 = Some(focus),
+                }
+            }
+        }
+    }
+
+    fn move_to(&mut self, step: Step) {
+        self.store();
+        let c = self.crumbs.last_mut().unwrap();
+        c.step = step;
+        self.focus = c.child(step).unwrap().clone();
+    }
+
+    fn splice_with(&mut self, nodes: Vec<GAst>) -> Option<GAst> {
+        let c = self.crumbs.last_mut()?;
+        let inserted = nodes.len();
+        let step = c.step;
+        let list = c.list_mut();
+        let next = match step {
+            Step::Elem(i) => {
+                list.0.splice(i..=i, nodes);
+                if inserted > 0 || i < list.0.len() {
+                    Some(Step::Elem(i))
+                } else if list.1.is_some() {
+                    Some(Step::Tail)
+                } else {
+                    i.checked_sub(1).map(Step::Elem)
+                }
+            }
+            Step::Tail => {
+                list.1 = None;
+                list.0.len().checked_sub(1).map(Step::Elem)
+            }
+        };
+        let removed = match next {
+            Some(step) => {
+                c.step = step;
+                let next = c.child(step).unwrap().clone();
+                std::mem::replace(&mut self.focus, next)
+            }
+            None => {
+                let parent = self.crumbs.pop().unwrap().into_gast();
+                std::mem::replace(&mut self.focus, parent)
+            }
+        };
+        Some(removed)
+    }
+}
@@ -1,6 +1,10 @@
 #[macro_use]
 pub mod utils;
+pub mod diagnostics;
 pub mod error;
 pub mod gast;
+pub mod lint;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod syntax;
 mod test;
@@ -0,0 +1,420 @@
+//! Checking parsed files against pluggable rules.
+//!
+//! A `Rule` looks at every node of a file with the lists enclosing it, or
+//! at the whole file at once, and reports `Lint`s through a `Context`. The
+//! built-in rules are in `rules`; `Linter::with_rule` adds others.
+//!
+//! A `Config` sets the severity of rules, or turns them off, and is read
+//! from a `.sexprlint` file of sexpr forms:
+//!
+//! ```text
+//! (error duplicate-key)         ; report as errors
+//! (warning mixed-brackets)      ; also `note` and `help`
+//! (allow dotted-pair)           ; do not run
+//! (heads define lambda let if)  ; known head symbols, for `unknown-head`
+//! ```
+//!
+//! A comment containing `lint: allow(rule, ...)` silences those rules on
+//! its line. On a line of its own it also silences the next line, or the
+//! whole top-level form starting there. Inside a sexpr list comments only
+//! appear right after the open bracket of a top-level list, so there only
+//! whole forms and their first and last lines can be silenced.
+
+pub mod rules;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pest::error::{Error, InputLocation};
+use pest::RuleType;
+use serde::Serialize;
+
+use crate::diagnostics::{floor_char_boundary, Label, Report, Severity};
+use crate::gast::constant::Constant;
+use crate::gast::list::List;
+use crate::gast::symbol::{Location, Symbol};
+use crate::gast::{GAst, Handle};
+use crate::syntax::{error_location, mexpr, scan, sexpr, Syntax, Token};
+
+/// Name of the config file `find_config` looks for.
+pub const CONFIG_FILE: &str = ".sexprlint";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Location,
+    /// byte range in the source
+    pub span: Range<usize>,
+    pub notes: Vec<String>,
+}
+
+impl Lint {
+    /// A parse error as an error of rule `parse-error`.
+    pub fn parse_error<R: RuleType>(e: &Error<R>, path: Handle<String>) -> Self {
+        let span = match e.location {
+            InputLocation::Pos(x) => x..x,
+            InputLocation::Span((a, b)) => a..b,
+        };
+        Lint {
+            rule: "parse-error",
+            severity: Severity::Error,
+            message: e.variant.message().to_string(),
+            location: error_location(e, path),
+            span,
+            notes: vec![],
+        }
+    }
+}
+
+impl From<&Lint> for Report {
+    fn from(l: &Lint) -> Self {
+        let report = Report::new(l.severity, &l.message)
+            .with_path(l.location.path.as_str())
+            .with_label(Label::primary(l.span.clone(), ""));
+        l.notes
+            .iter()
+            .fold(report, |r, x| r.with_note(x))
+            .with_note(format!("by rule `{}`", l.rule))
+    }
+}
+
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    /// One line on what the rule reports.
+    fn description(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    /// Called on every node, with the lists enclosing it, outermost first.
+    fn check_node(&self, _node: &GAst, _parents: &[&List], _cx: &mut Context) {}
+
+    /// Called once per file, before the nodes.
+    fn check_source(&self, _cx: &mut Context) {}
+}
+
+/// What a rule sees of the file and where its lints go.
+pub struct Context<'a> {
+    pub source: &'a str,
+    pub path: Handle<String>,
+    pub syntax: Syntax,
+    pub forms: &'a [(GAst, Range<usize>)],
+    rule: &'static str,
+    severity: Severity,
+    lints: Vec<Lint>,
+}
+
+impl Context<'_> {
+    /// The location of a byte offset, or of the start of its char.
+    pub fn location(&self, pos: usize) -> Location {
+        let pos = floor_char_boundary(self.source, pos);
+        let start = self.source[..pos].rfind('\n').map_or(0, |x| x + 1);
+        let line = self.source[..start].matches('\n').count() + 1;
+        let colum = self.source[start..pos].chars().count() + 1;
+        Location::new(self.path.clone(), line, colum, pos)
+    }
+
+    /// Reports a byte range; notes can be added to the lint returned.
+    pub fn report_span(&mut self, span: Range<usize>, message: impl Into<String>) -> &mut Lint {
+        self.lints.push(Lint {
+            rule: self.rule,
+            severity: self.severity,
+            message: message.into(),
+            location: self.location(span.start),
+            span,
+            notes: vec![],
+        });
+        self.lints.last_mut().unwrap()
+    }
+
+    /// Reports a symbol.
+    pub fn report_symbol(&mut self, sym: &Symbol, message: impl Into<String>) -> &mut Lint {
+        let span = self.span_of(sym);
+        self.report_span(span, message)
+    }
+
+    /// Reports a node at its first symbol, or else at the nearest enclosing
+    /// list that has one.
+    pub fn report_node(
+        &mut self,
+        node: &GAst,
+        parents: &[&List],
+        message: impl Into<String>,
+    ) -> &mut Lint {
+        let sym = first_symbol(node).or_else(|| {
+            parents
+                .iter()
+                .rev()
+                .find_map(|x| x.0.iter().chain(x.1.iter()).find_map(first_symbol))
+        });
+        let span = sym.map_or(0..0, |x| self.span_of(x));
+        self.report_span(span, message)
+    }
+
+    /// The text of a symbol, or where it starts if the parser made it up
+    /// (like the `quote` of `'x`).
+    fn span_of(&self, sym: &Symbol) -> Range<usize> {
+        let pos = sym.1.pos;
+        match self.source.get(pos..) {
+            Some(x) if x.starts_with(sym.0.as_str()) => pos..pos + sym.0.len(),
+            _ => pos..pos,
+        }
+    }
+}
+
+fn first_symbol(node: &GAst) -> Option<&Symbol> {
+    match node {
+        GAst::Const(Constant::Sym(x)) => Some(x),
+        GAst::Const(_) => None,
+        GAst::List(x) => x.0.iter().chain(x.1.iter()).find_map(first_symbol),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// severities by rule name, `None` for rules turned off
+    pub severities: HashMap<String, Option<Severity>>,
+    /// head symbols `unknown-head` accepts besides the file's definitions
+    pub heads: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(x) => write!(f, "{} at {}", self.message, x),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn parse(input: &str, path: Handle<String>) -> Result<Self, ConfigError> {
+        let forms = sexpr::parse(input, path.clone()).map_err(|e| ConfigError {
+            message: e.variant.message().to_string(),
+            location: Some(error_location(&e, path)),
+        })?;
+        Self::from_forms(&forms)
+    }
+
+    pub fn from_forms(forms: &[GAst]) -> Result<Self, ConfigError> {
+        let mut r = Config::default();
+        for form in forms {
+            let list = form.get_list();
+            let items = list.as_ref().map_or(&[][..], |x| &x.0[..]);
+            let mut names = vec![];
+            for x in items {
+                match x {
+                    GAst::Const(Constant::Sym(x)) => names.push(x),
+                    _ => {
+                        return Err(ConfigError {
+                            message: format!("expected symbols in `{}`", form),
+                            location: names.first().map(|x| x.1.clone()),
+                        })
+                    }
+                }
+            }
+            let (head, names) = match names.split_first() {
+                Some(x) => x,
+                None => {
+                    return Err(ConfigError {
+                        message: format!(
+                            "expected an `(error rule...)`, `(allow rule...)` or `(heads sym...)` form, found `{}`",
+                            form
+                        ),
+                        location: None,
+                    })
+                }
+            };
+            let severity = match head.0.as_str() {
+                "error" => Some(Severity::Error),
+                "warning" => Some(Severity::Warning),
+                "note" => Some(Severity::Note),
+                "help" => Some(Severity::Help),
+                "allow" => None,
+                "heads" => {
+                    r.heads.extend(names.iter().map(|x| x.0.to_string()));
+                    continue;
+                }
+                _ => {
+                    return Err(ConfigError {
+                        message: format!("unknown setting in `{}`", form),
+                        location: Some(head.1.clone()),
+                    })
+                }
+            };
+            for name in names {
+                r.severities.insert(name.0.to_string(), severity);
+            }
+        }
+        Ok(r)
+    }
+}
+
+/// The closest `.sexprlint` in the directory of `path` or above.
+pub fn find_config(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    path.ancestors()
+        .skip(1)
+        .map(|x| x.join(CONFIG_FILE))
+        .find(|x| x.is_file())
+}
+
+pub struct Linter {
+    config: Config,
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Linter {
+    /// A linter running the built-in rules.
+    pub fn new(config: Config) -> Self {
+        let rules = rules::builtin(&config);
+        Linter { config, rules }
+    }
+
+    pub fn with_rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|x| x.as_ref())
+    }
+
+    /// The severity a rule reports with, `None` if it is turned off.
+    pub fn severity(&self, rule: &dyn Rule) -> Option<Severity> {
+        match self.config.severities.get(rule.name()) {
+            Some(x) => *x,
+            None => Some(rule.default_severity()),
+        }
+    }
+
+    pub fn check_sexpr(
+        &self,
+        source: &str,
+        path: Handle<String>,
+    ) -> Result<Vec<Lint>, sexpr::ParseError> {
+        let forms = sexpr::parse_spanned(source, path.clone())?;
+        Ok(self.check(source, path, Syntax::Sexpr, &forms))
+    }
+
+    pub fn check_mexpr(
+        &self,
+        source: &str,
+        path: Handle<String>,
+    ) -> Result<Vec<Lint>, mexpr::ParseError> {
+        let forms = mexpr::parse_spanned(source, path.clone())?;
+        Ok(self.check(source, path, Syntax::Mexpr, &forms))
+    }
+
+    /// The lints of parsed forms, by position, without the silenced ones.
+    pub fn check(
+        &self,
+        source: &str,
+        path: Handle<String>,
+        syntax: Syntax,
+        forms: &[(GAst, Range<usize>)],
+    ) -> Vec<Lint> {
+        let mut cx = Context {
+            source,
+            path,
+            syntax,
+            forms,
+            rule: "",
+            severity: Severity::Warning,
+            lints: vec![],
+        };
+        for rule in &self.rules {
+            let severity = match self.severity(rule.as_ref()) {
+                Some(x) => x,
+                None => continue,
+            };
+            cx.rule = rule.name();
+            cx.severity = severity;
+            rule.check_source(&mut cx);
+            for (ast, _) in forms {
+                walk(rule.as_ref(), ast, &mut vec![], &mut cx);
+            }
+        }
+        let allowed = allowed_lines(source, syntax, forms);
+        let mut r: Vec<Lint> = cx
+            .lints
+            .into_iter()
+            .filter(|x| {
+                let allowed = allowed.get(&x.location.line);
+                !allowed.is_some_and(|names| names.contains(x.rule))
+            })
+            .collect();
+        r.sort_by_key(|x| (x.span.start, x.rule));
+        r
+    }
+}
+
+fn walk<'a>(rule: &dyn Rule, ast: &'a GAst, parents: &mut Vec<&'a List>, cx: &mut Context) {
+    rule.check_node(ast, parents, cx);
+    if let GAst::List(x) = ast {
+        parents.push(x);
+        for x in x.0.iter().chain(x.1.iter()) {
+            walk(rule, x, parents, cx);
+        }
+        parents.pop();
+    }
+}
+
+/// The rules `lint: allow(...)` comments silence, by line.
+fn allowed_lines(
+    source: &str,
+    syntax: Syntax,
+    forms: &[(GAst, Range<usize>)],
+) -> HashMap<usize, HashSet<String>> {
+    let line_of = |pos: usize| source[..pos].matches('\n').count() + 1;
+    let mut r: HashMap<usize, HashSet<String>> = HashMap::new();
+    scan(source, syntax, |token| {
+        let span = match token {
+            Token::Comment(x) => x,
+            Token::Open(_) => return,
+        };
+        let names = match allowed_rules(&source[span.clone()]) {
+            Some(x) => x,
+            None => return,
+        };
+        let line = line_of(span.start);
+        let mut lines = line..line + 1;
+        let line_start = source[..span.start].rfind('\n').map_or(0, |x| x + 1);
+        if source[line_start..span.start].trim().is_empty() {
+            let next = source[span.end..]
+                .find(|c: char| !c.is_whitespace())
+                .map(|x| span.end + x);
+            // the next line, or all of the form starting there
+            lines.end = match next.and_then(|x| forms.iter().find(|f| f.1.start == x)) {
+                Some((_, form)) if line_of(form.start) == line + 1 => line_of(form.end) + 1,
+                _ => line + 2,
+            };
+        }
+        for line in lines {
+            r.entry(line).or_default().extend(names.iter().cloned());
+        }
+    });
+    r
+}
+
+/// The names in the `lint: allow(a, b)` of a comment.
+fn allowed_rules(comment: &str) -> Option<Vec<String>> {
+    let rest = &comment[comment.find("lint:")? + "lint:".len()..];
+    let rest = rest.trim_start().strip_prefix("allow")?.trim_start();
+    let inner = &rest.strip_prefix('(')?[..rest.find(')')? - 1];
+    Some(inner.split(',').map(|x| x.trim().to_string()).collect())
+}
@@ -0,0 +1,294 @@
+//! The built-in rules.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{first_symbol, Config, Context, Rule};
+use crate::diagnostics::Severity;
+use crate::gast::constant::Constant;
+use crate::gast::list::List;
+use crate::gast::symbol::Symbol;
+use crate::gast::GAst;
+use crate::syntax::{scan, Syntax, Token};
+
+const QUOTES: &[&str] = &["quote", "quasiquote"];
+
+/// The heads the reader makes of `'`, `` ` ``, `,` and `,@`.
+const READER_HEADS: &[&str] = &["quote", "quasiquote", "unquote", "unquote-splicing"];
+
+pub(crate) fn builtin(config: &Config) -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(DuplicateKey),
+        Box::new(UnknownHead {
+            heads: config.heads.clone(),
+        }),
+        Box::new(MixedBrackets),
+        Box::new(DottedPair),
+        Box::new(ShadowedBinding),
+    ]
+}
+
+fn head(list: &List) -> Option<&Symbol> {
+    match list.0.first() {
+        Some(GAst::Const(Constant::Sym(x))) => Some(x),
+        _ => None,
+    }
+}
+
+fn as_list(x: &GAst) -> Option<&List> {
+    match x {
+        GAst::List(x) => Some(x),
+        GAst::Const(_) => None,
+    }
+}
+
+fn as_symbol(x: &GAst) -> Option<&Symbol> {
+    match x {
+        GAst::Const(Constant::Sym(x)) => Some(x),
+        _ => None,
+    }
+}
+
+fn is_quoted(parents: &[&List]) -> bool {
+    parents
+        .iter()
+        .any(|x| head(x).is_some_and(|x| QUOTES.contains(&x.0.as_str())))
+}
+
+/// Keys given twice in an association list: a list of lists, or a head
+/// symbol followed by dotted pairs, each entry starting with its key.
+pub struct DuplicateKey;
+
+impl Rule for DuplicateKey {
+    fn name(&self) -> &'static str {
+        "duplicate-key"
+    }
+
+    fn description(&self) -> &'static str {
+        "association lists giving a key twice"
+    }
+
+    fn check_node(&self, node: &GAst, _parents: &[&List], cx: &mut Context) {
+        let list = match node {
+            GAst::List(x) => x,
+            _ => return,
+        };
+        let entries: Vec<&List> = list.0.iter().filter_map(as_list).collect();
+        let alist = entries.len() == list.0.len();
+        let pairs = head(list).is_some()
+            && entries.len() + 1 == list.0.len()
+            && entries.iter().all(|x| x.1.is_some());
+        if !(alist || pairs) || entries.len() < 2 {
+            return;
+        }
+        let mut seen: HashMap<String, &List> = HashMap::new();
+        for entry in entries {
+            let key = match entry.0.first() {
+                Some(x @ GAst::Const(c)) if !matches!(c, Constant::Nil) => x,
+                _ => return,
+            };
+            match seen.get(&key.to_string()) {
+                Some(first) => {
+                    let message = format!("duplicate key `{}`", key);
+                    let lint = cx.report_node(key, &[list, entry], message);
+                    let first = first.0.iter().chain(first.1.iter()).find_map(first_symbol);
+                    if let Some(x) = first {
+                        lint.notes.push(format!("first given at {}", x.1));
+                    }
+                }
+                None => {
+                    seen.insert(key.to_string(), entry);
+                }
+            }
+        }
+    }
+}
+
+/// Lists headed by a symbol that is neither configured in `heads` nor
+/// defined in the file. Does nothing without `heads`.
+pub struct UnknownHead {
+    pub heads: HashSet<String>,
+}
+
+impl Rule for UnknownHead {
+    fn name(&self) -> &'static str {
+        "unknown-head"
+    }
+
+    fn description(&self) -> &'static str {
+        "lists headed by symbols that are not known heads or definitions"
+    }
+
+    fn check_source(&self, cx: &mut Context) {
+        if self.heads.is_empty() {
+            return;
+        }
+        let mut defined = HashSet::new();
+        for (ast, _) in cx.forms {
+            each_list(ast, &mut |list| {
+                defined.extend(bound_names(list).into_iter().map(|x| x.0.to_string()));
+            });
+        }
+        let mut unknown = vec![];
+        for (ast, _) in cx.forms {
+            each_list(ast, &mut |list| {
+                if let Some(x) = head(list) {
+                    let name = x.0.as_str();
+                    if !self.heads.contains(name)
+                        && !defined.contains(name)
+                        && !READER_HEADS.contains(&name)
+                    {
+                        unknown.push(x.clone());
+                    }
+                }
+            });
+        }
+        for x in unknown {
+            cx.report_symbol(&x, format!("unknown head `{}`", x));
+        }
+    }
+}
+
+/// Calls `f` on every list outside of quoted data.
+fn each_list<'a>(ast: &'a GAst, f: &mut impl FnMut(&'a List)) {
+    if let GAst::List(x) = ast {
+        f(x);
+        if head(x).is_some_and(|x| QUOTES.contains(&x.0.as_str())) {
+            return;
+        }
+        x.0.iter().chain(x.1.iter()).for_each(|x| each_list(x, f));
+    }
+}
+
+/// sexpr lists opened with a bracket other than the first one in the file.
+pub struct MixedBrackets;
+
+impl Rule for MixedBrackets {
+    fn name(&self) -> &'static str {
+        "mixed-brackets"
+    }
+
+    fn description(&self) -> &'static str {
+        "sexpr lists in brackets other than the ones the file starts with"
+    }
+
+    fn check_source(&self, cx: &mut Context) {
+        // mexpr brackets mean different things
+        if cx.syntax != Syntax::Sexpr {
+            return;
+        }
+        let mut opens = vec![];
+        scan(cx.source, Syntax::Sexpr, |x| {
+            if let Token::Open(open) = x {
+                opens.push(open);
+            }
+        });
+        opens.sort_unstable();
+        let source = cx.source;
+        let bracket = |x: usize| &source[x..x + 1];
+        let first = match opens.first() {
+            Some(&x) => bracket(x),
+            None => return,
+        };
+        for x in opens.into_iter().filter(|&x| bracket(x) != first) {
+            let message = format!("list in `{}`, the file uses `{}`", bracket(x), first);
+            cx.report_span(x..x + 1, message);
+        }
+    }
+}
+
+/// Lists ending in a `. tail`.
+pub struct DottedPair;
+
+impl Rule for DottedPair {
+    fn name(&self) -> &'static str {
+        "dotted-pair"
+    }
+
+    fn description(&self) -> &'static str {
+        "lists with a dotted tail"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Note
+    }
+
+    fn check_node(&self, node: &GAst, parents: &[&List], cx: &mut Context) {
+        if let GAst::List(x) = node {
+            if let Some(tail) = &x.1 {
+                let mut parents = parents.to_vec();
+                parents.push(x);
+                cx.report_node(tail, &parents, "dotted tail");
+            }
+        }
+    }
+}
+
+/// Names bound again inside a binding form that already binds them.
+pub struct ShadowedBinding;
+
+impl Rule for ShadowedBinding {
+    fn name(&self) -> &'static str {
+        "shadowed-binding"
+    }
+
+    fn description(&self) -> &'static str {
+        "`let`, `lambda` and `define` bindings hiding an enclosing one"
+    }
+
+    fn check_node(&self, node: &GAst, parents: &[&List], cx: &mut Context) {
+        let list = match node {
+            GAst::List(x) if !is_quoted(parents) => x,
+            _ => return,
+        };
+        let mut reported = HashSet::new();
+        for name in bound_names(list) {
+            let outer = parents
+                .iter()
+                .rev()
+                .flat_map(|x| bound_names(x))
+                .find(|x| x.0 == name.0);
+            if let Some(outer) = outer {
+                if reported.insert(name.0.clone()) {
+                    let message = format!("`{}` shadows an enclosing binding", name);
+                    let note = format!("first bound at {}", outer.1);
+                    cx.report_symbol(name, message).notes.push(note);
+                }
+            }
+        }
+    }
+}
+
+/// The names a binding form binds: the variables and name of a `let`,
+/// `let*`, `letrec` or `letrec*`, the parameters of a `lambda`, the name and
+/// parameters of a `define`.
+fn bound_names(list: &List) -> Vec<&Symbol> {
+    let mut r: Vec<&Symbol> = vec![];
+    let args = &list.0[1.min(list.0.len())..];
+    match head(list).map(|x| x.0.as_str()) {
+        Some("let" | "let*" | "letrec" | "letrec*") => {
+            let bindings = match args.first() {
+                Some(GAst::Const(Constant::Sym(x))) => {
+                    r.push(x);
+                    args.get(1)
+                }
+                x => x,
+            };
+            if let Some(GAst::List(x)) = bindings {
+                for binding in &x.0 {
+                    r.extend(
+                        as_list(binding)
+                            .and_then(|x| x.0.first())
+                            .and_then(as_symbol),
+                    );
+                }
+            }
+        }
+        Some("lambda" | "define") => match args.first() {
+            Some(GAst::List(x)) => r.extend(x.0.iter().chain(x.1.iter()).filter_map(as_symbol)),
+            Some(x) => r.extend(as_symbol(x)),
+            None => {}
+        },
+        _ => {}
+    }
+    r
+}
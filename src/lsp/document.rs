@@ -0,0 +1,285 @@
+//! What the server knows about one open file.
+
+use std::ops::Range;
+
+use crate::diagnostics::Report;
+use crate::gast::constant::Constant;
+use crate::gast::symbol::Symbol;
+use crate::gast::{GAst, Handle};
+use crate::syntax::format::{format_mexpr, format_sexpr, Style};
+use crate::syntax::sexpr::{self, recover};
+use crate::syntax::{mexpr, scan, Syntax, Token};
+
+/// mexpr for `.mexpr` files or the `mexpr` language id, sexpr otherwise.
+pub fn detect_syntax(uri: &str, language_id: Option<&str>) -> Syntax {
+    match language_id {
+        Some("mexpr") => Syntax::Mexpr,
+        _ => Syntax::of_path(uri),
+    }
+}
+
+/// An LSP position: line and UTF-16 offset in the line, both from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub uri: String,
+    pub syntax: Syntax,
+    text: String,
+    /// byte offsets of line starts
+    lines: Vec<usize>,
+    /// top-level forms and their byte ranges; empty if the text does not parse
+    forms: Vec<(GAst, Range<usize>)>,
+    reports: Vec<Report>,
+    /// byte offsets of matching open and close brackets, by open bracket
+    brackets: Vec<(usize, usize)>,
+}
+
+impl Document {
+    pub fn new(uri: String, syntax: Syntax, text: String) -> Self {
+        let mut r = Document {
+            uri,
+            syntax,
+            text,
+            lines: vec![],
+            forms: vec![],
+            reports: vec![],
+            brackets: vec![],
+        };
+        r.analyze();
+        r
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn forms(&self) -> &[(GAst, Range<usize>)] {
+        &self.forms
+    }
+
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    pub fn brackets(&self) -> &[(usize, usize)] {
+        &self.brackets
+    }
+
+    /// Replaces a range of the text, or all of it. The ends of an inverted
+    /// range are swapped.
+    pub fn edit(&mut self, range: Option<Range<Position>>, text: &str) {
+        match range {
+            Some(x) => {
+                let (start, end) = (self.offset(x.start), self.offset(x.end));
+                self.text
+                    .replace_range(start.min(end)..start.max(end), text);
+            }
+            None => self.text = text.to_string(),
+        }
+        self.analyze();
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&x| x <= offset) - 1;
+        let start = self.lines[line];
+        let end = offset.min(self.text.len());
+        Position {
+            line,
+            character: self.text[start..end].encode_utf16().count(),
+        }
+    }
+
+    /// The byte offset of a position, clamped to its line.
+    pub fn offset(&self, p: Position) -> usize {
+        let start = match self.lines.get(p.line) {
+            Some(&x) => x,
+            None => return self.text.len(),
+        };
+        let mut character = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if character >= p.character || c == '\n' || c == '\r' {
+                return start + i;
+            }
+            character += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    pub fn range(&self, span: &Range<usize>) -> Range<Position> {
+        self.position(span.start)..self.position(span.end)
+    }
+
+    fn analyze(&mut self) {
+        let path = Handle::new(self.uri.clone());
+        self.lines = std::iter::once(0)
+            .chain(self.text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.brackets = scan_brackets(&self.text, self.syntax);
+        let forms = match self.syntax {
+            Syntax::Sexpr => sexpr_forms(&self.text, path.clone()),
+            Syntax::Mexpr => mexpr_forms(&self.text, path.clone()).map_err(|e| vec![e]),
+        };
+        match forms {
+            Ok(x) => {
+                self.forms = x;
+                self.reports = vec![];
+            }
+            Err(e) => {
+                self.forms = vec![];
+                self.reports = e;
+            }
+        }
+    }
+
+    /// The symbol at a byte offset.
+    pub fn symbol_at(&self, offset: usize) -> Option<Handle<Symbol>> {
+        let mut found = None;
+        for (ast, span) in &self.forms {
+            if span.contains(&offset) {
+                each_symbol(ast, &mut |x| {
+                    let pos = x.1.pos;
+                    if pos <= offset
+                        && offset < pos + x.0.len()
+                        && self.text[pos..].starts_with(x.0.as_str())
+                    {
+                        found = Some(x.clone());
+                    }
+                });
+            }
+        }
+        found
+    }
+
+    /// The names `define`-like forms bind, anywhere in the document.
+    pub fn definitions(&self) -> Vec<Handle<Symbol>> {
+        let mut r = vec![];
+        for (ast, _) in &self.forms {
+            each_list(ast, &mut |list| {
+                if let Some(x) = defined_name(list) {
+                    r.push(x);
+                }
+            });
+        }
+        r
+    }
+
+    /// The bracket pair starting or ending at the offset, or right before it.
+    pub fn matching_bracket(&self, offset: usize) -> Option<(usize, usize)> {
+        let at = |offset| {
+            self.brackets
+                .iter()
+                .find(|&&(open, close)| open == offset || close == offset)
+        };
+        at(offset)
+            .or_else(|| offset.checked_sub(1).and_then(at))
+            .copied()
+    }
+
+    /// The text formatted in `style`, `None` if it does not parse.
+    pub fn format(&self, style: &Style) -> Option<String> {
+        let path = Handle::new(self.uri.clone());
+        match self.syntax {
+            Syntax::Sexpr => format_sexpr(&self.text, path, style).ok(),
+            Syntax::Mexpr => format_mexpr(&self.text, path, style).ok(),
+        }
+    }
+}
+
+fn sexpr_forms(text: &str, path: Handle<String>) -> Result<Vec<(GAst, Range<usize>)>, Vec<Report>> {
+    sexpr::parse_spanned(text, path.clone()).map_err(|e| {
+        let r = recover::parse_recovering(text, path.clone());
+        let reports: Vec<_> = r.diagnostics.iter().map(Report::from).collect();
+        if reports.is_empty() {
+            vec![Report::from(&e.with_path(&path))]
+        } else {
+            reports
+        }
+    })
+}
+
+fn mexpr_forms(text: &str, path: Handle<String>) -> Result<Vec<(GAst, Range<usize>)>, Report> {
+    mexpr::parse_spanned(text, path.clone()).map_err(|e| Report::from(&e.with_path(&path)))
+}
+
+/// Matches brackets outside of strings and comments. Unbalanced brackets
+/// are left out; the parser reports them.
+fn scan_brackets(text: &str, syntax: Syntax) -> Vec<(usize, usize)> {
+    let mut comments = vec![];
+    scan(text, syntax, |x| {
+        if let Token::Comment(x) = x {
+            comments.push(x);
+        }
+    });
+    let mut comments = comments.into_iter().peekable();
+    let bytes = text.as_bytes();
+    let mut r = vec![];
+    let mut stack: Vec<(usize, u8)> = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            _ if comments.peek().is_some_and(|x| x.start == i) => {
+                i = comments.next().unwrap().end;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'(' | b'[' | b'{' => stack.push((i, c)),
+            b')' | b']' | b'}' => {
+                let open = match c {
+                    b')' => b'(',
+                    b']' => b'[',
+                    _ => b'{',
+                };
+                if let Some(depth) = stack.iter().rposition(|x| x.1 == open) {
+                    r.push((stack[depth].0, i));
+                    stack.truncate(depth);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    r.sort();
+    r
+}
+
+/// The name a `(def... name ...)` or `(def... (name ...) ...)` list binds.
+pub fn defined_name(list: &[GAst]) -> Option<Handle<Symbol>> {
+    match list {
+        [GAst::Const(Constant::Sym(head)), name, ..] if head.0.starts_with("def") => match name {
+            GAst::Const(Constant::Sym(x)) => Some(x.clone()),
+            GAst::List(x) => match x.0.first() {
+                Some(GAst::Const(Constant::Sym(x))) => Some(x.clone()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn each_symbol(ast: &GAst, f: &mut impl FnMut(&Handle<Symbol>)) {
+    match ast {
+        GAst::Const(Constant::Sym(x)) => f(x),
+        GAst::Const(_) => {}
+        GAst::List(x) => {
+            x.0.iter().chain(x.1.iter()).for_each(|x| each_symbol(x, f));
+        }
+    }
+}
+
+fn each_list(ast: &GAst, f: &mut impl FnMut(&[GAst])) {
+    if let GAst::List(x) = ast {
+        f(&x.0);
+        x.0.iter().chain(x.1.iter()).for_each(|x| each_list(x, f));
+    }
+}
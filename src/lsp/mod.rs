@@ -0,0 +1,402 @@
+//! A language server for sexpr and mexpr files.
+//!
+//! `run` speaks JSON-RPC over a reader and a writer (stdin and stdout in
+//! `sexpr-lsp`), and `Server::handle` answers one decoded message, so the
+//! server can be driven without a client. It publishes the parser's
+//! diagnostics and provides document symbols for top-level forms, folding
+//! ranges for lists spanning several lines, formatting with
+//! `syntax::format` in the style of the closest `.sexprfmt` for `file:`
+//! URIs, go-to-definition for names bound by
+//! `def...` forms in any open document, and matching brackets as document
+//! highlights.
+
+pub mod document;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::diagnostics::Severity;
+use crate::gast::constant::Constant;
+use crate::gast::{GAst, Handle};
+use crate::syntax::format::{find_style, Style};
+use document::{defined_name, detect_syntax, Document, Position};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn document(&self, uri: &str) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    /// The process exit code once `exit` was received.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Handles a request or notification, returning the messages to send.
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = match msg["method"].as_str() {
+            Some(x) => x,
+            // a response to a request of ours; the server sends none
+            None => return vec![],
+        };
+        let params = &msg["params"];
+        let id = match msg.get("id") {
+            Some(x) => x.clone(),
+            None => return self.notification(method, params),
+        };
+        if self.shutdown {
+            return vec![error(id, INVALID_REQUEST, "shutting down")];
+        }
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 1 },
+                    "documentSymbolProvider": true,
+                    "foldingRangeProvider": true,
+                    "documentFormattingProvider": true,
+                    "definitionProvider": true,
+                    "documentHighlightProvider": true,
+                },
+                "serverInfo": { "name": "sexpr-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/documentSymbol" => self.with_document(params, document_symbols),
+            "textDocument/foldingRange" => self.with_document(params, folding_ranges),
+            "textDocument/formatting" => self.with_document(params, formatting),
+            "textDocument/documentHighlight" => self.with_document(params, highlights),
+            "textDocument/definition" => self.definition(params),
+            _ => return vec![error(id, METHOD_NOT_FOUND, method)],
+        };
+        match result {
+            Ok(x) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": x })],
+            Err(e) => vec![error(id, INVALID_PARAMS, &e)],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        match method {
+            "exit" => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            }
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                let syntax = detect_syntax(&uri, doc["languageId"].as_str());
+                let text = doc["text"].as_str().unwrap_or("").to_string();
+                let doc = Document::new(uri.clone(), syntax, text);
+                let r = publish(&doc);
+                self.documents.insert(uri, doc);
+                vec![r]
+            }
+            "textDocument/didChange" => {
+                let doc = match self.documents.get_mut(&uri) {
+                    Some(x) => x,
+                    None => return vec![],
+                };
+                let changes = params["contentChanges"]
+                    .as_array()
+                    .map_or(&[][..], Vec::as_slice);
+                for x in changes {
+                    let range = x
+                        .get("range")
+                        .map(|x| position(&x["start"])..position(&x["end"]));
+                    doc.edit(range, x["text"].as_str().unwrap_or(""));
+                }
+                vec![publish(doc)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn with_document(
+        &self,
+        params: &Value,
+        f: impl FnOnce(&Document, &Value) -> Value,
+    ) -> Result<Value, String> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(x) => Ok(f(x, params)),
+            None => Err(format!("unknown document `{}`", uri)),
+        }
+    }
+
+    /// Definitions of the symbol at the position, from the same document
+    /// first.
+    fn definition(&self, params: &Value) -> Result<Value, String> {
+        let name = self.with_document(params, |doc, params| {
+            let offset = doc.offset(position(&params["position"]));
+            match doc.symbol_at(offset) {
+                Some(x) => json!(x.0.as_str()),
+                None => Value::Null,
+            }
+        })?;
+        let name = match name.as_str() {
+            Some(x) => x,
+            None => return Ok(Value::Null),
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let mut documents: Vec<&Document> = self.documents.values().collect();
+        documents.sort_by_key(|x| (x.uri != uri, x.uri.clone()));
+        let mut r = vec![];
+        for doc in documents {
+            for x in doc.definitions() {
+                if x.0.as_str() == name {
+                    let span = x.1.pos..x.1.pos + x.0.len();
+                    r.push(json!({ "uri": doc.uri, "range": range(doc, &span) }));
+                }
+            }
+        }
+        Ok(Value::Array(r))
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn position(x: &Value) -> Position {
+    Position {
+        line: x["line"].as_u64().unwrap_or(0) as usize,
+        character: x["character"].as_u64().unwrap_or(0) as usize,
+    }
+}
+
+fn range(doc: &Document, span: &Range<usize>) -> Value {
+    let r = doc.range(span);
+    json!({
+        "start": { "line": r.start.line, "character": r.start.character },
+        "end": { "line": r.end.line, "character": r.end.character },
+    })
+}
+
+fn publish(doc: &Document) -> Value {
+    let diagnostics: Vec<_> = doc
+        .reports()
+        .iter()
+        .map(|x| {
+            let span = x
+                .labels
+                .iter()
+                .find(|x| x.primary)
+                .map_or(0..0, |x| x.span.clone());
+            let severity = match x.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+                Severity::Note => 3,
+                Severity::Help => 4,
+            };
+            json!({
+                "range": range(doc, &span),
+                "severity": severity,
+                "source": "sexpr",
+                "message": x.message,
+            })
+        })
+        .collect();
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": doc.uri, "diagnostics": diagnostics },
+    })
+}
+
+/// A `DocumentSymbol` for each top-level form, named after what a `def...`
+/// form binds or else after the head symbol.
+fn document_symbols(doc: &Document, _: &Value) -> Value {
+    let symbols: Vec<_> = doc
+        .forms()
+        .iter()
+        .map(|(ast, span)| {
+            let list = match ast {
+                GAst::List(x) => &x.0[..],
+                GAst::Const(_) => &[],
+            };
+            let head = match list.first() {
+                Some(GAst::Const(Constant::Sym(x))) => Some(x),
+                _ => None,
+            };
+            let (name, kind, selection) = match (defined_name(list), head) {
+                (Some(x), Some(head)) => {
+                    // 12: Function, 13: Variable
+                    let kind = if list[1].is_list() { 12 } else { 13 };
+                    let selection = x.1.pos..x.1.pos + x.0.len();
+                    (
+                        x.0.to_string(),
+                        json!(kind),
+                        (head.0.to_string(), selection),
+                    )
+                }
+                // 19: Object
+                (None, Some(head)) => {
+                    let selection = head.1.pos..head.1.pos + head.0.len();
+                    (head.0.to_string(), json!(19), (String::new(), selection))
+                }
+                // 15: String
+                _ => (
+                    short(&ast.to_string()),
+                    json!(15),
+                    (String::new(), span.clone()),
+                ),
+            };
+            let (detail, selection) = selection;
+            json!({
+                "name": name,
+                "detail": detail,
+                "kind": kind,
+                "range": range(doc, span),
+                "selectionRange": range(doc, &selection),
+            })
+        })
+        .collect();
+    Value::Array(symbols)
+}
+
+/// The text, cut to 40 chars.
+fn short(s: &str) -> String {
+    if s.chars().count() > 40 {
+        format!("{}...", s.chars().take(37).collect::<String>())
+    } else {
+        s.to_string()
+    }
+}
+
+fn folding_ranges(doc: &Document, _: &Value) -> Value {
+    let ranges: Vec<_> = doc
+        .brackets()
+        .iter()
+        .map(|&(open, close)| (doc.position(open).line, doc.position(close).line))
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| json!({ "startLine": start, "endLine": end }))
+        .collect();
+    Value::Array(ranges)
+}
+
+/// One edit replacing the whole text, none if it is already formatted.
+fn formatting(doc: &Document, _: &Value) -> Value {
+    match doc.format(&style(&doc.uri)) {
+        Some(x) if x == doc.text() => json!([]),
+        Some(x) => json!([{ "range": range(doc, &(0..doc.text().len())), "newText": x }]),
+        None => Value::Null,
+    }
+}
+
+/// The style of the closest `.sexprfmt` to a `file:` URI (without percent
+/// escapes), the default one if there is none or it does not parse.
+fn style(uri: &str) -> Style {
+    let style = uri
+        .strip_prefix("file://")
+        .and_then(|x| find_style(Path::new(x)))
+        .and_then(|x| Some((std::fs::read_to_string(&x).ok()?, x)))
+        .and_then(|(text, path)| {
+            let path = Handle::new(path.to_string_lossy().into_owned());
+            Style::parse(&text, path).ok()
+        });
+    style.unwrap_or_default()
+}
+
+/// The bracket under or before the cursor and its match.
+fn highlights(doc: &Document, params: &Value) -> Value {
+    let offset = doc.offset(position(&params["position"]));
+    match doc.matching_bracket(offset) {
+        Some((open, close)) => json!([
+            { "range": range(doc, &(open..open + 1)), "kind": 1 },
+            { "range": range(doc, &(close..close + 1)), "kind": 1 },
+        ]),
+        None => Value::Null,
+    }
+}
+
+/// Reads the body of one `Content-Length` framed message, `None` at the end
+/// of input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(x) = line.strip_prefix("Content-Length:") {
+            length = x.trim().parse::<usize>().ok();
+        }
+    }
+    let length = match length {
+        Some(x) => x,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing Content-Length",
+            ))
+        }
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serves until `exit` or the end of input, returning the exit code.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match serde_json::from_str(&body) {
+            Ok(msg) => server.handle(&msg),
+            Err(e) => vec![error(Value::Null, PARSE_ERROR, &e.to_string())],
+        };
+        for x in replies {
+            write_message(&mut output, &x)?;
+        }
+        if let Some(x) = server.exit_code() {
+            return Ok(x);
+        }
+    }
+    Ok(1)
+}
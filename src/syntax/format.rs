@@ -0,0 +1,213 @@
+//! Formatting whole files.
+//!
+//! Every top-level form is pretty printed on its own lines. The comments
+//! between forms are kept, a comment after a form stays on its last line
+//! and runs of blank lines shrink to one. Forms with comments inside, like
+//! mexpr lists or a sexpr comment after the open bracket of a top-level
+//! list, are copied as they are, since the tree has no place for them.
+//!
+//! A `Style` is read from a `.sexprfmt` file of sexpr forms:
+//!
+//! ```text
+//! (width 100)     ; line width, 80 by default
+//! (indent 2)      ; columns past the bracket of broken lists, 2 by default
+//! (keep define 2) ; arguments kept next to the head symbol `define`
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::{error_location, mexpr, scan, sexpr, Syntax, Token};
+use crate::gast::constant::Constant;
+use crate::gast::symbol::Location;
+use crate::gast::{GAst, Handle};
+
+/// Name of the style file `find_style` looks for.
+pub const STYLE_FILE: &str = ".sexprfmt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    /// line width lists are broken to fit
+    pub width: usize,
+    /// columns past the bracket of the elements of a broken list
+    pub indent: usize,
+    /// arguments kept on the first line of a broken list, by head symbol
+    pub keep: HashMap<String, usize>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            width: 80,
+            indent: 2,
+            keep: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleError {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Display for StyleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(x) => write!(f, "{} at {}", self.message, x),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for StyleError {}
+
+impl Style {
+    pub fn keep(&self, head: &str) -> Option<usize> {
+        self.keep.get(head).copied()
+    }
+
+    pub fn parse(input: &str, path: Handle<String>) -> Result<Self, StyleError> {
+        let forms = sexpr::parse(input, path.clone()).map_err(|e| StyleError {
+            message: e.variant.message().to_string(),
+            location: Some(error_location(&e, path)),
+        })?;
+        Self::from_forms(&forms)
+    }
+
+    pub fn from_forms(forms: &[GAst]) -> Result<Self, StyleError> {
+        let mut r = Style::default();
+        for form in forms {
+            let list = form.get_list();
+            let items = list.as_ref().map_or(&[][..], |x| &x.0[..]);
+            let sym = |i: usize| match items.get(i) {
+                Some(GAst::Const(Constant::Sym(x))) => Some(x.clone()),
+                _ => None,
+            };
+            let head = sym(0).ok_or_else(|| StyleError {
+                message: format!(
+                    "expected a `(width n)`, `(indent n)` or `(keep head n)` form, found `{}`",
+                    form
+                ),
+                location: None,
+            })?;
+            let error = |message: &str| StyleError {
+                message: format!("{} in `{}`", message, form),
+                location: Some(head.1.clone()),
+            };
+            let number = |i: usize| match items.get(i) {
+                Some(GAst::Const(Constant::Int(x))) if *x >= 0 && i + 1 == items.len() => {
+                    Ok(*x as usize)
+                }
+                Some(GAst::Const(Constant::Uint(x))) if i + 1 == items.len() => Ok(*x as usize),
+                _ => Err(error("expected a number as the last element")),
+            };
+            match head.0.as_str() {
+                "width" => r.width = number(1)?,
+                "indent" => r.indent = number(1)?,
+                "keep" => {
+                    let name = sym(1).ok_or_else(|| error("expected a head symbol"))?;
+                    r.keep.insert(name.0.to_string(), number(2)?);
+                }
+                _ => return Err(error("unknown setting")),
+            }
+        }
+        Ok(r)
+    }
+}
+
+/// The closest `.sexprfmt` in the directory of `path` or above.
+pub fn find_style(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    path.ancestors()
+        .skip(1)
+        .map(|x| x.join(STYLE_FILE))
+        .find(|x| x.is_file())
+}
+
+pub fn format_sexpr(
+    input: &str,
+    path: Handle<String>,
+    style: &Style,
+) -> Result<String, sexpr::ParseError> {
+    let forms = sexpr::parse_spanned(input, path)?;
+    Ok(layout(input, &forms, Syntax::Sexpr, |ast, out| {
+        sexpr::printer::write_pretty(ast, 0, style, out)
+    }))
+}
+
+pub fn format_mexpr(
+    input: &str,
+    path: Handle<String>,
+    style: &Style,
+) -> Result<String, mexpr::ParseError> {
+    let forms = mexpr::parse_spanned(input, path)?;
+    Ok(layout(input, &forms, Syntax::Mexpr, |ast, out| {
+        mexpr::printer::write_pretty(ast, 0, style, out)
+    }))
+}
+
+fn layout(
+    input: &str,
+    forms: &[(GAst, Range<usize>)],
+    syntax: Syntax,
+    print: impl Fn(&GAst, &mut String),
+) -> String {
+    let mut out = String::new();
+    let mut end = None;
+    for (ast, span) in forms {
+        let gap = &input[end.unwrap_or(0)..span.start];
+        write_gap(gap, end.is_none(), true, &mut out);
+        let text = &input[span.clone()];
+        let mut commented = false;
+        scan(text, syntax, |x| {
+            commented |= matches!(x, Token::Comment(_))
+        });
+        if commented {
+            out.push_str(text);
+        } else {
+            print(ast, &mut out);
+        }
+        end = Some(span.end);
+    }
+    write_gap(&input[end.unwrap_or(0)..], end.is_none(), false, &mut out);
+    out
+}
+
+/// Copies the comments of the text between two top-level forms, ending the
+/// line of the previous form (unless `first`) and keeping at most one blank
+/// line. `before_form` if a form follows.
+fn write_gap(gap: &str, first: bool, before_form: bool, out: &mut String) {
+    let mut lines: Vec<&str> = gap.split('\n').map(str::trim).collect();
+    // the text before the next form or a comment closing the file
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    let mut lines = lines.into_iter();
+    if !first {
+        let trailing = lines.next().unwrap_or("");
+        if !trailing.is_empty() {
+            out.push(' ');
+            out.push_str(trailing);
+        }
+        out.push('\n');
+    }
+    let mut blank = false;
+    for line in lines {
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if blank && before_form {
+        out.push('\n');
+    }
+}
@@ -1,15 +1,20 @@
+pub mod printer;
+
+use std::ops::Range;
 use std::{fs::File, io::Read};
 
-use pest::error::Error;
+use pest::error::{Error, ErrorVariant};
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::*;
 
+use crate::error::CompilerError;
 use crate::gast::constant::Constant;
 use crate::gast::list::List;
 use crate::gast::symbol::{Location, Symbol};
 use crate::gast::*;
-use crate::{error::CompilerError, utils::escape_str};
+use crate::syntax::{unit_result, Balance, Syntax, UnitError};
+use crate::utils::{escape_str, parse_float_lit, parse_int_lit, parse_uint_lit};
 
 #[derive(Parser)]
 #[grammar = "./syntax/mexpr/grammar.pest"]
@@ -36,7 +41,7 @@ impl ParseFrom<Rule> for GAst {
             let mut last = parse_list(last, path);
             let mut r = [first].to_vec();
             r.append(&mut last);
-            GAst::List(Handle::new(List(r, None)))
+            GAst::List(Handle::new(List::new(r, None)))
         } else {
             first
         }
@@ -71,7 +76,7 @@ fn parse_quote(pair: Pair<Rule>, path: Handle<String>) -> GAst {
     let first = GAst::Const(Constant::Sym(Handle::new(first)));
     let mut first = [first].to_vec();
     first.append(&mut r);
-    GAst::List(Handle::new(List(first, None)))
+    GAst::List(Handle::new(List::new(first, None)))
 }
 
 #[inline]
@@ -94,9 +99,9 @@ impl ParseFrom<Rule> for Constant {
             Rule::string_lit => Self::Str(Handle::new(escape_str(
                 &pair.as_str()[1..pair.as_str().len() - 1],
             ))),
-            Rule::uint_lit => Self::Uint(pair.as_str().parse().unwrap()),
-            Rule::int_lit => Self::Int(pair.as_str().parse().unwrap()),
-            Rule::float_lit => Self::Float(pair.as_str().parse().unwrap()),
+            Rule::uint_lit => Self::Uint(parse_uint_lit(pair.as_str()).unwrap()),
+            Rule::int_lit => Self::Int(parse_int_lit(pair.as_str()).unwrap()),
+            Rule::float_lit => Self::Float(parse_float_lit(pair.as_str()).unwrap()),
             Rule::bool_lit => Self::Bool(pair.as_str().parse().unwrap()),
             // Rule::char_lit => Self::Char(str2char(&escape_str(pair.as_str()))),
             Rule::nil_lit => Self::Nil,
@@ -125,39 +130,90 @@ pub fn parse_unit(pair: Pair<Rule>, path: Handle<String>) -> Vec<GAst> {
         .collect()
 }
 
+/// `Cement::parse` also rejecting the number literals the grammar accepts
+/// but that are out of range, which `ParseFrom` cannot convert.
+fn parse_pairs(rule: Rule, input: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    let pairs = Cement::parse(rule, input)?;
+    for pair in pairs.clone().flatten() {
+        let message = match pair.as_rule() {
+            Rule::uint_lit if parse_uint_lit(pair.as_str()).is_none() => {
+                "integer literal out of range"
+            }
+            Rule::int_lit if parse_int_lit(pair.as_str()).is_none() => {
+                "integer literal out of range"
+            }
+            Rule::float_lit if parse_float_lit(pair.as_str()).is_none() => "invalid float literal",
+            _ => continue,
+        };
+        let variant = ErrorVariant::CustomError {
+            message: message.to_owned(),
+        };
+        return Err(Error::new_from_span(variant, pair.as_span()));
+    }
+    Ok(pairs)
+}
+
 pub fn parse(input: &str, path: Handle<String>) -> Result<Vec<GAst>, ParseError> {
-    let pairs: Pairs<Rule> = Cement::parse(Rule::unit, input)?;
+    let pairs: Pairs<Rule> = parse_pairs(Rule::unit, input)?;
     let result = pairs.flat_map(|x| parse_unit(x, path.clone())).collect();
     Ok(result)
 }
 
+/// `parse` also returning the byte range of each datum.
+pub fn parse_spanned(
+    input: &str,
+    path: Handle<String>,
+) -> Result<Vec<(GAst, Range<usize>)>, ParseError> {
+    let unit = parse_pairs(Rule::unit, input)?.next().unwrap();
+    let forms = unit.into_inner().filter(|x| x.as_rule() == Rule::sexpr);
+    Ok(forms
+        .map(|x| {
+            let span = x.as_span().start()..x.as_span().end();
+            (GAst::parse_from(x, path.clone()), span)
+        })
+        .collect())
+}
+
 pub fn file_parse(path: &str) -> Result<Vec<GAst>, CompilerError<ParseError>> {
     use std::path::PathBuf;
-    let path_buf = PathBuf::from(path).canonicalize().unwrap();
-    let file_path = Handle::new(Symbol::new(path_buf.to_str().unwrap()));
-    let mut f = if let Ok(f) = File::open(path_buf) {
-        f
-    } else {
-        return Err(CompilerError::FileOpenError(file_path.0.clone()));
+    let path_buf = match PathBuf::from(path).canonicalize() {
+        Ok(x) => x,
+        Err(e) => {
+            let path = Handle::new(path.to_owned());
+            return Err(CompilerError::FileOpenError(path, e));
+        }
     };
+    let file_path = Handle::new(path_buf.to_string_lossy().into_owned());
     let mut buf = String::new();
-    if f.read_to_string(&mut buf).is_err() {
-        return Err(CompilerError::FileOpenError(file_path.0.clone()));
+    if let Err(e) = File::open(path_buf).and_then(|mut f| f.read_to_string(&mut buf)) {
+        return Err(CompilerError::FileOpenError(file_path, e));
     }
-    parse(&buf, file_path.0.clone()).map_err(CompilerError::ParseError)
+    parse(&buf, file_path).map_err(CompilerError::ParseError)
 }
 
-pub fn repl_parse(input: &str) -> Result<GAst, ParseError> {
+/// `file_parse` reading the file through a memory map, validating and
+/// parsing it a chunk at a time.
+#[cfg(feature = "mmap")]
+pub fn file_parse_mmap(path: &str) -> Result<Vec<GAst>, CompilerError<ParseError>> {
+    crate::syntax::mmap::with_mapped_file(path, Syntax::Mexpr, parse)
+}
+
+pub fn repl_parse(input: &str) -> Result<GAst, UnitError<ParseError>> {
     one_unit_parse(input, "<stdin>")
 }
 
-pub fn one_unit_parse(input: &str, path: &str) -> Result<GAst, ParseError> {
+/// Parses one datum; `UnitError::Incomplete` if the input could still be
+/// completed.
+pub fn one_unit_parse(input: &str, path: &str) -> Result<GAst, UnitError<ParseError>> {
     let path = Handle::new(Symbol::new(path));
-    let pair = Cement::parse(Rule::repl_unit, input)?
-        .next()
-        .unwrap()
-        .into_inner()
-        .next()
-        .unwrap();
-    Ok(GAst::parse_from(pair, path.0.clone()))
+    let r = parse_pairs(Rule::repl_unit, input).map(|mut x| {
+        let pair = x.next().unwrap().into_inner().next().unwrap();
+        GAst::parse_from(pair, path.0.clone())
+    });
+    unit_result(input, r, balance(input))
+}
+
+/// The open brackets and string at the end of a partial buffer.
+pub fn balance(input: &str) -> Balance {
+    crate::syntax::balance(input, Syntax::Mexpr)
 }
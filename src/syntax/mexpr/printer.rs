@@ -0,0 +1,166 @@
+//! Printing trees as mexpr source text.
+//!
+//! A list `(f a b)` prints as the call `f(a, b)`, and the lists the parser
+//! makes of bare brackets, `(tuple ...)`, `(list ...)` and `(array ...)`,
+//! print as `(...)`, `[...]` and `{...}`. What the grammar cannot express
+//! does not read back the same: empty and dotted lists, lists headed by a
+//! call, uints (read back as ints), chars (printed as one-char strings) and
+//! non-finite floats.
+
+use crate::gast::constant::Constant;
+use crate::gast::list::List;
+use crate::gast::GAst;
+use crate::syntax::format::Style;
+use crate::syntax::sexpr::printer::{column, constant_width, write_constant};
+
+/// How a list prints: its head, brackets, separator and arguments.
+struct Shape<'a> {
+    head: Option<&'a GAst>,
+    open: char,
+    separator: char,
+    close: char,
+    args: &'a [GAst],
+}
+
+fn shape(list: &List) -> Shape<'_> {
+    let bracket = |open, separator, close| Shape {
+        head: None,
+        open,
+        separator,
+        close,
+        args: &list.0[1..],
+    };
+    match list.0.first() {
+        None => Shape {
+            head: None,
+            open: '(',
+            separator: ',',
+            close: ')',
+            args: &[],
+        },
+        Some(GAst::Const(Constant::Sym(x))) if x.0.as_str() == "tuple" => bracket('(', ',', ')'),
+        Some(GAst::Const(Constant::Sym(x))) if x.0.as_str() == "list" => bracket('[', ';', ']'),
+        Some(GAst::Const(Constant::Sym(x))) if x.0.as_str() == "array" => bracket('{', ',', '}'),
+        Some(head) => Shape {
+            head: Some(head),
+            ..bracket('(', ',', ')')
+        },
+    }
+}
+
+pub fn to_source(ast: &GAst) -> String {
+    let mut out = String::new();
+    write_source(ast, &mut out);
+    out
+}
+
+pub fn write_source(ast: &GAst, out: &mut String) {
+    let list = match ast {
+        GAst::Const(Constant::Uint(x)) => return out.push_str(&x.to_string()),
+        GAst::Const(c) => return write_constant(c, out),
+        GAst::List(x) => x,
+    };
+    let shape = shape(list);
+    if let Some(x) = shape.head {
+        write_source(x, out);
+    }
+    out.push(shape.open);
+    for (i, x) in shape.args.iter().enumerate() {
+        if i != 0 {
+            out.push(shape.separator);
+            out.push(' ');
+        }
+        write_source(x, out);
+    }
+    if let Some(x) = &list.1 {
+        out.push_str(" . ");
+        write_source(x, out);
+    }
+    out.push(shape.close);
+}
+
+/// The width of `write_source(ast)` if it is at most `limit`, looking at no
+/// more of the tree than fits.
+fn flat_width(ast: &GAst, limit: usize) -> Option<usize> {
+    let list = match ast {
+        GAst::List(x) => x,
+        GAst::Const(c) => {
+            let width = match c {
+                Constant::Uint(x) => x.to_string().len(),
+                c => constant_width(c, limit),
+            };
+            return Some(width).filter(|&x| x <= limit);
+        }
+    };
+    let shape = shape(list);
+    let separators = 2 * shape.args.len().saturating_sub(1) + if list.1.is_some() { 3 } else { 0 };
+    let mut width = 2 + separators;
+    let children = shape
+        .head
+        .into_iter()
+        .chain(shape.args)
+        .chain(list.1.iter());
+    for x in children {
+        width += flat_width(x, limit.checked_sub(width)?)?;
+    }
+    Some(width).filter(|&x| x <= limit)
+}
+
+pub fn to_pretty(ast: &GAst, style: &Style) -> String {
+    let mut out = String::new();
+    write_pretty(ast, 0, style, &mut out);
+    out
+}
+
+/// Prints `ast` starting at column `indent`. A list that does not fit the
+/// line keeps as many arguments as `style.keep` says for its head (none by
+/// default) after the open bracket, puts the others on their own lines
+/// `style.indent` columns further in and the close bracket on a line of
+/// its own.
+pub fn write_pretty(ast: &GAst, indent: usize, style: &Style, out: &mut String) {
+    let list = match ast {
+        GAst::List(x) if flat_width(ast, style.width.saturating_sub(indent)).is_none() => x,
+        _ => return write_source(ast, out),
+    };
+    let shape = shape(list);
+    let start = out.len();
+    let keep = match shape.head {
+        Some(x) => {
+            write_pretty(x, indent, style, out);
+            match x {
+                GAst::Const(Constant::Sym(x)) => style.keep(&x.0).unwrap_or(0),
+                _ => 0,
+            }
+        }
+        None => 0,
+    };
+    out.push(shape.open);
+    let keep = keep.min(shape.args.len());
+    for (i, x) in shape.args[..keep].iter().enumerate() {
+        if i != 0 {
+            out.push(shape.separator);
+            out.push(' ');
+        }
+        let column = column(out, start, indent);
+        write_pretty(x, column, style, out);
+    }
+    let inner = indent + style.indent;
+    for (i, x) in shape.args[keep..].iter().enumerate() {
+        if i != 0 || keep != 0 {
+            out.push(shape.separator);
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(inner));
+        write_pretty(x, inner, style, out);
+    }
+    if let Some(x) = &list.1 {
+        out.push_str(" . ");
+        let column = column(out, start, indent);
+        write_pretty(x, column, style, out);
+    }
+    if keep < shape.args.len() {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+    }
+    out.push(shape.close);
+}
@@ -0,0 +1,168 @@
+//! Memory-mapped input files.
+
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use memmap2::Mmap;
+
+use super::{scan, Balance, Syntax, Token};
+use crate::error::CompilerError;
+use crate::gast::constant::Constant;
+use crate::gast::{GAst, Handle};
+
+/// Bytes validated and parsed at a time, give or take a form.
+const CHUNK: usize = 1 << 20;
+
+/// Maps the file at `path` and parses it with `parse`, a chunk of whole
+/// forms at a time; see `parse_chunks`. The file must not be truncated or
+/// rewritten while it is mapped.
+pub(crate) fn with_mapped_file<E>(
+    path: &str,
+    syntax: Syntax,
+    parse: impl Fn(&str, Handle<String>) -> Result<Vec<GAst>, E>,
+) -> Result<Vec<GAst>, CompilerError<E>> {
+    let path_buf = match PathBuf::from(path).canonicalize() {
+        Ok(x) => x,
+        Err(e) => {
+            let path = Handle::new(path.to_owned());
+            return Err(CompilerError::FileOpenError(path, e));
+        }
+    };
+    let file_path = Handle::new(path_buf.to_string_lossy().into_owned());
+    let map = File::open(&path_buf).and_then(|f| unsafe { Mmap::map(&f) });
+    let map = match map {
+        Ok(x) => x,
+        Err(e) => return Err(CompilerError::FileOpenError(file_path, e)),
+    };
+    parse_chunks(&map, syntax, CHUNK, file_path, parse)
+}
+
+/// Parses `bytes` in chunks of about `chunk` bytes of whole lines and forms.
+/// Each chunk is validated as UTF-8 right before it is parsed, so invalid
+/// bytes are only found once the parser gets to them. The locations of a
+/// chunk are moved to where it starts; a parse error is that of the text
+/// up to the end of its chunk.
+pub(crate) fn parse_chunks<E>(
+    bytes: &[u8],
+    syntax: Syntax,
+    chunk: usize,
+    path: Handle<String>,
+    parse: impl Fn(&str, Handle<String>) -> Result<Vec<GAst>, E>,
+) -> Result<Vec<GAst>, CompilerError<E>> {
+    let invalid = |pos: usize| {
+        let message = format!("invalid UTF-8 at byte {}", pos);
+        let e = io::Error::new(io::ErrorKind::InvalidData, message);
+        CompilerError::FileOpenError(path.clone(), e)
+    };
+    let mut forms = vec![];
+    let mut start = 0;
+    let mut lines = 0;
+    while start < bytes.len() {
+        let mut size = chunk.max(1);
+        let text = loop {
+            let end = line_end(bytes, start + size);
+            let text = std::str::from_utf8(&bytes[start..end])
+                .map_err(|e| invalid(start + e.valid_up_to()))?;
+            if end == bytes.len() || ends_forms(text, &bytes[end..], syntax) {
+                break text;
+            }
+            size *= 2;
+        };
+        let end = start + text.len();
+        let mut chunk_forms = match parse(text, path.clone()) {
+            Ok(x) => x,
+            Err(e) => {
+                // the chunks before parse, so the whole text fails the same
+                let whole = std::str::from_utf8(&bytes[..end]).unwrap();
+                let e = parse(whole, path.clone()).err().unwrap_or(e);
+                return Err(CompilerError::ParseError(e));
+            }
+        };
+        if start > 0 {
+            chunk_forms
+                .iter_mut()
+                .for_each(|x| relocate(x, lines, start));
+        }
+        forms.extend(chunk_forms);
+        lines += text.matches('\n').count();
+        start = end;
+    }
+    Ok(forms)
+}
+
+/// The offset after the first newline at or after `pos`, or the length.
+fn line_end(bytes: &[u8], pos: usize) -> usize {
+    let pos = pos.min(bytes.len());
+    bytes[pos..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |x| pos + x + 1)
+}
+
+/// Whether `text` holds whole forms that `rest` cannot continue.
+fn ends_forms(text: &str, rest: &[u8], syntax: Syntax) -> bool {
+    let mut comments = vec![];
+    let balance = scan(text, syntax, |x| {
+        if let Token::Comment(x) = x {
+            comments.push(x);
+        }
+    });
+    if balance != Balance::default() {
+        return false;
+    }
+    match syntax {
+        // a quote prefix takes the datum after it
+        Syntax::Sexpr => {
+            let mut end = text.len();
+            let mut comments = comments.iter().rev().peekable();
+            loop {
+                end = text[..end].trim_end().len();
+                match comments.next_if(|x| x.start < end && end <= x.end) {
+                    Some(x) => end = x.start,
+                    None => break,
+                }
+            }
+            let text = &text[..end];
+            match text.chars().next_back() {
+                Some('\'') | Some('`') | Some(',') => false,
+                Some('@') => !text[..end - 1].ends_with(','),
+                _ => true,
+            }
+        }
+        // a bracket after a datum, even on another line, applies it
+        Syntax::Mexpr => {
+            let mut rest = rest;
+            loop {
+                let next = rest.iter().position(|b| !b.is_ascii_whitespace());
+                match next.map(|x| (x, rest[x])) {
+                    None => return true,
+                    Some((x, b'#')) => match rest[x..].iter().position(|&b| b == b'\n') {
+                        Some(end) => rest = &rest[x + end..],
+                        None => return true,
+                    },
+                    Some((_, b)) => return b.is_ascii() && !b"([{".contains(&b),
+                }
+            }
+        }
+    }
+}
+
+/// Moves the locations of a tree parsed from a chunk starting at byte
+/// `start`, after `lines` lines.
+fn relocate(ast: &mut GAst, lines: usize, start: usize) {
+    match ast {
+        GAst::Const(Constant::Sym(x)) => {
+            let x = Handle::make_mut(x);
+            x.1.line += lines;
+            x.1.pos += start;
+        }
+        GAst::Const(_) => {}
+        GAst::List(x) => {
+            let x = Handle::make_mut(x);
+            for x in x.0.iter_mut().chain(x.1.iter_mut()) {
+                relocate(x, lines, start);
+            }
+        }
+    }
+}
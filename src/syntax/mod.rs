@@ -1,2 +1,231 @@
+pub mod format;
 pub mod mexpr;
+#[cfg(feature = "mmap")]
+pub(crate) mod mmap;
 pub mod sexpr;
+
+use std::fmt::Display;
+use std::ops::Range;
+
+use pest::error::{Error, InputLocation, LineColLocation};
+use pest::RuleType;
+
+use crate::gast::symbol::Location;
+use crate::gast::Handle;
+
+/// The surface syntaxes a file can be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Sexpr,
+    Mexpr,
+}
+
+impl Syntax {
+    /// mexpr for `.mexpr` files, sexpr otherwise.
+    pub fn of_path(path: &str) -> Self {
+        if path.ends_with(".mexpr") {
+            Syntax::Mexpr
+        } else {
+            Syntax::Sexpr
+        }
+    }
+
+    /// The character starting line comments.
+    pub fn comment(self) -> char {
+        match self {
+            Syntax::Sexpr => ';',
+            Syntax::Mexpr => '#',
+        }
+    }
+}
+
+/// How a partial buffer ends: what it takes to close its open brackets and
+/// string.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Balance {
+    /// closing brackets of the lists still open, innermost first
+    pub closers: Vec<char>,
+    /// offset of the `"` opening the string literal the buffer ends in
+    pub unterminated_string: Option<usize>,
+    /// byte offset of the first closing bracket that closes nothing or a
+    /// different bracket
+    pub unmatched: Option<usize>,
+}
+
+impl Balance {
+    pub fn depth(&self) -> usize {
+        self.closers.len()
+    }
+
+    /// Whether more input could complete the buffer.
+    pub fn is_open(&self) -> bool {
+        self.unmatched.is_none() && (self.unterminated_string.is_some() || !self.closers.is_empty())
+    }
+
+    /// What has to follow, in order: `"` for an open string, then the
+    /// closing brackets.
+    pub fn expected_closers(&self) -> Vec<char> {
+        let quote = self.unterminated_string.map(|_| '"');
+        quote
+            .into_iter()
+            .chain(self.closers.iter().copied())
+            .collect()
+    }
+}
+
+/// What `scan` finds besides the balance.
+pub(crate) enum Token {
+    Comment(Range<usize>),
+    /// offset of an open bracket that is closed
+    Open(usize),
+}
+
+/// Scans brackets, strings and line comments. Comments only start where a
+/// token may. In sexpr they only start outside of lists or right after the
+/// open bracket of an outermost list: the grammar skips comments only
+/// outside of `list_core`.
+pub(crate) fn balance(input: &str, syntax: Syntax) -> Balance {
+    scan(input, syntax, |_| {})
+}
+
+/// `balance` also passing comments and closed brackets to `on_token`.
+pub(crate) fn scan(input: &str, syntax: Syntax, mut on_token: impl FnMut(Token)) -> Balance {
+    let comment = syntax.comment();
+    let mut opens = vec![];
+    let mut r = Balance::default();
+    let mut chars = input.char_indices();
+    let mut token_start = true;
+    // whether a sexpr comment may start here
+    let mut skipping = true;
+    while let Some((i, c)) = chars.next() {
+        let at_token = token_start;
+        token_start = c.is_whitespace() || "()[]{},;".contains(c);
+        let was_skipping = skipping;
+        if syntax == Syntax::Sexpr && !c.is_whitespace() && c != comment {
+            // a quote prefix ends a symbol
+            token_start |= "'`".contains(c) || c == '@' && input[..i].ends_with(',');
+            skipping = r.closers.is_empty() && "([{".contains(c);
+        }
+        match c {
+            '"' => loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => {
+                        chars.next();
+                    }
+                    Some(_) => {}
+                    None => {
+                        r.unterminated_string = Some(i);
+                        return r;
+                    }
+                }
+            },
+            // elsewhere `;` inside a sexpr list is part of a symbol
+            c if c == comment && at_token && (syntax == Syntax::Mexpr || was_skipping) => {
+                let end = match chars.by_ref().find(|&(_, c)| c == '\n') {
+                    Some((x, _)) => x,
+                    None => input.len(),
+                };
+                on_token(Token::Comment(i..end));
+                token_start = true;
+            }
+            '(' | '[' | '{' => {
+                let close = match c {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                r.closers.insert(0, close);
+                opens.push(i);
+            }
+            ')' | ']' | '}' => {
+                if r.closers.first() == Some(&c) {
+                    r.closers.remove(0);
+                    on_token(Token::Open(opens.pop().unwrap()));
+                } else if r.unmatched.is_none() {
+                    r.unmatched = Some(i);
+                }
+            }
+            _ => {}
+        }
+        skipping |= r.closers.is_empty();
+    }
+    r
+}
+
+/// Where a parse error starts.
+pub(crate) fn error_location<R: RuleType>(e: &Error<R>, path: Handle<String>) -> Location {
+    let (line, colum) = match e.line_col {
+        LineColLocation::Pos(x) => x,
+        LineColLocation::Span(x, _) => x,
+    };
+    let pos = match e.location {
+        InputLocation::Pos(x) => x,
+        InputLocation::Span((x, _)) => x,
+    };
+    Location::new(path, line, colum, pos)
+}
+
+/// Why a single datum did not parse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitError<E> {
+    /// the input ends inside a list or string, or has no datum yet
+    Incomplete {
+        expected_closers: Vec<char>,
+    },
+    Syntax(E),
+}
+
+impl<E: Display> Display for UnitError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Incomplete { expected_closers } if expected_closers.is_empty() => {
+                f.write_str("needs more input")
+            }
+            Self::Incomplete { expected_closers } => {
+                let closers: String = expected_closers.iter().collect();
+                write!(f, "needs more input, expected `{}`", closers)
+            }
+            Self::Syntax(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for UnitError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Incomplete { .. } => None,
+            Self::Syntax(e) => Some(e),
+        }
+    }
+}
+
+/// Turns an error the parser hit at the end of `input` into `Incomplete`
+/// if more input could fix it.
+pub(crate) fn unit_result<T, R: RuleType>(
+    input: &str,
+    result: Result<T, Error<R>>,
+    balance: Balance,
+) -> Result<T, UnitError<Error<R>>> {
+    let e = match result {
+        Ok(x) => return Ok(x),
+        Err(e) => e,
+    };
+    let pos = match e.location {
+        InputLocation::Pos(x) => x,
+        InputLocation::Span((x, _)) => x,
+    };
+    // the parser gives up on an open string where it starts
+    let end = balance
+        .unterminated_string
+        .unwrap_or_else(|| input.trim_end().len());
+    let at_end = pos >= end;
+    let empty = balance == Balance::default() && at_end;
+    if at_end && balance.is_open() || empty {
+        Err(UnitError::Incomplete {
+            expected_closers: balance.expected_closers(),
+        })
+    } else {
+        Err(UnitError::Syntax(e))
+    }
+}
@@ -0,0 +1,830 @@
+//! Hand-written recursive-descent parser for the sexpr grammar.
+//!
+//! It follows `grammar.pest` rule by rule (ordered choices, the atomic
+//! `list_core`, implicit whitespace and comments outside of it), so it
+//! accepts exactly the same language, but it scans the input bytes in place
+//! and builds the tree directly instead of going through `Pairs`.
+//! Select it as the default `parse` with the `fast-parser` feature.
+//!
+//! What the tree is made of is up to a `Builder`: `GAstBuilder` makes `GAst`,
+//! `RefBuilder` makes `GAstRef` borrowing from the input and `ArenaBuilder`
+//! fills a `GAstArena`.
+
+use std::borrow::Cow;
+use std::cell::Cell;
+
+use pest::error::{Error, ErrorVariant, InputLocation, LineColLocation};
+use pest::Position;
+
+use super::recover::{Diagnostic, DiagnosticKind, Recovered};
+use super::{ParseError, Rule};
+use crate::gast::arena::{ArenaLocation, ArenaNode, GAstArena, NodeId};
+use crate::gast::borrowed::{ConstantRef, GAstRef, ListRef, SymbolRef};
+use crate::gast::constant::Constant;
+use crate::gast::diff::Step;
+use crate::gast::list::List;
+use crate::gast::symbol::{Location, Symbol};
+use crate::gast::*;
+use crate::utils::{escape_char, parse_float_lit, parse_int_lit, parse_uint_lit};
+
+type PResult<T> = Result<Option<(T, usize)>, ParseError>;
+
+const QUOTES: [(&str, &str); 4] = [
+    ("'", "quote"),
+    ("`", "quasiquote"),
+    (",@", "unquote-splicing"),
+    (",", "unquote"),
+];
+
+const KEYWORDS: [&[u8]; 3] = [b"true", b"false", b"nil"];
+
+/// Makes tree nodes for the parser. Strings are handed over as written
+/// (between the quotes, escapes not yet replaced).
+pub trait Builder<'a> {
+    type Node;
+    /// any constant other than `Str` and `Sym`
+    fn constant(&mut self, value: Constant) -> Self::Node;
+    fn string(&mut self, raw: &'a str) -> Self::Node;
+    fn symbol(&mut self, name: &'a str, location: Location) -> Self::Node;
+    fn list(&mut self, list: Vec<Self::Node>, pair_right: Option<Self::Node>) -> Self::Node;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GAstBuilder;
+
+impl<'a> Builder<'a> for GAstBuilder {
+    type Node = GAst;
+
+    fn constant(&mut self, value: Constant) -> GAst {
+        GAst::Const(value)
+    }
+
+    fn string(&mut self, raw: &'a str) -> GAst {
+        GAst::Const(Constant::Str(Handle::new(unescape(raw).into_owned())))
+    }
+
+    fn symbol(&mut self, name: &'a str, location: Location) -> GAst {
+        let sym = Symbol(Handle::new(name.to_owned()), location);
+        GAst::Const(Constant::Sym(Handle::new(sym)))
+    }
+
+    fn list(&mut self, list: Vec<GAst>, pair_right: Option<GAst>) -> GAst {
+        GAst::List(Handle::new(List::new(list, pair_right)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefBuilder;
+
+impl<'a> Builder<'a> for RefBuilder {
+    type Node = GAstRef<'a>;
+
+    fn constant(&mut self, value: Constant) -> GAstRef<'a> {
+        GAstRef::Const(match value {
+            Constant::Nil => ConstantRef::Nil,
+            Constant::Bool(x) => ConstantRef::Bool(x),
+            Constant::Char(x) => ConstantRef::Char(x),
+            Constant::Int(x) => ConstantRef::Int(x),
+            Constant::Uint(x) => ConstantRef::Uint(x),
+            Constant::Float(x) => ConstantRef::Float(x),
+            Constant::Str(_) | Constant::Sym(_) => unreachable!(),
+        })
+    }
+
+    fn string(&mut self, raw: &'a str) -> GAstRef<'a> {
+        GAstRef::Const(ConstantRef::Str(unescape(raw)))
+    }
+
+    fn symbol(&mut self, name: &'a str, location: Location) -> GAstRef<'a> {
+        GAstRef::Const(ConstantRef::Sym(SymbolRef(name, location)))
+    }
+
+    fn list(&mut self, list: Vec<GAstRef<'a>>, pair_right: Option<GAstRef<'a>>) -> GAstRef<'a> {
+        GAstRef::List(ListRef(list, pair_right.map(Box::new)))
+    }
+}
+
+pub struct ArenaBuilder<'b> {
+    arena: &'b mut GAstArena,
+    path: u32,
+}
+
+impl<'b> ArenaBuilder<'b> {
+    pub fn new(arena: &'b mut GAstArena, path: &Handle<String>) -> Self {
+        let path = arena.path_id(path);
+        Self { arena, path }
+    }
+}
+
+impl<'a> Builder<'a> for ArenaBuilder<'_> {
+    type Node = NodeId;
+
+    fn constant(&mut self, value: Constant) -> NodeId {
+        self.arena.insert(&GAst::Const(value))
+    }
+
+    fn string(&mut self, raw: &'a str) -> NodeId {
+        let s = self.arena.push_str(&unescape(raw));
+        self.arena.push(ArenaNode::Str(s))
+    }
+
+    fn symbol(&mut self, name: &'a str, location: Location) -> NodeId {
+        let name = self.arena.push_str(name);
+        let location = ArenaLocation {
+            path: self.path,
+            line: location.line,
+            colum: location.colum,
+            pos: location.pos,
+        };
+        self.arena.push(ArenaNode::Sym(name, location))
+    }
+
+    fn list(&mut self, list: Vec<NodeId>, pair_right: Option<NodeId>) -> NodeId {
+        self.arena.push_list(&list, pair_right)
+    }
+}
+
+/// Incremental line/column tracking, computed the same way as
+/// `pest::Position::line_col`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCounter {
+    pub pos: usize,
+    pub line: usize,
+    pub colum: usize,
+}
+
+impl Default for LineCounter {
+    fn default() -> Self {
+        Self {
+            pos: 0,
+            line: 1,
+            colum: 1,
+        }
+    }
+}
+
+impl LineCounter {
+    pub fn line_col(&mut self, input: &str, pos: usize) -> (usize, usize) {
+        if pos < self.pos {
+            *self = Self::default();
+        }
+        for &b in &input.as_bytes()[self.pos..pos] {
+            if b == b'\n' {
+                self.line += 1;
+                self.colum = 1;
+            } else if b & 0xC0 != 0x80 {
+                self.colum += 1;
+            }
+        }
+        self.pos = pos;
+        (self.line, self.colum)
+    }
+}
+
+pub struct Parser<'a, B = GAstBuilder> {
+    builder: B,
+    input: &'a str,
+    bytes: &'a [u8],
+    path: Handle<String>,
+    origin: LineCounter,
+    lines: LineCounter,
+    furthest: usize,
+    expected: Rule,
+    truncated: Cell<bool>,
+    /// recovering mode: closers of the open lists, what went wrong and where
+    /// in the tree the parser is
+    recover: bool,
+    closers: Vec<u8>,
+    diagnostics: Vec<Diagnostic>,
+    form: usize,
+    node: Vec<Step>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str, path: Handle<String>) -> Self {
+        Self::with_origin(input, path, LineCounter::default())
+    }
+
+    /// A parser for a slice of a larger input which starts at `origin`;
+    /// locations and errors are reported relative to the whole input.
+    pub fn with_origin(input: &'a str, path: Handle<String>, origin: LineCounter) -> Self {
+        Self::with_builder(input, path, origin, GAstBuilder)
+    }
+}
+
+impl<'a, B: Builder<'a>> Parser<'a, B> {
+    pub fn with_builder(
+        input: &'a str,
+        path: Handle<String>,
+        origin: LineCounter,
+        builder: B,
+    ) -> Self {
+        Self {
+            builder,
+            input,
+            bytes: input.as_bytes(),
+            path,
+            origin,
+            lines: LineCounter::default(),
+            furthest: 0,
+            expected: Rule::sexpr,
+            truncated: Cell::new(false),
+            recover: false,
+            closers: vec![],
+            diagnostics: vec![],
+            form: 0,
+            node: vec![],
+        }
+    }
+
+    /// Parses `unit`: every datum of the input.
+    pub fn parse_unit(&mut self) -> Result<Vec<B::Node>, ParseError> {
+        let mut r = vec![];
+        let mut pos = 0;
+        loop {
+            let start = self.skip(pos);
+            if start == self.bytes.len() {
+                return Ok(r);
+            }
+            match self.sexpr(start, false)? {
+                Some((x, end)) => {
+                    r.push(x);
+                    pos = end;
+                }
+                None => return Err(self.error()),
+            }
+        }
+    }
+
+    /// Parses `unit`, reporting errors as diagnostics instead of stopping.
+    pub fn parse_recovering(mut self) -> Recovered<B::Node> {
+        self.recover = true;
+        let mut forms = vec![];
+        let mut pos = self.skip(0);
+        while let Some(&c) = self.byte(pos) {
+            self.form = forms.len();
+            match self.sexpr(pos, false) {
+                Ok(Some((x, end))) => {
+                    forms.push(x);
+                    pos = end;
+                }
+                Ok(None) if matches!(c, b')' | b']' | b'}') => {
+                    let message = format!("unexpected `{}`", c as char);
+                    self.diagnose(
+                        DiagnosticKind::UnexpectedCloser(c as char),
+                        message,
+                        pos..pos + 1,
+                    );
+                    pos += 1;
+                }
+                _ => pos = self.unexpected_input(pos),
+            }
+            pos = self.skip(pos);
+        }
+        Recovered {
+            forms,
+            diagnostics: self.diagnostics,
+        }
+    }
+
+    /// Parses `repl_unit`: exactly one datum.
+    pub fn parse_one(&mut self) -> Result<B::Node, ParseError> {
+        let start = self.skip(0);
+        if let Some((x, end)) = self.sexpr(start, false)? {
+            if self.skip(end) == self.bytes.len() {
+                return Ok(x);
+            }
+            self.fail::<()>(self.skip(end), Rule::EOI);
+        }
+        Err(self.error())
+    }
+
+    /// Parses the first datum at or after `pos` when the input may be only a
+    /// prefix of the whole text (`eof` is false). Returns `Ok(None)` if there
+    /// is no datum left, or if more input could still change the result.
+    pub fn parse_prefix(
+        &mut self,
+        pos: usize,
+        eof: bool,
+    ) -> Result<Option<(B::Node, usize)>, ParseError> {
+        self.truncated.set(false);
+        let start = self.skip(pos);
+        if start == self.bytes.len() {
+            return Ok(None);
+        }
+        let r = self.sexpr(start, false);
+        if !eof && self.truncated.get() {
+            return Ok(None);
+        }
+        match r? {
+            Some(x) => Ok(Some(x)),
+            None => Err(self.error()),
+        }
+    }
+
+    /// The absolute position of offset `pos`.
+    pub fn position(&mut self, pos: usize) -> LineCounter {
+        let (line, colum) = self.lines.line_col(self.input, pos);
+        LineCounter {
+            pos: self.origin.pos + pos,
+            line: self.origin.line + line - 1,
+            colum: if line == 1 {
+                self.origin.colum + colum - 1
+            } else {
+                colum
+            },
+        }
+    }
+
+    /// Skips whitespace and comments, returning the offset of the next token.
+    pub fn skip(&self, mut pos: usize) -> usize {
+        loop {
+            pos = self.whitespaces(pos);
+            if self.byte(pos) != Some(&b';') {
+                return pos;
+            }
+            while !matches!(self.byte(pos), None | Some(b'\n') | Some(b'\r')) {
+                pos += 1;
+            }
+        }
+    }
+
+    fn error(&mut self) -> ParseError {
+        let variant = ErrorVariant::ParsingError {
+            positives: vec![self.expected],
+            negatives: vec![],
+        };
+        self.error_at(variant, self.furthest)
+    }
+
+    pub fn error_at(&mut self, variant: ErrorVariant<Rule>, pos: usize) -> ParseError {
+        let mut e = Error::new_from_pos(variant, Position::new(self.input, pos).unwrap());
+        let p = self.position(pos);
+        e.location = InputLocation::Pos(p.pos);
+        e.line_col = LineColLocation::Pos((p.line, p.colum));
+        e
+    }
+
+    fn custom_error(&mut self, message: &str, pos: usize) -> ParseError {
+        let variant = ErrorVariant::CustomError {
+            message: message.to_owned(),
+        };
+        self.error_at(variant, pos)
+    }
+
+    fn fail<T>(&mut self, pos: usize, rule: Rule) -> Option<T> {
+        if pos >= self.furthest {
+            self.furthest = pos;
+            self.expected = rule;
+        }
+        None
+    }
+
+    fn diagnose(&mut self, kind: DiagnosticKind, message: String, span: std::ops::Range<usize>) {
+        let location = self.location(span.start);
+        let span = self.origin.pos + span.start..self.origin.pos + span.end;
+        self.diagnostics.push(Diagnostic {
+            kind,
+            message,
+            location,
+            span,
+            node: None,
+        });
+    }
+
+    /// The form and path of the node being parsed.
+    fn current_node(&self) -> Option<(usize, Vec<Step>)> {
+        Some((self.form, self.node.clone()))
+    }
+
+    /// Reports the input at `pos` that does not start a datum and skips the
+    /// token it belongs to.
+    fn unexpected_input(&mut self, pos: usize) -> usize {
+        let mut end = pos + self.char_at(pos).map_or(0, char::len_utf8);
+        while let Some(c) = self.char_at(end) {
+            if c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | '"') {
+                break;
+            }
+            end += c.len_utf8();
+        }
+        let message = format!("expected a datum, found `{}`", &self.input[pos..end]);
+        self.diagnose(DiagnosticKind::UnexpectedInput, message, pos..end);
+        end
+    }
+
+    fn invalid_literal(&mut self, message: &str, pos: usize, end: usize) -> PResult<B::Node> {
+        if !self.recover {
+            return Err(self.custom_error(message, pos));
+        }
+        self.diagnose(DiagnosticKind::InvalidLiteral, message.to_owned(), pos..end);
+        self.diagnostics.last_mut().unwrap().node = self.current_node();
+        Ok(Some((self.builder.constant(Constant::Nil), end)))
+    }
+
+    fn location(&mut self, pos: usize) -> Location {
+        let p = self.position(pos);
+        Location::new(self.path.clone(), p.line, p.colum, p.pos)
+    }
+
+    #[inline]
+    fn char_at(&self, pos: usize) -> Option<char> {
+        match self.byte(pos) {
+            Some(&b) if b < 0x80 => Some(b as char),
+            Some(_) => self.input[pos..].chars().next(),
+            None => None,
+        }
+    }
+
+    #[inline]
+    fn byte(&self, pos: usize) -> Option<&u8> {
+        let r = self.bytes.get(pos);
+        if r.is_none() {
+            self.truncated.set(true);
+        }
+        r
+    }
+
+    #[inline]
+    fn starts_with(&self, pos: usize, s: &[u8]) -> bool {
+        let rest = &self.bytes[pos..];
+        if rest.len() < s.len() && s.starts_with(rest) {
+            self.truncated.set(true);
+        }
+        rest.starts_with(s)
+    }
+
+    #[inline]
+    fn whitespace(&self, pos: usize) -> Option<usize> {
+        match self.char_at(pos) {
+            Some(c) if c.is_whitespace() => Some(pos + c.len_utf8()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn whitespaces(&self, mut pos: usize) -> usize {
+        while let Some(x) = self.whitespace(pos) {
+            pos = x;
+        }
+        pos
+    }
+
+    fn sexpr(&mut self, pos: usize, atomic: bool) -> PResult<B::Node> {
+        if let Some(x) = self.list(pos, atomic)? {
+            return Ok(Some(x));
+        }
+        for (prefix, name) in QUOTES.iter() {
+            if !self.starts_with(pos, prefix.as_bytes()) {
+                continue;
+            }
+            // locate before descending so the line counter only moves forward
+            let location = self.location(pos);
+            let start = pos + prefix.len();
+            let start = if atomic { start } else { self.skip(start) };
+            self.node.push(Step::Elem(1));
+            let value = self.sexpr(start, atomic)?;
+            self.node.pop();
+            if let Some((value, end)) = value {
+                let quote = self.builder.symbol(name, location);
+                return Ok(Some((self.builder.list(vec![quote, value], None), end)));
+            }
+        }
+        Ok(self.constant(pos)?.or_else(|| self.fail(pos, Rule::sexpr)))
+    }
+
+    fn list(&mut self, pos: usize, atomic: bool) -> PResult<B::Node> {
+        let close = match self.byte(pos) {
+            Some(b'(') => b')',
+            Some(b'[') => b']',
+            Some(b'{') => b'}',
+            _ => return Ok(self.fail(pos, Rule::list)),
+        };
+        let start = if atomic { pos + 1 } else { self.skip(pos + 1) };
+        if self.recover {
+            return self.list_recovering(pos, start, close).map(Some);
+        }
+        let (list, pair_right, end) = self.list_core(start)?;
+        let end = if atomic { end } else { self.skip(end) };
+        if self.byte(end) == Some(&close) {
+            Ok(Some((self.builder.list(list, pair_right), end + 1)))
+        } else {
+            Ok(self.fail(end, Rule::pair_right))
+        }
+    }
+
+    /// `list` in recovering mode: the same as `list_core` on valid input,
+    /// but it never fails once the opening bracket is seen.
+    fn list_recovering(
+        &mut self,
+        open: usize,
+        start: usize,
+        close: u8,
+    ) -> Result<(B::Node, usize), ParseError> {
+        let open_location = self.location(open);
+        self.closers.push(close);
+        let mut pos = self.whitespaces(start);
+        let mut list = vec![];
+        let mut pair_right = None;
+        let mut prev = None;
+        loop {
+            match self.byte(pos) {
+                Some(&c) if c == close => {
+                    self.closers.pop();
+                    return Ok((self.builder.list(list, pair_right), pos + 1));
+                }
+                Some(&c) if matches!(c, b')' | b']' | b'}') && !self.closers.contains(&c) => {
+                    let message = format!("unexpected `{}`", c as char);
+                    self.diagnose(
+                        DiagnosticKind::UnexpectedCloser(c as char),
+                        message,
+                        pos..pos + 1,
+                    );
+                    pos = self.whitespaces(pos + 1);
+                    prev = None;
+                    continue;
+                }
+                Some(b')') | Some(b']') | Some(b'}') | None => {
+                    self.closers.pop();
+                    let open_char = self.bytes[open] as char;
+                    self.diagnostics.push(Diagnostic {
+                        kind: DiagnosticKind::Unclosed(open_char),
+                        message: format!("unclosed `{}`", open_char),
+                        location: open_location,
+                        span: self.origin.pos + open..self.origin.pos + open + 1,
+                        node: self.current_node(),
+                    });
+                    return Ok((self.builder.list(list, pair_right), pos));
+                }
+                _ => {}
+            }
+            if let (Some(prev_end), None) = (prev, &pair_right) {
+                self.node.push(Step::Tail);
+                let x = self.pair_right(prev_end)?;
+                self.node.pop();
+                if let Some((x, end)) = x {
+                    pair_right = Some(x);
+                    prev = Some(end);
+                    pos = self.whitespaces(end);
+                    continue;
+                }
+            }
+            let diagnosed = self.diagnostics.len();
+            self.node.push(Step::Elem(list.len()));
+            let x = self.sexpr(pos, true)?;
+            self.node.pop();
+            match x {
+                Some((x, end)) => {
+                    if prev == Some(pos) {
+                        let message = "expected whitespace before this".to_owned();
+                        self.diagnose(DiagnosticKind::MissingWhitespace, message, pos..end);
+                    }
+                    if pair_right.is_some() {
+                        // the datum is dropped, so are the paths into it
+                        for x in &mut self.diagnostics[diagnosed..] {
+                            x.node = None;
+                        }
+                        let message = "unexpected datum after the tail of a dotted list".to_owned();
+                        self.diagnose(DiagnosticKind::AfterPairRight, message, pos..end);
+                    } else {
+                        list.push(x);
+                    }
+                    prev = Some(end);
+                    pos = self.whitespaces(end);
+                }
+                None => {
+                    pos = self.unexpected_input(pos);
+                    pos = self.whitespaces(pos);
+                    prev = None;
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn list_core(
+        &mut self,
+        pos: usize,
+    ) -> Result<(Vec<B::Node>, Option<B::Node>, usize), ParseError> {
+        let mut pos = self.whitespaces(pos);
+        let mut list = vec![];
+        let mut pair_right = None;
+        if let Some((x, end)) = self.sexpr(pos, true)? {
+            list.push(x);
+            pos = end;
+            loop {
+                if let Some((x, end)) = self.pair_right(pos)? {
+                    pair_right = Some(x);
+                    pos = end;
+                    break;
+                }
+                let start = self.whitespaces(pos);
+                if start == pos {
+                    break;
+                }
+                match self.sexpr(start, true)? {
+                    Some((x, end)) => {
+                        list.push(x);
+                        pos = end;
+                    }
+                    None => break,
+                }
+            }
+        }
+        Ok((list, pair_right, self.whitespaces(pos)))
+    }
+
+    fn pair_right(&mut self, pos: usize) -> PResult<B::Node> {
+        let dot = self.whitespaces(pos);
+        if dot == pos || self.byte(dot) != Some(&b'.') {
+            return Ok(None);
+        }
+        let start = self.whitespaces(dot + 1);
+        if start == dot + 1 {
+            return Ok(None);
+        }
+        self.sexpr(start, true)
+    }
+
+    fn constant(&mut self, pos: usize) -> PResult<B::Node> {
+        if let Some(end) = self.string_lit(pos) {
+            let value = self.builder.string(&self.input[pos + 1..end - 1]);
+            return Ok(Some((value, end)));
+        }
+        if let Some(end) = self.float_lit(pos) {
+            return match parse_float_lit(&self.input[pos..end]) {
+                Some(x) => Ok(Some((self.builder.constant(Constant::Float(x)), end))),
+                None => self.invalid_literal("invalid float literal", pos, end),
+            };
+        }
+        if let Some(end) = self.int_lit(pos) {
+            return match parse_int_lit(&self.input[pos..end]) {
+                Some(x) => Ok(Some((self.builder.constant(Constant::Int(x)), end))),
+                None => self.invalid_literal("integer literal out of range", pos, end),
+            };
+        }
+        if let Some(end) = self.number(pos).filter(|&x| self.byte(x) == Some(&b'u')) {
+            return match parse_uint_lit(&self.input[pos..end + 1]) {
+                Some(x) => Ok(Some((self.builder.constant(Constant::Uint(x)), end + 1))),
+                None => self.invalid_literal("integer literal out of range", pos, end + 1),
+            };
+        }
+        let keyword = if self.starts_with(pos, b"true") {
+            Some((Constant::Bool(true), pos + 4))
+        } else if self.starts_with(pos, b"false") {
+            Some((Constant::Bool(false), pos + 5))
+        } else if self.starts_with(pos, b"nil") {
+            Some((Constant::Nil, pos + 3))
+        } else {
+            None
+        };
+        if let Some((x, end)) = keyword {
+            return Ok(Some((self.builder.constant(x), end)));
+        }
+        let end = self.symbol(pos);
+        if end == pos {
+            return Ok(None);
+        }
+        let location = self.location(pos);
+        Ok(Some((
+            self.builder.symbol(&self.input[pos..end], location),
+            end,
+        )))
+    }
+
+    fn string_lit(&self, pos: usize) -> Option<usize> {
+        if self.byte(pos) != Some(&b'"') {
+            return None;
+        }
+        let mut pos = pos + 1;
+        loop {
+            match self.byte(pos)? {
+                b'"' => return Some(pos + 1),
+                b'\\' => match self.byte(pos + 1)? {
+                    b'\\' | b'"' | b'\'' | b'n' | b'r' | b't' => pos += 2,
+                    _ => return None,
+                },
+                _ => pos += 1,
+            }
+        }
+    }
+
+    fn digits(&self, pos: usize, radix: u32) -> usize {
+        let mut end = pos;
+        while let Some(&b) = self.byte(end) {
+            if !(b as char).is_digit(radix) {
+                break;
+            }
+            end += 1;
+        }
+        end
+    }
+
+    fn sign(&self, pos: usize) -> usize {
+        match self.byte(pos) {
+            Some(b'+') | Some(b'-') => pos + 1,
+            _ => pos,
+        }
+    }
+
+    /// `number` (and each `float_number` branch) in grammar order, yielding
+    /// the radix and the offset where its digits start.
+    fn radixes(&self, pos: usize) -> [(u32, Option<usize>); 4] {
+        let prefixed = |p: &[u8]| Some(pos + 2).filter(|_| self.starts_with(pos, p));
+        [
+            (16, prefixed(b"0x")),
+            (8, prefixed(b"0o")),
+            (2, prefixed(b"0b")),
+            (10, Some(pos)),
+        ]
+    }
+
+    fn number(&self, pos: usize) -> Option<usize> {
+        self.radixes(pos).iter().find_map(|&(radix, start)| {
+            let start = start?;
+            Some(self.digits(start, radix)).filter(|&end| end > start)
+        })
+    }
+
+    fn float_lit(&self, pos: usize) -> Option<usize> {
+        let end = self
+            .radixes(self.sign(pos))
+            .iter()
+            .find_map(|&(radix, start)| {
+                let start = start?;
+                let dot = self.digits(start, radix);
+                if dot == start || self.byte(dot) != Some(&b'.') {
+                    return None;
+                }
+                Some(self.digits(dot + 1, radix)).filter(|&end| end > dot + 1)
+            })?;
+        Some(if self.byte(end) == Some(&b'f') {
+            end + 1
+        } else {
+            end
+        })
+    }
+
+    fn int_lit(&self, pos: usize) -> Option<usize> {
+        let end = self.number(self.sign(pos))?;
+        match self.byte(end) {
+            Some(b'u') | Some(b'f') => None,
+            Some(b'i') => Some(end + 1),
+            _ => Some(end),
+        }
+    }
+
+    fn symbol(&self, mut pos: usize) -> usize {
+        while let Some(c) = self.char_at(pos) {
+            if c.is_whitespace()
+                || matches!(
+                    c,
+                    '"' | '\'' | '`' | ',' | '(' | ')' | '[' | ']' | '{' | '}'
+                )
+                || KEYWORDS.iter().any(|k| self.starts_with(pos, k))
+            {
+                break;
+            }
+            pos += c.len_utf8();
+        }
+        pos
+    }
+}
+
+fn unescape(i: &str) -> Cow<'_, str> {
+    if !i.contains('\\') {
+        return Cow::Borrowed(i);
+    }
+    let mut r = String::with_capacity(i.len());
+    let mut chars = i.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            r.push(escape_char(chars.next().unwrap()));
+        } else {
+            r.push(c);
+        }
+    }
+    Cow::Owned(r)
+}
+
+pub fn parse(input: &str, path: Handle<String>) -> Result<Vec<GAst>, ParseError> {
+    Parser::new(input, path).parse_unit()
+}
+
+pub fn one_unit_parse(input: &str, path: Handle<String>) -> Result<GAst, ParseError> {
+    Parser::new(input, path).parse_one()
+}
+
+/// Parses into `arena`, returning the top-level nodes.
+pub fn parse_into(
+    input: &str,
+    path: Handle<String>,
+    arena: &mut GAstArena,
+) -> Result<Vec<NodeId>, ParseError> {
+    let builder = ArenaBuilder::new(arena, &path);
+    Parser::with_builder(input, path, LineCounter::default(), builder).parse_unit()
+}
+
+/// Parses without copying symbols and strings out of `input`.
+pub fn parse_borrowed(input: &str, path: Handle<String>) -> Result<Vec<GAstRef<'_>>, ParseError> {
+    Parser::with_builder(input, path, LineCounter::default(), RefBuilder).parse_unit()
+}
@@ -0,0 +1,251 @@
+//! Incremental reparsing for editors.
+//!
+//! A `Document` keeps its text split into top-level forms. An edit only
+//! reparses the forms around the edited range: parsing restarts at the end of
+//! the last form the edit cannot affect and stops as soon as it lines up with
+//! an old form past the edit, whose remaining forms are then reused as they
+//! are. Inside reparsed forms, lists that did not change (same content and
+//! same locations) keep their old `Handle`, so `Handle::ptr_eq` tells which
+//! nodes are new.
+
+use std::ops::Range;
+
+use pest::error::ErrorVariant;
+use pest::Position;
+
+use super::fast::{LineCounter, Parser};
+use super::ParseError;
+use crate::diagnostics::floor_char_boundary;
+use crate::gast::constant::Constant;
+use crate::gast::list::List;
+use crate::gast::symbol::Location;
+use crate::gast::{GAst, Handle};
+
+/// How far the grammar looks past the end of a datum (`false` in a symbol).
+const LOOKAHEAD: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct Form {
+    pub ast: GAst,
+    /// byte range of the datum in the current text
+    pub span: Range<usize>,
+    start: LineCounter,
+    end: LineCounter,
+    /// where the datum started when `ast` was parsed
+    parsed: LineCounter,
+}
+
+impl Form {
+    /// Maps a location inside `ast` to the current text. The locations in
+    /// `ast` are the ones it was parsed with, which go stale when the form is
+    /// reused after an edit in front of it.
+    pub fn relocate(&self, loc: &Location) -> Location {
+        let p = rebase(
+            LineCounter {
+                pos: loc.pos,
+                line: loc.line,
+                colum: loc.colum,
+            },
+            self.parsed,
+            self.start,
+        );
+        Location::new(loc.path.clone(), p.line, p.colum, p.pos)
+    }
+
+    fn shift(mut self, from: LineCounter, to: LineCounter) -> Self {
+        self.start = rebase(self.start, from, to);
+        self.end = rebase(self.end, from, to);
+        self.span = self.start.pos..self.end.pos;
+        self
+    }
+}
+
+/// Which forms an edit replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes {
+    /// indices of the replaced forms before the edit
+    pub removed: Range<usize>,
+    /// indices of their replacements after the edit
+    pub inserted: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    text: String,
+    path: Handle<String>,
+    forms: Vec<Form>,
+    error: Option<ParseError>,
+}
+
+impl Document {
+    pub fn new(text: String, path: Handle<String>) -> Self {
+        let mut r = Document {
+            text: String::new(),
+            path,
+            forms: vec![],
+            error: None,
+        };
+        r.edit(0..0, &text).ok();
+        r
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn forms(&self) -> &[Form] {
+        &self.forms
+    }
+
+    /// The error that stopped the last parse; `forms` then holds the data
+    /// in front of it.
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    /// Replaces the bytes in `range` with `text` and reparses what changed.
+    /// The text is updated even if it no longer parses. A range that is out
+    /// of bounds or not on char boundaries is an error and changes nothing.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<Changes, ParseError> {
+        let valid = range.start <= range.end
+            && range.end <= self.text.len()
+            && self.text.is_char_boundary(range.start)
+            && self.text.is_char_boundary(range.end);
+        if !valid {
+            let variant = ErrorVariant::CustomError {
+                message: format!("invalid edit range {:?}", range),
+            };
+            let pos = floor_char_boundary(&self.text, range.start.min(range.end));
+            let pos = Position::new(&self.text, pos).unwrap();
+            return Err(ParseError::new_from_pos(variant, pos));
+        }
+        self.text.replace_range(range.clone(), text);
+        let new_end = range.start + text.len();
+
+        let first = self
+            .forms
+            .iter()
+            .position(|x| x.span.end + LOOKAHEAD > range.start)
+            .unwrap_or(self.forms.len());
+        let old: Vec<Form> = self.forms.drain(first..).collect();
+        // after an error the old forms do not cover the rest of the text
+        let reusable = if self.error.take().is_some() {
+            old.len()
+        } else {
+            old.iter()
+                .position(|x| x.span.start >= range.end)
+                .unwrap_or(old.len())
+        };
+
+        let (base, origin) = match self.forms.last() {
+            Some(x) => (x.span.end, x.end),
+            None => (0, LineCounter::default()),
+        };
+        let mut parser = Parser::with_origin(&self.text[base..], self.path.clone(), origin);
+        let mut pos = 0;
+        let mut candidate = reusable;
+        loop {
+            let start = base + parser.skip(pos);
+            while candidate < old.len() && old[candidate].span.start - range.end + new_end < start {
+                candidate += 1;
+            }
+            if start >= new_end
+                && candidate < old.len()
+                && old[candidate].span.start - range.end + new_end == start
+            {
+                // the rest of the text is unchanged from here on
+                let to = parser.position(start - base);
+                let from = old[candidate].start;
+                let changes = Changes {
+                    removed: first..first + candidate,
+                    inserted: first..self.forms.len(),
+                };
+                self.forms
+                    .extend(old[candidate..].iter().cloned().map(|x| x.shift(from, to)));
+                return Ok(changes);
+            }
+            match parser.parse_prefix(pos, true) {
+                Ok(Some((ast, end))) => {
+                    let ast = match old.get(self.forms.len() - first) {
+                        Some(x) => share(&x.ast, ast),
+                        None => ast,
+                    };
+                    let start = parser.position(start - base);
+                    let end_pos = parser.position(end);
+                    self.forms.push(Form {
+                        ast,
+                        span: start.pos..end_pos.pos,
+                        start,
+                        end: end_pos,
+                        parsed: start,
+                    });
+                    pos = end;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+        let changes = Changes {
+            removed: first..first + old.len(),
+            inserted: first..self.forms.len(),
+        };
+        match &self.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(changes),
+        }
+    }
+}
+
+fn rebase(p: LineCounter, from: LineCounter, to: LineCounter) -> LineCounter {
+    LineCounter {
+        pos: p.pos - from.pos + to.pos,
+        line: p.line - from.line + to.line,
+        colum: if p.line == from.line {
+            p.colum - from.colum + to.colum
+        } else {
+            p.colum
+        },
+    }
+}
+
+/// Equal including symbol locations.
+fn same(a: &GAst, b: &GAst) -> bool {
+    match (a, b) {
+        (GAst::List(a), GAst::List(b)) => {
+            Handle::ptr_eq(a, b)
+                || a.0.len() == b.0.len()
+                    && a.0.iter().zip(b.0.iter()).all(|(a, b)| same(a, b))
+                    && match (&a.1, &b.1) {
+                        (Some(a), Some(b)) => same(a, b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+        }
+        (GAst::Const(Constant::Sym(a)), GAst::Const(Constant::Sym(b))) => a == b && a.1 == b.1,
+        (GAst::Const(a), GAst::Const(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Rebuilds `new` reusing the lists of `old` that did not change.
+fn share(old: &GAst, new: GAst) -> GAst {
+    let (a, b) = match (old, &new) {
+        (GAst::List(a), GAst::List(b)) => (a, b),
+        _ => return new,
+    };
+    if same(old, &new) {
+        return old.clone();
+    }
+    if a.0.len() != b.0.len() || a.1.is_some() != b.1.is_some() {
+        return new;
+    }
+    let list = a.0.iter().zip(b.0.iter()).map(|(a, b)| share(a, b.clone()));
+    let pair_right = match (&a.1, &b.1) {
+        (Some(a), Some(b)) => Some(share(a, b.clone())),
+        _ => None,
+    };
+    GAst::List(Handle::new(List::new(list.collect(), pair_right)))
+}
@@ -1,16 +1,31 @@
+pub mod fast;
+pub mod incremental;
+#[cfg(feature = "sync")]
+pub mod parallel;
+pub mod printer;
+pub mod reader;
+pub mod recover;
+
+#[cfg(feature = "sync")]
+pub use parallel::parse_files;
+pub use reader::Reader;
+
+use std::ops::Range;
 use std::vec;
 use std::{fs::File, io::Read};
 
-use pest::error::Error;
+use pest::error::{Error, ErrorVariant};
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::*;
 
+use crate::error::CompilerError;
 use crate::gast::constant::Constant;
 use crate::gast::list::List;
 use crate::gast::symbol::{Location, Symbol};
 use crate::gast::*;
-use crate::{error::CompilerError, utils::escape_str};
+use crate::syntax::{unit_result, Balance, Syntax, UnitError};
+use crate::utils::{escape_str, parse_float_lit, parse_int_lit, parse_uint_lit};
 
 #[derive(Parser)]
 #[grammar = "./syntax/sexpr/grammar.pest"]
@@ -53,7 +68,7 @@ impl ParseFrom<Rule> for GAst {
 
                 let value = GAst::parse_from(pair.into_inner().next().unwrap(), path);
 
-                let lst = List(vec![quote, value], None);
+                let lst = List::new(vec![quote, value], None);
                 Self::List(Handle::new(lst))
             }
             _ => unreachable!(),
@@ -74,9 +89,9 @@ impl ParseFrom<Rule> for Constant {
             Rule::string_lit => Self::Str(Handle::new(escape_str(
                 &pair.as_str()[1..pair.as_str().len() - 1],
             ))),
-            Rule::uint_lit => Self::Uint(pair.as_str().parse().unwrap()),
-            Rule::int_lit => Self::Int(pair.as_str().parse().unwrap()),
-            Rule::float_lit => Self::Float(pair.as_str().parse().unwrap()),
+            Rule::uint_lit => Self::Uint(parse_uint_lit(pair.as_str()).unwrap()),
+            Rule::int_lit => Self::Int(parse_int_lit(pair.as_str()).unwrap()),
+            Rule::float_lit => Self::Float(parse_float_lit(pair.as_str()).unwrap()),
             Rule::bool_lit => Self::Bool(pair.as_str().parse().unwrap()),
             // Rule::char_lit => Self::Char(str2char(&escape_str(pair.as_str()))),
             Rule::nil_lit => Self::Nil,
@@ -92,8 +107,8 @@ impl ParseFrom<Rule> for List {
         debug_assert_eq!(pair.as_rule(), Rule::list_core);
         let r: Vec<_> = pair.into_inner().collect();
         match r.len() {
-            1 => List(
-                vec![GAst::parse_from(r.get(0).unwrap().clone(), path)],
+            1 => List::new(
+                vec![GAst::parse_from(r.first().unwrap().clone(), path)],
                 None,
             ),
             x if x > 1 => {
@@ -111,9 +126,9 @@ impl ParseFrom<Rule> for List {
                     list.push(GAst::parse_from(pair_right.clone(), path));
                     None
                 };
-                List(list, pair_right)
+                List::new(list, pair_right)
             }
-            _ => List(vec![], None),
+            _ => List::new(vec![], None),
         }
     }
 }
@@ -138,35 +153,106 @@ pub fn parse_unit(pair: Pair<Rule>, path: Handle<String>) -> Vec<GAst> {
         .collect()
 }
 
+#[cfg(not(feature = "fast-parser"))]
+pub fn parse(input: &str, path: Handle<String>) -> Result<Vec<GAst>, ParseError> {
+    pest_parse(input, path)
+}
+
+#[cfg(feature = "fast-parser")]
 pub fn parse(input: &str, path: Handle<String>) -> Result<Vec<GAst>, ParseError> {
-    let pairs: Pairs<Rule> = Cement::parse(Rule::unit, input)?;
+    fast::parse(input, path)
+}
+
+/// `Cement::parse` also rejecting the number literals the grammar accepts
+/// but that are out of range, which `ParseFrom` cannot convert.
+fn parse_pairs(rule: Rule, input: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    let pairs = Cement::parse(rule, input)?;
+    for pair in pairs.clone().flatten() {
+        let message = match pair.as_rule() {
+            Rule::uint_lit if parse_uint_lit(pair.as_str()).is_none() => {
+                "integer literal out of range"
+            }
+            Rule::int_lit if parse_int_lit(pair.as_str()).is_none() => {
+                "integer literal out of range"
+            }
+            Rule::float_lit if parse_float_lit(pair.as_str()).is_none() => "invalid float literal",
+            _ => continue,
+        };
+        let variant = ErrorVariant::CustomError {
+            message: message.to_owned(),
+        };
+        return Err(Error::new_from_span(variant, pair.as_span()));
+    }
+    Ok(pairs)
+}
+
+pub fn pest_parse(input: &str, path: Handle<String>) -> Result<Vec<GAst>, ParseError> {
+    let pairs: Pairs<Rule> = parse_pairs(Rule::unit, input)?;
     let result = pairs.flat_map(|x| parse_unit(x, path.clone())).collect();
     Ok(result)
 }
 
+/// `parse` also returning the byte range of each datum.
+pub fn parse_spanned(
+    input: &str,
+    path: Handle<String>,
+) -> Result<Vec<(GAst, Range<usize>)>, ParseError> {
+    let unit = parse_pairs(Rule::unit, input)?.next().unwrap();
+    let forms = unit.into_inner().filter(|x| x.as_rule() == Rule::sexpr);
+    Ok(forms
+        .map(|x| {
+            let span = x.as_span().start()..x.as_span().end();
+            (GAst::parse_from(x, path.clone()), span)
+        })
+        .collect())
+}
+
 pub fn file_parse(path: &str) -> Result<Vec<GAst>, CompilerError<ParseError>> {
     use std::path::PathBuf;
-    let path_buf = PathBuf::from(path).canonicalize().unwrap();
-    let file_path = Handle::new(Symbol::new(path_buf.to_str().unwrap()));
-    let mut f = if let Ok(f) = File::open(path_buf) {
-        f
-    } else {
-        return Err(CompilerError::FileOpenError(file_path.0.clone()));
+    let path_buf = match PathBuf::from(path).canonicalize() {
+        Ok(x) => x,
+        Err(e) => {
+            let path = Handle::new(path.to_owned());
+            return Err(CompilerError::FileOpenError(path, e));
+        }
     };
+    let file_path = Handle::new(path_buf.to_string_lossy().into_owned());
     let mut buf = String::new();
-    if f.read_to_string(&mut buf).is_err() {
-        return Err(CompilerError::FileOpenError(file_path.0.clone()));
+    if let Err(e) = File::open(path_buf).and_then(|mut f| f.read_to_string(&mut buf)) {
+        return Err(CompilerError::FileOpenError(file_path, e));
     }
-    parse(&buf, file_path.0.clone()).map_err(CompilerError::ParseError)
+    parse(&buf, file_path).map_err(CompilerError::ParseError)
 }
 
-pub fn repl_parse(input: &str) -> Result<GAst, ParseError> {
+/// `file_parse` reading the file through a memory map, validating and
+/// parsing it a chunk at a time.
+#[cfg(feature = "mmap")]
+pub fn file_parse_mmap(path: &str) -> Result<Vec<GAst>, CompilerError<ParseError>> {
+    crate::syntax::mmap::with_mapped_file(path, Syntax::Sexpr, parse)
+}
+
+pub fn repl_parse(input: &str) -> Result<GAst, UnitError<ParseError>> {
     one_unit_parse(input, "<stdin>")
 }
 
-pub fn one_unit_parse(input: &str, path: &str) -> Result<GAst, ParseError> {
+/// Parses one datum; `UnitError::Incomplete` if the input could still be
+/// completed.
+pub fn one_unit_parse(input: &str, path: &str) -> Result<GAst, UnitError<ParseError>> {
+    #[cfg(not(feature = "fast-parser"))]
+    let r = pest_one_unit_parse(input, path);
+    #[cfg(feature = "fast-parser")]
+    let r = fast::one_unit_parse(input, Handle::new(path.to_owned()));
+    unit_result(input, r, balance(input))
+}
+
+/// The open brackets and string at the end of a partial buffer.
+pub fn balance(input: &str) -> Balance {
+    crate::syntax::balance(input, Syntax::Sexpr)
+}
+
+pub fn pest_one_unit_parse(input: &str, path: &str) -> Result<GAst, ParseError> {
     let path = Handle::new(Symbol::new(path));
-    let pair = Cement::parse(Rule::repl_unit, input)?
+    let pair = parse_pairs(Rule::repl_unit, input)?
         .next()
         .unwrap()
         .into_inner()
@@ -0,0 +1,110 @@
+//! Parsing many files at once.
+//!
+//! Files are handed out to a fixed number of worker threads one at a time,
+//! so a few large files do not hold up the rest. The library has no symbol
+//! interner; every file gets its own `Handle`s. A panic while parsing a file
+//! becomes the error of that file.
+
+use std::any::Any;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use pest::error::ErrorVariant;
+use pest::Position;
+
+use super::{file_parse, ParseError};
+use crate::error::CompilerError;
+use crate::gast::GAst;
+
+pub type FileResult = Result<Vec<GAst>, CompilerError<ParseError>>;
+
+#[derive(Debug)]
+pub struct ParsedFiles {
+    /// one entry per input path, in input order
+    pub results: Vec<(String, FileResult)>,
+}
+
+impl ParsedFiles {
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(|(_, x)| x.is_ok())
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &CompilerError<ParseError>)> {
+        self.results
+            .iter()
+            .filter_map(|(path, x)| x.as_ref().err().map(|e| (path.as_str(), e)))
+    }
+
+    /// The trees of every file if all of them parsed, otherwise every error.
+    #[allow(clippy::type_complexity)]
+    pub fn into_result(
+        self,
+    ) -> Result<Vec<(String, Vec<GAst>)>, Vec<(String, CompilerError<ParseError>)>> {
+        if self.is_ok() {
+            Ok(self
+                .results
+                .into_iter()
+                .map(|(path, x)| (path, x.unwrap()))
+                .collect())
+        } else {
+            Err(self
+                .results
+                .into_iter()
+                .filter_map(|(path, x)| x.err().map(|e| (path, e)))
+                .collect())
+        }
+    }
+}
+
+/// Parses `paths` with `file_parse` on one thread per available core.
+pub fn parse_files<P: AsRef<str> + Sync>(paths: &[P]) -> ParsedFiles {
+    let threads = thread::available_parallelism().map_or(1, |x| x.get());
+    parse_files_with(paths, threads)
+}
+
+pub fn parse_files_with<P: AsRef<str> + Sync>(paths: &[P], threads: usize) -> ParsedFiles {
+    let next = AtomicUsize::new(0);
+    let slots: Vec<Mutex<Option<FileResult>>> = paths.iter().map(|_| Mutex::new(None)).collect();
+    let worker = || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        let path = match paths.get(i) {
+            Some(x) => x.as_ref(),
+            None => break,
+        };
+        let result = panic::catch_unwind(|| file_parse(path));
+        let result = result.unwrap_or_else(|e| Err(panicked(path, e)));
+        *slots[i].lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+    };
+    thread::scope(|s| {
+        for _ in 1..threads.clamp(1, paths.len().max(1)) {
+            s.spawn(worker);
+        }
+        worker();
+    });
+    let results = paths
+        .iter()
+        .zip(slots)
+        .map(|(path, x)| {
+            let x = x.into_inner().unwrap_or_else(PoisonError::into_inner);
+            (path.as_ref().to_owned(), x.unwrap())
+        })
+        .collect();
+    ParsedFiles { results }
+}
+
+fn panicked(path: &str, payload: Box<dyn Any + Send>) -> CompilerError<ParseError> {
+    let message = match payload.downcast::<String>() {
+        Ok(x) => *x,
+        Err(x) => x
+            .downcast_ref::<&str>()
+            .map_or("unknown panic", |x| x)
+            .to_owned(),
+    };
+    let variant = ErrorVariant::CustomError {
+        message: format!("parser panicked: {}", message),
+    };
+    let e = ParseError::new_from_pos(variant, Position::from_start(""));
+    CompilerError::ParseError(e.with_path(path))
+}
@@ -0,0 +1,277 @@
+//! Printing trees as source text that `parse` reads back as the same tree.
+//!
+//! Everything round-trips except what the grammar cannot express: chars
+//! (printed as one-char strings), non-finite floats, empty dotted lists and
+//! symbols whose names are not valid symbols.
+//!
+//! `to_pretty` breaks lists that do not fit a `Style`'s line width over
+//! several lines and prints quote forms with their shorthand.
+//!
+//! Diff edits and patches display the trees they hold as source too.
+
+use std::fmt::Display;
+
+use crate::gast::constant::Constant;
+use crate::gast::diff::{fmt_path, Edit, Patch};
+use crate::gast::list::List;
+use crate::gast::GAst;
+use crate::syntax::format::Style;
+
+pub fn to_source(ast: &GAst) -> String {
+    let mut out = String::new();
+    write_source(ast, &mut out);
+    out
+}
+
+pub fn write_source(ast: &GAst, out: &mut String) {
+    match ast {
+        GAst::Const(c) => write_constant(c, out),
+        GAst::List(x) => {
+            out.push('(');
+            for (i, x) in x.0.iter().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                write_source(x, out);
+            }
+            if let Some(x) = &x.1 {
+                out.push_str(" . ");
+                write_source(x, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+pub fn to_pretty(ast: &GAst, style: &Style) -> String {
+    let mut out = String::new();
+    write_pretty(ast, 0, style, &mut out);
+    out
+}
+
+/// Prints `ast` starting at column `indent`. A list that does not fit the
+/// line keeps a leading symbol and as many arguments as `style.keep` says
+/// (one by default) on its first line and puts the other elements on their
+/// own lines, `style.indent` columns past the bracket; lists not led by a
+/// symbol line their elements up under the first one.
+pub fn write_pretty(ast: &GAst, indent: usize, style: &Style, out: &mut String) {
+    let list = match ast {
+        GAst::List(x)
+            if !x.0.is_empty() && flat_width(ast, style.width.saturating_sub(indent)).is_none() =>
+        {
+            x
+        }
+        _ => return write_flat(ast, out),
+    };
+    if let Some((prefix, x)) = quoted(list) {
+        out.push_str(prefix);
+        return write_pretty(x, indent + prefix.len(), style, out);
+    }
+    let start = out.len();
+    out.push('(');
+    write_pretty(&list.0[0], indent + 1, style, out);
+    let (inner, keep) = match &list.0[0] {
+        GAst::Const(Constant::Sym(head)) => {
+            (indent + style.indent, style.keep(&head.0).unwrap_or(1))
+        }
+        _ => (indent + 1, 0),
+    };
+    let args = &list.0[1..];
+    let keep = keep.min(args.len());
+    for x in &args[..keep] {
+        out.push(' ');
+        let column = column(out, start, indent);
+        write_pretty(x, column, style, out);
+    }
+    let newline = |out: &mut String| {
+        out.push('\n');
+        out.push_str(&" ".repeat(inner));
+    };
+    for x in &args[keep..] {
+        newline(out);
+        write_pretty(x, inner, style, out);
+    }
+    if let Some(x) = &list.1 {
+        newline(out);
+        out.push_str(". ");
+        write_pretty(x, inner + 2, style, out);
+    }
+    out.push(')');
+}
+
+/// The column at the end of `out`, which was at column `indent` at `start`.
+pub(crate) fn column(out: &str, start: usize, indent: usize) -> usize {
+    match out[start..].rfind('\n') {
+        Some(x) => out[start + x + 1..].chars().count(),
+        None => indent + out[start..].chars().count(),
+    }
+}
+
+/// `write_source` with quote shorthands.
+fn write_flat(ast: &GAst, out: &mut String) {
+    let list = match ast {
+        GAst::Const(c) => return write_constant(c, out),
+        GAst::List(x) => x,
+    };
+    if let Some((prefix, x)) = quoted(list) {
+        out.push_str(prefix);
+        return write_flat(x, out);
+    }
+    out.push('(');
+    for (i, x) in list.0.iter().enumerate() {
+        if i != 0 {
+            out.push(' ');
+        }
+        write_flat(x, out);
+    }
+    if let Some(x) = &list.1 {
+        out.push_str(" . ");
+        write_flat(x, out);
+    }
+    out.push(')');
+}
+
+/// The width of `write_flat(ast)` if it is at most `limit`, looking at no
+/// more of the tree than fits.
+fn flat_width(ast: &GAst, limit: usize) -> Option<usize> {
+    let list = match ast {
+        GAst::Const(c) => {
+            let width = constant_width(c, limit);
+            return Some(width).filter(|&x| x <= limit);
+        }
+        GAst::List(x) => x,
+    };
+    if let Some((prefix, x)) = quoted(list) {
+        return Some(prefix.len() + flat_width(x, limit.checked_sub(prefix.len())?)?);
+    }
+    let separators = list.0.len().saturating_sub(1) + if list.1.is_some() { 3 } else { 0 };
+    let mut width = 2 + separators;
+    for x in list.0.iter().chain(list.1.iter()) {
+        width += flat_width(x, limit.checked_sub(width)?)?;
+    }
+    Some(width).filter(|&x| x <= limit)
+}
+
+/// The width of `write_constant(c)`, or something over `limit`.
+pub(crate) fn constant_width(c: &Constant, limit: usize) -> usize {
+    let escaped = |c: char| match c {
+        '\\' | '"' | '\n' | '\r' | '\t' => 2,
+        _ => 1,
+    };
+    match c {
+        Constant::Char(x) => 2 + escaped(*x),
+        Constant::Str(x) => 2 + x.chars().take(limit).map(escaped).sum::<usize>(),
+        Constant::Sym(x) => x.0.chars().take(limit + 1).count(),
+        c => {
+            let mut out = String::new();
+            write_constant(c, &mut out);
+            out.len()
+        }
+    }
+}
+
+/// The shorthand and quoted datum of `(quote x)` and the like.
+fn quoted(list: &List) -> Option<(&'static str, &GAst)> {
+    let prefix = match (list.0.as_slice(), &list.1) {
+        ([GAst::Const(Constant::Sym(head)), _], None) => match head.0.as_str() {
+            "quote" => "'",
+            "quasiquote" => "`",
+            // `,@x` would read back as unquote-splicing
+            "unquote" if !starts_with_at(&list.0[1]) => ",",
+            "unquote-splicing" => ",@",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((prefix, &list.0[1]))
+}
+
+fn starts_with_at(ast: &GAst) -> bool {
+    matches!(ast, GAst::Const(Constant::Sym(x)) if x.0.starts_with('@'))
+}
+
+pub(crate) fn write_constant(c: &Constant, out: &mut String) {
+    match c {
+        Constant::Nil => out.push_str("nil"),
+        Constant::Bool(x) => out.push_str(&x.to_string()),
+        Constant::Char(x) => write_str_lit(&x.to_string(), out),
+        Constant::Int(x) => out.push_str(&x.to_string()),
+        Constant::Uint(x) => {
+            out.push_str(&x.to_string());
+            out.push('u');
+        }
+        Constant::Float(x) => {
+            // `Display` never uses an exponent, which the grammar lacks
+            let s = x.to_string();
+            out.push_str(&s);
+            if x.is_finite() && !s.contains('.') {
+                out.push_str(".0");
+            }
+        }
+        Constant::Str(x) => write_str_lit(x, out),
+        Constant::Sym(x) => out.push_str(&x.0),
+    }
+}
+
+fn write_str_lit(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edit::Replace { path, old, new } => write!(
+                f,
+                "replace {}: {} => {}",
+                fmt_path(path),
+                to_source(old),
+                to_source(new)
+            ),
+            Edit::Insert { list, index, value } => {
+                write!(
+                    f,
+                    "insert {} [{}]: {}",
+                    fmt_path(list),
+                    index,
+                    to_source(value)
+                )
+            }
+            Edit::Delete { list, index, old } => {
+                write!(
+                    f,
+                    "delete {} [{}]: {}",
+                    fmt_path(list),
+                    index,
+                    to_source(old)
+                )
+            }
+            Edit::Move { list, from, to } => {
+                write!(f, "move {} [{}] -> [{}]", fmt_path(list), from, to)
+            }
+            Edit::SetTail { list, old, new } => {
+                let show = |x: &Option<GAst>| x.as_ref().map_or("none".to_string(), to_source);
+                write!(f, "tail {}: {} => {}", fmt_path(list), show(old), show(new))
+            }
+        }
+    }
+}
+
+impl Display for Patch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for e in &self.edits {
+            writeln!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
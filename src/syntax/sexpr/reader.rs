@@ -0,0 +1,133 @@
+use std::io::{self, Read, Stdin};
+
+use pest::error::ErrorVariant;
+
+use super::fast::{LineCounter, Parser};
+use super::ParseError;
+use crate::gast::{GAst, Handle};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads top-level data one at a time from a byte stream.
+///
+/// Only the datum being parsed (plus at most one read chunk) is kept in
+/// memory, so arbitrarily long inputs can be processed. Symbol locations
+/// and error positions are relative to the start of the stream.
+/// The iterator stops after the first error.
+pub struct Reader<R> {
+    inner: R,
+    path: Handle<String>,
+    /// decoded text not consumed yet
+    buf: String,
+    /// trailing bytes of an incomplete UTF-8 sequence
+    raw: Vec<u8>,
+    chunk: Vec<u8>,
+    origin: LineCounter,
+    eof: bool,
+    done: bool,
+}
+
+impl Reader<Stdin> {
+    pub fn stdin() -> Self {
+        Self::new(io::stdin(), Handle::new("<stdin>".to_string()))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R, path: Handle<String>) -> Self {
+        Self {
+            inner,
+            path,
+            buf: String::new(),
+            raw: vec![],
+            chunk: vec![],
+            origin: LineCounter::default(),
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn fill(&mut self, size: usize) -> io::Result<()> {
+        self.chunk.resize(size, 0);
+        let n = loop {
+            match self.inner.read(&mut self.chunk) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            if !self.raw.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                ));
+            }
+            return Ok(());
+        }
+        self.raw.extend_from_slice(&self.chunk[..n]);
+        let valid = match std::str::from_utf8(&self.raw) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                ))
+            }
+        };
+        self.buf
+            .push_str(std::str::from_utf8(&self.raw[..valid]).unwrap());
+        self.raw.drain(..valid);
+        Ok(())
+    }
+
+    fn io_error(&mut self, e: io::Error) -> ParseError {
+        let mut parser = Parser::with_origin(&self.buf, self.path.clone(), self.origin);
+        let variant = ErrorVariant::CustomError {
+            message: e.to_string(),
+        };
+        parser.error_at(variant, self.buf.len())
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<GAst, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let mut parser = Parser::with_origin(&self.buf, self.path.clone(), self.origin);
+            match parser.parse_prefix(0, self.eof) {
+                Ok(Some((x, end))) => {
+                    self.origin = parser.position(end);
+                    self.buf.drain(..end);
+                    return Some(Ok(x));
+                }
+                Ok(None) if self.eof => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(None) => {
+                    // grow geometrically so a large datum is reparsed O(log n) times
+                    let size = CHUNK_SIZE.max(self.buf.len());
+                    if let Err(e) = self.fill(size) {
+                        self.done = true;
+                        return Some(Err(self.io_error(e)));
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Error-recovering parsing.
+//!
+//! Instead of stopping at the first error, the parser reports it and goes
+//! on: unclosed lists are closed at the end of input (or at a closing
+//! bracket of an enclosing list), stray closing brackets and input that does
+//! not start a datum are skipped, and data missing the whitespace in front of
+//! them are kept. Valid input gives the same tree as `parse` and no
+//! diagnostics.
+
+use std::ops::Range;
+
+use super::fast::Parser;
+use crate::gast::diff::Step;
+use crate::gast::symbol::Location;
+use crate::gast::{GAst, Handle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// a list still open at the end of input or at an outer closing bracket
+    Unclosed(char),
+    /// a closing bracket without a matching open one
+    UnexpectedCloser(char),
+    /// two data inside a list not separated by whitespace
+    MissingWhitespace,
+    /// a datum after the tail of a dotted list
+    AfterPairRight,
+    /// input that does not start a datum
+    UnexpectedInput,
+    /// a number literal out of range
+    InvalidLiteral,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub location: Location,
+    /// byte range of the offending input
+    pub span: Range<usize>,
+    /// the index of the form and the path in it of the node standing in for
+    /// the error, if any
+    pub node: Option<(usize, Vec<Step>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovered<T = GAst> {
+    pub forms: Vec<T>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<T> Recovered<T> {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// The diagnostic about the node at `path` in form `form`, if it stands
+    /// in for an error.
+    pub fn error_at(&self, form: usize, path: &[Step]) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .find(|x| matches!(&x.node, Some((f, p)) if *f == form && p == path))
+    }
+}
+
+pub fn parse_recovering(input: &str, path: Handle<String>) -> Recovered {
+    Parser::new(input, path).parse_recovering()
+}
@@ -1,2 +1,2125 @@
 #[test]
 fn test_match() {}
+
+#[cfg(test)]
+mod sexpr_fast {
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::recover::parse_recovering;
+    use crate::syntax::sexpr::{fast, pest_one_unit_parse, pest_parse};
+    use pest::error::InputLocation;
+
+    const CASES: &[&str] = &[
+        "",
+        " ",
+        "foo",
+        " a ;c",
+        ";c\na",
+        "a;c\nb",
+        "(a ; c\n b)",
+        "(  ;c\na ;d\n)",
+        "'bar `bar ,a ,@a , @b ,@ b",
+        "' a",
+        "(' a)",
+        "(a ' b)",
+        "(a ,@ b)",
+        "(a . b)",
+        "(a .b)",
+        "(a.b)",
+        "(a . b c)",
+        "(a . b . c)",
+        "(. a)",
+        "(.)",
+        "(a .)",
+        "(a . 'b)",
+        "(a  .  b  )",
+        "(a'b)",
+        "(a(b))",
+        "((a)b)",
+        "[a)",
+        "(a b",
+        "a)",
+        "( ) [] {}",
+        "1 -1 +1 1u 1i 1.5 1.5f -0.5 00",
+        "0x1f 0x1.f 0x1.8f -0b101 0o17 0x 0b12 1uu 1.0f1",
+        "1f 1a 1. 1.a 1.5e3 1.5.5 1.0nil",
+        "99999999999999999999",
+        "(a -9223372036854775809i 18446744073709551616u)",
+        "9223372036854775807 -9223372036854775808 18446744073709551615u",
+        "truex nil nilx true false anil true(a) nil(a)",
+        "(nil) (truex) (true)",
+        "\"a\\nb\" \"\\\"\" \"\\'\" \"\\q\" \"abc",
+        "a\"x\" (a\"x\") ax\"",
+        "x:y a.b . - +a a,b a`b",
+        "a\tb\r\nc\rd (a\u{3000}b) 嗨 (嗨 . 嗨)",
+        "(config [\n  (name . \"hoshino tented\")\n  (age . 512)\n  {abaaba}\n])",
+        "(let [a 1]\n [b -1]\n '(a b c . e)\n `(+ a b c d))",
+    ];
+
+    fn path() -> Handle<String> {
+        Handle::new("<test>".to_string())
+    }
+
+    fn check(input: &str) {
+        let expected = pest_parse(input, path());
+        let actual = fast::parse(input, path());
+        match (&expected, &actual) {
+            // `Debug` includes symbol locations, which `PartialEq` ignores
+            (Ok(a), Ok(b)) => assert_eq!(format!("{:?}", a), format!("{:?}", b), "{:?}", input),
+            (Err(_), Err(_)) => {}
+            _ => panic!("{:?}: pest {:?}, fast {:?}", input, expected, actual),
+        }
+        let recovered = parse_recovering(input, path());
+        match &expected {
+            Ok(x) => {
+                assert!(
+                    recovered.is_ok(),
+                    "{:?}: {:?}",
+                    input,
+                    recovered.diagnostics
+                );
+                assert_eq!(format!("{:?}", x), format!("{:?}", recovered.forms));
+            }
+            Err(_) => assert!(!recovered.is_ok(), "{:?}", input),
+        }
+        let expected = pest_one_unit_parse(input, "<test>");
+        let actual = fast::one_unit_parse(input, path());
+        assert_eq!(
+            expected.map(|x| format!("{:?}", x)).ok(),
+            actual.map(|x| format!("{:?}", x)).ok(),
+            "{:?}",
+            input
+        );
+    }
+
+    #[test]
+    fn differential_cases() {
+        CASES.iter().for_each(|x| check(x));
+    }
+
+    #[test]
+    fn differential_random() {
+        const PIECES: &[&str] = &[
+            "(", ")", "[", "]", "{", "}", " ", "  ", "\n", "\t", ";c\n", "'", "`", ",", ",@", ".",
+            " . ", "a", "foo", "1", "-2", "3u", "0x1f", "1.5", "\"s\"", "\"\\n\"", "true", "nil",
+            "x", "嗨",
+        ];
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..5000 {
+            let len = next() % 16;
+            let input: String = (0..len)
+                .map(|_| PIECES[(next() % PIECES.len() as u64) as usize])
+                .collect();
+            check(&input);
+        }
+    }
+
+    #[test]
+    fn out_of_range_literals() {
+        let range = "integer literal out of range";
+        let cases = [
+            ("(a 18446744073709551616u)", "18446744073709551616u", range),
+            ("(a -9223372036854775809)", "-9223372036854775809", range),
+            ("0x10000000000000000", "0x10000000000000000", range),
+            (
+                "(a 0x10000000000000000.8)",
+                "0x10000000000000000.8",
+                "invalid float literal",
+            ),
+        ];
+        for (input, literal, message) in cases.iter() {
+            let e = pest_parse(input, path()).unwrap_err();
+            assert_eq!(e.variant.message(), *message, "{}", input);
+            let start = input.find(literal).unwrap();
+            let span = (start, start + literal.len());
+            assert_eq!(e.location, InputLocation::Span(span), "{}", input);
+            assert!(fast::parse(input, path()).is_err(), "{}", input);
+        }
+        let limits =
+            "(18446744073709551615u -9223372036854775808 0xffffffffffffffffu -0x8000000000000000)";
+        assert_eq!(
+            pest_parse(limits, path()).unwrap()[0].to_string(),
+            "(18446744073709551615 -9223372036854775808 18446744073709551615 -9223372036854775808)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod sexpr_reader {
+    use std::io::Read;
+
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::{fast, Reader};
+
+    /// Hands out one byte per `read`, splitting every token and UTF-8 sequence.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((x, rest)) if !buf.is_empty() => {
+                    buf[0] = *x;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn path() -> Handle<String> {
+        Handle::new("<test>".to_string())
+    }
+
+    #[test]
+    fn matches_whole_parse() {
+        let input =
+            "foo 1.5 1.\n 1 ;c\n(a [b . c]\n  {嗨 \"s\\n\"}) 'x\r\n`(y ,@z) abfalse 0x1f1u tru";
+        let expected: Vec<_> = fast::parse(input, path())
+            .unwrap()
+            .iter()
+            .map(|x| format!("{:?}", x))
+            .collect();
+        let actual: Vec<_> = Reader::new(Trickle(input.as_bytes()), path())
+            .map(|x| format!("{:?}", x.unwrap()))
+            .collect();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn error_location() {
+        let mut r = Reader::new(Trickle("(a)\n  (b c]".as_bytes()), path());
+        assert!(r.next().unwrap().is_ok());
+        let e = r.next().unwrap().unwrap_err();
+        assert_eq!(e.line_col, pest::error::LineColLocation::Pos((2, 7)));
+        assert!(r.next().is_none());
+    }
+}
+
+#[cfg(test)]
+mod sexpr_incremental {
+    use crate::gast::constant::Constant;
+    use crate::gast::list::List;
+    use crate::gast::symbol::Symbol;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+    use crate::syntax::sexpr::incremental::{Document, Form};
+
+    fn path() -> Handle<String> {
+        Handle::new("<test>".to_string())
+    }
+
+    fn relocate(form: &Form, x: &GAst) -> GAst {
+        match x {
+            GAst::Const(Constant::Sym(s)) => {
+                let s = Symbol(s.0.clone(), form.relocate(&s.1));
+                GAst::Const(Constant::Sym(Handle::new(s)))
+            }
+            GAst::Const(_) => x.clone(),
+            GAst::List(l) => GAst::List(Handle::new(List::new(
+                l.0.iter().map(|x| relocate(form, x)).collect(),
+                l.1.as_ref().map(|x| relocate(form, x)),
+            ))),
+        }
+    }
+
+    fn check(doc: &Document) {
+        let expected = fast::parse(doc.text(), path());
+        assert_eq!(expected.is_err(), doc.error().is_some(), "{:?}", doc.text());
+        if let Ok(expected) = expected {
+            let actual: Vec<_> = doc.forms().iter().map(|f| relocate(f, &f.ast)).collect();
+            assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+        }
+    }
+
+    #[test]
+    fn reuses_unchanged_forms() {
+        let mut doc = Document::new("(a b)\n(c (d e) f)\n(g h)".to_string(), path());
+        let before: Vec<_> = doc.forms().iter().map(|x| x.ast.clone()).collect();
+        let changes = doc.edit(15..16, "ff\n").unwrap();
+        assert_eq!(changes.removed, 1..2);
+        assert_eq!(changes.inserted, 1..2);
+        let after = doc.forms();
+        let ptr_eq =
+            |a: &GAst, b: &GAst| Handle::ptr_eq(&a.get_list().unwrap(), &b.get_list().unwrap());
+        assert!(ptr_eq(&before[0], &after[0].ast));
+        assert!(ptr_eq(&before[2], &after[2].ast));
+        assert!(!ptr_eq(&before[1], &after[1].ast));
+        let old_inner = before[1].get_list().unwrap().0[1].clone();
+        assert!(ptr_eq(&old_inner, &after[1].ast.get_list().unwrap().0[1]));
+        check(&doc);
+    }
+
+    #[test]
+    fn random_edits() {
+        const PIECES: &[&str] = &[
+            "(", ")", "[", "]", " ", "\n", ";c\n", "'", ",@", " . ", "a", "foo", "1", "1.5",
+            "\"s\"", "true", "嗨",
+        ];
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut doc = Document::new("(a b) (c d)\n(e . f) g".to_string(), path());
+        for _ in 0..3000 {
+            let text = doc.text();
+            let boundaries: Vec<_> = (0..=text.len())
+                .filter(|&x| text.is_char_boundary(x))
+                .collect();
+            let a = boundaries[(next() % boundaries.len() as u64) as usize];
+            let b = boundaries[(next() % boundaries.len() as u64) as usize];
+            let (a, b) = (a.min(b), a.max(b));
+            // mostly small edits, so the document keeps some structure
+            let b = if next() % 4 == 0 { b } else { a };
+            let insert = PIECES[(next() % PIECES.len() as u64) as usize];
+            doc.edit(a..b, insert).ok();
+            check(&doc);
+        }
+    }
+
+    #[test]
+    fn invalid_edit_ranges() {
+        let mut doc = Document::new("(a 嗨)".to_string(), path());
+        for range in [0..8, 8..8, 4..5, 3..4, 5..7] {
+            let e = doc.edit(range.clone(), "x").unwrap_err();
+            assert_eq!(
+                e.variant.message(),
+                format!("invalid edit range {:?}", range)
+            );
+        }
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = 3..1;
+        assert!(doc.edit(inverted, "x").is_err());
+        assert_eq!(doc.text(), "(a 嗨)");
+        assert_eq!(doc.forms().len(), 1);
+        check(&doc);
+        assert!(doc.edit(3..6, "b").is_ok());
+        assert_eq!(doc.text(), "(a b)");
+    }
+}
+
+#[cfg(test)]
+mod sexpr_recover {
+    use crate::gast::diff::Step;
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::recover::{parse_recovering, DiagnosticKind};
+
+    #[test]
+    fn reports_every_error() {
+        let input = "(a (b c]\n)) (d(e) . f g)\n\"x\\q\" (h";
+        let r = parse_recovering(input, Handle::new("<test>".to_string()));
+        let forms: Vec<_> = r.forms.iter().map(|x| x.to_string()).collect();
+        assert_eq!(forms, ["(a (b c))", "(d (e) . f)", "(h)"]);
+        let kinds: Vec<_> = r.diagnostics.iter().map(|x| x.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                DiagnosticKind::UnexpectedCloser(']'),
+                DiagnosticKind::MissingWhitespace,
+                DiagnosticKind::AfterPairRight,
+                DiagnosticKind::UnexpectedInput,
+                DiagnosticKind::UnexpectedInput,
+                DiagnosticKind::Unclosed('('),
+            ]
+        );
+        let lines: Vec<_> = r.diagnostics.iter().map(|x| x.location.line).collect();
+        assert_eq!(lines, [1, 2, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn links_error_nodes() {
+        let input =
+            "(a '99999999999999999999) (b . 99999999999999999999u (c 99999999999999999999)) (d (e";
+        let r = parse_recovering(input, Handle::new("<test>".to_string()));
+        let forms: Vec<_> = r.forms.iter().map(|x| x.to_string()).collect();
+        assert_eq!(forms, ["(a (quote nil))", "(b . nil)", "(d (e))"]);
+        let nodes: Vec<_> = r.diagnostics.iter().map(|x| x.node.clone()).collect();
+        assert_eq!(
+            nodes,
+            [
+                Some((0, vec![Step::Elem(1), Step::Elem(1)])),
+                Some((1, vec![Step::Tail])),
+                None,
+                None,
+                Some((2, vec![Step::Elem(1)])),
+                Some((2, vec![])),
+            ]
+        );
+        let e = r.error_at(1, &[Step::Tail]).unwrap();
+        assert_eq!(e.kind, DiagnosticKind::InvalidLiteral);
+        assert_eq!(&input[e.span.clone()], "99999999999999999999u");
+        assert!(r.error_at(0, &[Step::Elem(0)]).is_none());
+    }
+}
+
+#[cfg(test)]
+mod gast_borrowed {
+    use std::borrow::Cow;
+
+    use crate::gast::borrowed::{ConstantRef, GAstRef};
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::fast;
+
+    #[test]
+    fn to_owned_matches_parse() {
+        let input = "(a \"plain\" \"esc\\n\" . 'b) [1 -2 3u 1.5 true nil] `(x ,@y)";
+        let path = Handle::new("<test>".to_string());
+        let borrowed = fast::parse_borrowed(input, path.clone()).unwrap();
+        let owned: Vec<_> = borrowed.iter().map(GAstRef::to_owned).collect();
+        let expected = fast::parse(input, path).unwrap();
+        assert_eq!(format!("{:?}", owned), format!("{:?}", expected));
+        let shown: Vec<_> = borrowed.iter().map(|x| x.to_string()).collect();
+        let expected: Vec<_> = expected.iter().map(|x| x.to_string()).collect();
+        assert_eq!(shown, expected);
+
+        let list = borrowed[0].get_list().unwrap();
+        match (&list.0[0], &list.0[1], &list.0[2]) {
+            (
+                GAstRef::Const(ConstantRef::Sym(a)),
+                GAstRef::Const(ConstantRef::Str(Cow::Borrowed(plain))),
+                GAstRef::Const(ConstantRef::Str(Cow::Owned(esc))),
+            ) => {
+                assert!(std::ptr::eq(a.0.as_ptr(), input[1..].as_ptr()));
+                assert_eq!(*plain, "plain");
+                assert_eq!(esc, "esc\n");
+            }
+            x => panic!("{:?}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod gast_arena {
+    use crate::gast::arena::{ArenaNode, GAstArena};
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::fast;
+
+    #[test]
+    fn round_trip() {
+        let input = "(a \"s\\n\" . 'b) [1 -2 3u 1.5 true nil] `(x ,@y) ()";
+        let path = Handle::new("<test>".to_string());
+        let expected = fast::parse(input, path.clone()).unwrap();
+        let mut arena = GAstArena::new();
+        let roots = fast::parse_into(input, path, &mut arena).unwrap();
+        let back: Vec<_> = roots.iter().map(|&x| arena.to_gast(x)).collect();
+        assert_eq!(format!("{:?}", back), format!("{:?}", expected));
+
+        let first = arena.children(roots[0]);
+        assert_eq!(first.len(), 2);
+        match arena[first[0]] {
+            ArenaNode::Sym(name, loc) => {
+                assert_eq!(arena.str(name), "a");
+                assert_eq!(arena.location(&loc).colum, 2);
+            }
+            x => panic!("{:?}", x),
+        }
+        assert!(arena.pair_right(roots[0]).is_some());
+
+        let mut copy = GAstArena::new();
+        let copied: Vec<_> = expected.iter().map(|x| copy.insert(x)).collect();
+        let back: Vec<_> = copied.iter().map(|&x| copy.to_gast(x)).collect();
+        assert_eq!(format!("{:?}", back), format!("{:?}", expected));
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod handle_sync {
+    use crate::gast::{symbol::Symbol, GAst, Record};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn trees_are_send_sync() {
+        assert_send_sync::<GAst>();
+        assert_send_sync::<Symbol>();
+        assert_send_sync::<Record>();
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod sexpr_parallel {
+    use crate::error::CompilerError;
+    use crate::syntax::sexpr::parallel::parse_files_with;
+
+    #[test]
+    fn keeps_order_and_collects_errors() {
+        let dir = std::env::temp_dir().join(format!("sexpr_parallel_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = vec![];
+        for i in 0..20 {
+            let path = dir.join(format!("{}.sexpr", i));
+            let text = if i == 7 {
+                "(a b".to_string()
+            } else {
+                format!("(file {}) x", i)
+            };
+            std::fs::write(&path, text).unwrap();
+            paths.push(path.to_str().unwrap().to_owned());
+        }
+        paths.push(dir.join("missing").to_str().unwrap().to_owned());
+
+        let r = parse_files_with(&paths, 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(r.results.len(), 21);
+        for (i, (path, x)) in r.results.iter().enumerate() {
+            assert_eq!(path, &paths[i]);
+            if i != 7 && i != 20 {
+                assert_eq!(x.as_ref().unwrap()[0].to_string(), format!("(file {})", i));
+            }
+        }
+        let errors: Vec<_> = r.errors().map(|(path, _)| path).collect();
+        assert_eq!(errors, [&paths[7], &paths[20]]);
+        match r.into_result().unwrap_err().as_slice() {
+            [(_, CompilerError::ParseError(_)), (_, CompilerError::FileOpenError(..))] => {}
+            x => panic!("{:?}", x),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod file_mmap {
+    use crate::error::CompilerError;
+    use crate::gast::Handle;
+    use crate::syntax::mmap::parse_chunks;
+    use crate::syntax::{mexpr, sexpr, Syntax};
+
+    #[test]
+    fn matches_file_parse() {
+        let dir = std::env::temp_dir().join(format!("file_mmap_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sexpr_path = dir.join("a.sexpr");
+        std::fs::write(&sexpr_path, "(a \"é\"\n  'b) ; c\n[1 2.5]").unwrap();
+        let mexpr_path = dir.join("a.mexpr");
+        std::fs::write(&mexpr_path, "if[cond; \"é\";\n  else]").unwrap();
+        let empty_path = dir.join("empty");
+        std::fs::write(&empty_path, "").unwrap();
+        let bad_path = dir.join("bad");
+        std::fs::write(&bad_path, b"(a \xff)").unwrap();
+
+        let path = sexpr_path.to_str().unwrap();
+        let r = sexpr::file_parse_mmap(path).unwrap();
+        assert_eq!(
+            format!("{:?}", r),
+            format!("{:?}", sexpr::file_parse(path).unwrap())
+        );
+        let path = mexpr_path.to_str().unwrap();
+        let r = mexpr::file_parse_mmap(path).unwrap();
+        assert_eq!(
+            format!("{:?}", r),
+            format!("{:?}", mexpr::file_parse(path).unwrap())
+        );
+        assert!(sexpr::file_parse_mmap(empty_path.to_str().unwrap())
+            .unwrap()
+            .is_empty());
+        assert!(sexpr::file_parse_mmap(bad_path.to_str().unwrap()).is_err());
+        assert!(sexpr::file_parse_mmap(dir.join("missing").to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chunks_parse_like_the_whole_text() {
+        let path = || Handle::new("<test>".to_string());
+        let sexpr_input = "(a \"é\nb\"\n  'c) ; (\n'\n; x\nd `\n,@\n(e\n . f) \"\n\" g\n[1\n2.5]";
+        let chunked = parse_chunks(
+            sexpr_input.as_bytes(),
+            Syntax::Sexpr,
+            1,
+            path(),
+            sexpr::parse,
+        );
+        assert_eq!(
+            format!("{:?}", chunked.unwrap()),
+            format!("{:?}", sexpr::parse(sexpr_input, path()).unwrap())
+        );
+        let mexpr_input = "f\n(x) # c\ng\n# (\n\n[y; z]\nh(\"é\n\")\n  {1, 2}\nk\n\u{a0}(w)\nv";
+        let chunked = parse_chunks(
+            mexpr_input.as_bytes(),
+            Syntax::Mexpr,
+            1,
+            path(),
+            mexpr::parse,
+        );
+        assert_eq!(
+            format!("{:?}", chunked.unwrap()),
+            format!("{:?}", mexpr::parse(mexpr_input, path()).unwrap())
+        );
+
+        let bad = "(a)\n(b\n(c)\n";
+        let chunked = parse_chunks(bad.as_bytes(), Syntax::Sexpr, 1, path(), sexpr::parse);
+        match chunked {
+            Err(CompilerError::ParseError(e)) => {
+                assert_eq!(e, sexpr::parse(bad, path()).unwrap_err())
+            }
+            x => panic!("{:?}", x),
+        }
+        let chunked = parse_chunks(b"(a)\n(b \xff)\n", Syntax::Sexpr, 1, path(), sexpr::parse);
+        match chunked {
+            Err(CompilerError::FileOpenError(_, e)) => {
+                assert_eq!(e.to_string(), "invalid UTF-8 at byte 7")
+            }
+            x => panic!("{:?}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod gast_binary {
+    use crate::gast::binary::{decode, encode, DecodeError, EncodeError, MAX_DEPTH};
+    use crate::gast::constant::Constant;
+    use crate::gast::list::List;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    const INPUT: &str = "(define (f x) (g x \"s\" \"s\"))
+(1 -1 -9223372036854775808 18446744073709551615u 2.5 true false nil . x)
+[() 'x `(a ,@b)]";
+
+    fn symbols(ast: &GAst, out: &mut Vec<Handle<crate::gast::symbol::Symbol>>) {
+        match ast {
+            GAst::Const(Constant::Sym(x)) => out.push(x.clone()),
+            GAst::Const(_) => {}
+            GAst::List(x) => {
+                x.0.iter().chain(x.1.iter()).for_each(|x| symbols(x, out));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let forms = fast::parse(INPUT, Handle::new("<test>".to_string())).unwrap();
+        let bytes = encode(&forms, true).unwrap();
+        let back = decode(&bytes).unwrap();
+        assert_eq!(format!("{:?}", back), format!("{:?}", forms));
+
+        let mut syms = vec![];
+        back.iter().for_each(|x| symbols(x, &mut syms));
+        let x: Vec<_> = syms.iter().filter(|s| s.0.as_str() == "x").collect();
+        assert!(Handle::ptr_eq(&x[0].0, &x[1].0));
+        assert!(Handle::ptr_eq(&x[0].1.path, &syms[0].1.path));
+
+        let short = encode(&forms, false).unwrap();
+        assert!(short.len() < bytes.len());
+        let back = decode(&short).unwrap();
+        assert_eq!(back, forms);
+        let mut syms = vec![];
+        back.iter().for_each(|x| symbols(x, &mut syms));
+        assert!(Handle::ptr_eq(&syms[2], &syms[4]));
+    }
+
+    #[test]
+    fn keeps_shared_lists() {
+        let inner = fast::one_unit_parse("(a (b))", Handle::new("<test>".to_string())).unwrap();
+        let list = |x: Vec<GAst>| GAst::List(Handle::new(List::new(x, None)));
+        let forms = [list(vec![inner.clone(), list(vec![inner.clone()]), inner])];
+        let back = decode(&encode(&forms, false).unwrap()).unwrap();
+        assert_eq!(back, forms);
+        let items = &back[0].get_list().unwrap().0;
+        let nested = items[1].get_list().unwrap().0[0].get_list().unwrap();
+        assert!(Handle::ptr_eq(&items[0].get_list().unwrap(), &nested));
+        assert!(Handle::ptr_eq(
+            &items[0].get_list().unwrap(),
+            &items[2].get_list().unwrap()
+        ));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n: usize| {
+            let text = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+            fast::parse(&text, Handle::new("<test>".to_string())).unwrap()
+        };
+        let forms = nested(MAX_DEPTH);
+        assert_eq!(decode(&encode(&forms, false).unwrap()).unwrap(), forms);
+        assert_eq!(encode(&nested(300), false), Err(EncodeError::TooDeep));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let forms = fast::parse(INPUT, Handle::new("<test>".to_string())).unwrap();
+        let bytes = encode(&forms, true).unwrap();
+        for i in 0..bytes.len() {
+            assert!(decode(&bytes[..i]).is_err());
+        }
+        assert_eq!(decode(b"JSON\x01\x00"), Err(DecodeError::BadMagic));
+        assert_eq!(
+            decode(b"GAST\x03\x00"),
+            Err(DecodeError::UnsupportedVersion(3))
+        );
+        assert_eq!(
+            decode(b"GAST\x01\x00"),
+            Err(DecodeError::UnsupportedVersion(1))
+        );
+        let nested = |n: usize| {
+            let mut bytes = b"GAST\x02\x00\x00\x00\x01".to_vec();
+            bytes.extend([9, 1].repeat(n));
+            bytes.extend([9, 0]);
+            decode(&bytes)
+        };
+        assert!(nested(MAX_DEPTH - 1).is_ok());
+        assert_eq!(nested(100_000), Err(DecodeError::TooDeep));
+        assert_eq!(
+            decode(b"GAST\x02\x00\x00\x00\x01\x07\x00"),
+            Err(DecodeError::InvalidIndex(0))
+        );
+    }
+}
+
+#[cfg(test)]
+mod gast_csexp {
+    use crate::gast::csexp::{
+        from_canonical, from_transport, to_canonical, to_transport, CsexpError, MAX_DEPTH,
+    };
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::fast;
+
+    #[test]
+    fn canonical_round_trip() {
+        let input = "(foo \"bar\" (-12 12u 1.5 true nil) (a . b) () é)";
+        let ast = fast::one_unit_parse(input, Handle::new("<test>".to_string())).unwrap();
+        let bytes = to_canonical(&ast).unwrap();
+        assert_eq!(
+            bytes,
+            &b"(3:foo[3:str]3:bar([3:int]3:-12[4:uint]2:12[5:float]8:?\xf8\0\0\0\0\0\0\
+             [4:bool]4:true[3:nil]0:)(1:a[3:dot]0:1:b)()2:\xc3\xa9)"[..]
+        );
+        let back = from_canonical(&bytes).unwrap();
+        assert_eq!(back, ast);
+        assert_eq!(to_canonical(&back).unwrap(), bytes);
+
+        let transport = to_transport(&ast).unwrap();
+        assert_eq!(from_transport(&transport).unwrap(), ast);
+        let wrapped: String = transport
+            .chars()
+            .enumerate()
+            .flat_map(|(i, c)| if i % 10 == 9 { vec![c, '\n'] } else { vec![c] })
+            .collect();
+        assert_eq!(from_transport(&wrapped).unwrap(), ast);
+        assert_eq!(
+            to_transport(&from_canonical(b"3:abc").unwrap()).unwrap(),
+            "{MzphYmM=}"
+        );
+    }
+
+    #[test]
+    fn rejects_non_canonical() {
+        assert_eq!(
+            from_canonical(b"03:abc"),
+            Err(CsexpError::NonCanonicalLength(0))
+        );
+        assert_eq!(
+            from_canonical(b"[3:int]2:07"),
+            Err(CsexpError::InvalidAtom(7))
+        );
+        assert_eq!(
+            from_canonical(b"[3:int]2:+7"),
+            Err(CsexpError::InvalidAtom(7))
+        );
+        assert_eq!(
+            from_canonical(b"(1:a )"),
+            Err(CsexpError::UnexpectedByte(4))
+        );
+        assert_eq!(
+            from_canonical(b"([3:dot]0:1:a)"),
+            Err(CsexpError::UnexpectedByte(1))
+        );
+        assert_eq!(
+            from_canonical(b"(1:a[3:dot]0:1:b1:c)"),
+            Err(CsexpError::UnexpectedByte(4))
+        );
+        assert_eq!(
+            from_canonical(b"[10:text/plain]2:hi"),
+            Err(CsexpError::InvalidAtom(15))
+        );
+        assert_eq!(
+            from_canonical(b"[4:text]2:\xff\xfe"),
+            Err(CsexpError::InvalidAtom(8))
+        );
+        assert_eq!(from_canonical(b"1:a1:b"), Err(CsexpError::TrailingBytes(3)));
+        assert_eq!(from_canonical(b"(1:a"), Err(CsexpError::UnexpectedEnd));
+        assert_eq!(from_transport("{MzphYmM}"), Err(CsexpError::InvalidBase64));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |n: usize| {
+            let text = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+            fast::one_unit_parse(&text, Handle::new("<test>".to_string())).unwrap()
+        };
+        let deep = nested(MAX_DEPTH);
+        assert_eq!(from_canonical(&to_canonical(&deep).unwrap()).unwrap(), deep);
+        assert_eq!(from_transport(&to_transport(&deep).unwrap()).unwrap(), deep);
+        let deeper = nested(MAX_DEPTH + 1);
+        assert_eq!(to_canonical(&deeper), Err(CsexpError::TooDeep(MAX_DEPTH)));
+        assert_eq!(to_transport(&deeper), Err(CsexpError::TooDeep(MAX_DEPTH)));
+
+        let bytes = |n: usize| [vec![b'('; n], vec![b')'; n]].concat();
+        assert!(from_canonical(&bytes(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            from_canonical(&bytes(MAX_DEPTH + 1)),
+            Err(CsexpError::TooDeep(MAX_DEPTH))
+        );
+        assert_eq!(
+            from_canonical(&bytes(100_000)),
+            Err(CsexpError::TooDeep(MAX_DEPTH))
+        );
+    }
+}
+
+#[cfg(test)]
+mod gast_hash {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::gast::constant::Constant;
+    use crate::gast::list::List;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    fn parse(input: &str) -> Vec<GAst> {
+        fast::parse(input, Handle::new("<test>".to_string())).unwrap()
+    }
+
+    #[test]
+    fn structural_equality() {
+        let a = parse("(f (g x) \"s\" 1.5 . y)");
+        let b = parse("\n\n   (f (g x) \"s\" 1.5 . y)");
+        assert_eq!(a, b);
+        assert_eq!(a[0].structural_hash(), b[0].structural_hash());
+        // cached now; still equal to a fresh copy
+        assert_eq!(
+            a[0],
+            GAst::List(Handle::new(a[0].get_list().unwrap().as_ref().clone()))
+        );
+
+        // a stale cache changes neither comparisons nor clones
+        let mut list = List::clone(&a[0].get_list().unwrap());
+        list.structural_hash();
+        list.0.pop();
+        let fresh = List::new(list.0.clone(), list.1.clone());
+        assert_eq!(list, fresh);
+        assert_eq!(list.clone().structural_hash(), fresh.structural_hash());
+
+        let nan = GAst::Const(Constant::Float(f64::NAN));
+        assert_eq!(nan, nan.clone());
+        assert_ne!(
+            GAst::Const(Constant::Float(0.0)),
+            GAst::Const(Constant::Float(-0.0))
+        );
+
+        let forms = parse("(a) b (a) \"b\" 2 1 (a . b) (a b) nil b");
+        let mut counts = HashMap::new();
+        for x in &forms {
+            *counts.entry(x).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&forms[0]], 2);
+        assert_eq!(counts[&forms[1]], 2);
+        assert_eq!(counts.len(), 8);
+
+        let sorted: Vec<String> = forms
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            sorted,
+            ["nil", "1", "2", "b", "b", "(a)", "(a . b)", "(a b)"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod gast_hashcons {
+    use crate::gast::hashcons::HashConsTable;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    fn lists<'a>(ast: &'a GAst, out: &mut Vec<&'a Handle<crate::gast::list::List>>) {
+        if let GAst::List(x) = ast {
+            out.push(x);
+            x.0.iter().chain(x.1.iter()).for_each(|x| lists(x, out));
+        }
+    }
+
+    #[test]
+    fn shares_equal_subtrees() {
+        let input = "(f (g x) (g x) (h (g x) . (g x))) (g x) (h (g x) . (g x))";
+        let forms = fast::parse(input, Handle::new("<test>".to_string())).unwrap();
+        let mut table = HashConsTable::new();
+        let interned: Vec<_> = forms.iter().map(|x| table.intern(x)).collect();
+        assert_eq!(interned, forms);
+        // (g x), (h (g x) g x), (f ...)
+        assert_eq!(table.len(), 3);
+
+        let mut all = vec![];
+        interned.iter().for_each(|x| lists(x, &mut all));
+        let gx: Vec<_> = all.iter().filter(|x| x.to_string() == "(g x)").collect();
+        assert_eq!(gx.len(), 7);
+        assert!(gx.iter().all(|x| Handle::ptr_eq(x, gx[0])));
+        assert!(
+            matches!((&interned[0], &interned[2]), (GAst::List(a), GAst::List(b))
+            if Handle::ptr_eq(&a.0[3].get_list().unwrap(), b))
+        );
+
+        let again = table.intern(&forms[1]);
+        assert!(Handle::ptr_eq(
+            &again.get_list().unwrap(),
+            &interned[1].get_list().unwrap()
+        ));
+        let built = table.list(vec![forms[1].get_list().unwrap().0[0].clone()], None);
+        assert_eq!(table.len(), 4);
+        assert!(Handle::ptr_eq(&built, &table.list(built.0.clone(), None)));
+    }
+}
+
+#[cfg(test)]
+mod gast_diff {
+    use crate::gast::diff::{apply_patch, diff, edit_distance, PatchError};
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::format::Style;
+    use crate::syntax::sexpr::fast;
+    use crate::syntax::sexpr::printer::{to_pretty, to_source};
+
+    fn parse(input: &str) -> GAst {
+        fast::one_unit_parse(input, Handle::new("<test>".to_string())).unwrap()
+    }
+
+    #[test]
+    fn printer_round_trip() {
+        let input =
+            "(a \"q\\\"\\\\\\n\\t\" -1 2u 1.5 -0.0 100000000000000000000.0 true nil () (b . c))";
+        let ast = parse(input);
+        assert_eq!(parse(&to_source(&ast)), ast);
+        assert_eq!(to_source(&ast), input);
+    }
+
+    #[test]
+    fn pretty_breaks_long_lists() {
+        let ast = parse("(define (f x) (if (> x 0) '(a b) (g x `(y ,x ,@z))))");
+        let narrow = Style {
+            width: 24,
+            ..Style::default()
+        };
+        assert_eq!(
+            to_pretty(&ast, &Style::default()),
+            "(define (f x) (if (> x 0) '(a b) (g x `(y ,x ,@z))))"
+        );
+        let expected = "\
+(define (f x)
+  (if (> x 0)
+    '(a b)
+    (g x `(y ,x ,@z))))";
+        assert_eq!(to_pretty(&ast, &narrow), expected);
+        assert_eq!(parse(&to_pretty(&ast, &Style { width: 0, ..narrow })), ast);
+        assert_eq!(
+            to_pretty(&parse("(unquote @x)"), &Style::default()),
+            "(unquote @x)"
+        );
+
+        // a list breaks exactly when its flat form is one column too wide
+        let inputs = [
+            "(a \"q\\\"\\n\" 'b . c)",
+            "(f `(,x ,@y) -1.5 2u)",
+            "(\"ü\" ())",
+        ];
+        for input in inputs.iter() {
+            let ast = parse(input);
+            let flat = to_pretty(&ast, &Style::default());
+            let width = flat.chars().count();
+            let fits = Style {
+                width,
+                ..Style::default()
+            };
+            assert_eq!(to_pretty(&ast, &fits), flat);
+            let over = Style {
+                width: width - 1,
+                ..Style::default()
+            };
+            assert_ne!(to_pretty(&ast, &over), flat);
+        }
+    }
+
+    #[test]
+    fn diff_and_apply() {
+        let cases = [
+            ("(a b c)", "(a b c)"),
+            ("(a b c)", "(c a b)"),
+            ("(a (b 1) c)", "(a (b 2) c d)"),
+            ("(f (x y) z)", "(f z (x y) w)"),
+            ("(a . b)", "(a . c)"),
+            ("(a . b)", "(a b)"),
+            ("(a b)", "(a . b)"),
+            ("x", "(x)"),
+            ("(1 2 3 4 5 6)", "(6 5 4 3 2 1)"),
+            ("((a) (b) (c))", "((c) (a x) (b))"),
+            ("(define (f x) (+ x 1))", "(define (f x y) (* (+ x 1) y))"),
+        ];
+        for (a, b) in cases.iter() {
+            let (a, b) = (parse(a), parse(b));
+            let patch = diff(&a, &b);
+            assert_eq!(apply_patch(&a, &patch).unwrap(), b, "{}", patch);
+            assert_eq!(patch.is_empty(), a == b);
+        }
+
+        let patch = diff(&parse("(a b c)"), &parse("(c a b)"));
+        assert_eq!(patch.to_string(), "move / [2] -> [0]\n");
+        let patch = diff(&parse("(a (b 1) c)"), &parse("(a (b 2) c d)"));
+        assert_eq!(patch.to_string(), "insert / [3]: d\nreplace /1/1: 1 => 2\n");
+        assert_eq!(patch.cost(), 2);
+
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut rand = |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let mut tree = |depth: u32| {
+            let mut s = String::new();
+            let mut open = 0;
+            for _ in 0..12 {
+                match rand(6) {
+                    0 if open < depth => {
+                        s.push_str(" (");
+                        open += 1;
+                    }
+                    1 if open > 0 => {
+                        s.push(')');
+                        open -= 1;
+                    }
+                    _ => s.push_str([" a", " b", " 1", " \"s\""][rand(4) as usize]),
+                }
+            }
+            format!("(x{}{})", s, ")".repeat(open as usize))
+        };
+        for _ in 0..500 {
+            let (a, b, c) = (parse(&tree(3)), parse(&tree(3)), parse(&tree(3)));
+            let patch = diff(&a, &b);
+            assert_eq!(apply_patch(&a, &patch).unwrap(), b, "{}", patch);
+            let ab = edit_distance(&a, &b);
+            assert_eq!(ab, edit_distance(&b, &a));
+            assert!(edit_distance(&a, &c) <= ab + edit_distance(&b, &c));
+            assert_eq!(ab == 0, a == b);
+        }
+
+        let stale = parse("(a (b 3) c)");
+        assert_eq!(apply_patch(&stale, &patch), Err(PatchError::Mismatch(1)));
+        assert_eq!(
+            apply_patch(&parse("(a)"), &patch),
+            Err(PatchError::IndexOutOfRange(0))
+        );
+    }
+
+    #[test]
+    fn tree_edit_distance() {
+        // deleting a list node keeps its children in place
+        let distances = [
+            ("(a b c)", "(a b c)", 0),
+            ("(a . b)", "(a b)", 1),
+            ("(a b c)", "(a c)", 1),
+            ("x", "(x)", 1),
+            ("(a (b c) d)", "(a b c d)", 1),
+            ("(f (g a) b)", "(f a b)", 2),
+            ("(a b)", "(b a)", 2),
+            ("(a 1 \"s\")", "(a 1u s)", 2),
+            ("(a (b (c d)))", "(a b c d)", 2),
+            ("(a b c)", "((a b c))", 1),
+            ("((a b) (c d))", "(a b c d)", 2),
+            ("()", "(a b)", 2),
+        ];
+        for (a, b, d) in distances.iter() {
+            let (a_ast, b_ast) = (parse(a), parse(b));
+            assert_eq!(edit_distance(&a_ast, &b_ast), *d, "{} {}", a, b);
+            assert_eq!(edit_distance(&b_ast, &a_ast), *d, "{} {}", b, a);
+        }
+    }
+}
+
+#[cfg(test)]
+mod gast_visit {
+    use crate::gast::constant::Constant;
+    use crate::gast::diff::Step;
+    use crate::gast::list::List;
+    use crate::gast::symbol::{Location, Symbol};
+    use crate::gast::visit::{rewrite_bottom_up, rewrite_top_down, Flow, Fold, Visit, VisitMut};
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    fn parse(input: &str) -> GAst {
+        fast::one_unit_parse(input, Handle::new("<test>".to_string())).unwrap()
+    }
+
+    fn int(x: &GAst) -> Option<i64> {
+        x.get_const().and_then(|x| x.get_int())
+    }
+
+    #[derive(Default)]
+    struct Collect(Vec<String>);
+
+    impl Visit for Collect {
+        fn enter_const(&mut self, c: &Constant, path: &[Step]) -> Flow {
+            self.0.push(format!("{}@{:?}", c, path));
+            if c.to_string() == "stop" {
+                Flow::Stop
+            } else {
+                Flow::Continue
+            }
+        }
+
+        fn enter_list(&mut self, list: &List, _path: &[Step]) -> Flow {
+            if list.0.first().map(|x| x.to_string()) == Some("quote".to_string()) {
+                Flow::Skip
+            } else {
+                Flow::Continue
+            }
+        }
+
+        fn leave_list(&mut self, list: &List, _path: &[Step]) -> Flow {
+            self.0.push(format!("{}", list));
+            Flow::Continue
+        }
+    }
+
+    struct Depth;
+
+    impl Fold for Depth {
+        type Output = usize;
+
+        fn fold_const(&mut self, _c: &Constant, _path: &[Step]) -> usize {
+            0
+        }
+
+        fn fold_list(
+            &mut self,
+            _: &List,
+            elems: Vec<usize>,
+            tail: Option<usize>,
+            _: &[Step],
+        ) -> usize {
+            1 + elems.into_iter().chain(tail).max().unwrap_or(0)
+        }
+    }
+
+    struct Double;
+
+    impl VisitMut for Double {
+        fn enter_const(&mut self, node: &mut GAst, _path: &[Step]) -> Flow {
+            if let Some(x) = int(node) {
+                *node = GAst::Const(Constant::Int(x * 2));
+            }
+            Flow::Continue
+        }
+    }
+
+    #[test]
+    fn visit_and_fold() {
+        let ast = parse("(a 'b (c . d) stop e)");
+        let mut v = Collect::default();
+        assert_eq!(v.visit(&ast), Flow::Stop);
+        assert_eq!(
+            v.0,
+            [
+                "a@[Elem(0)]",
+                "c@[Elem(2), Elem(0)]",
+                "d@[Elem(2), Tail]",
+                "(c . d)",
+                "stop@[Elem(3)]"
+            ]
+        );
+        assert_eq!(Depth.fold(&ast), 2);
+        assert_eq!(Depth.fold(&parse("(((x)) y)")), 3);
+    }
+
+    #[test]
+    fn rewrites_share_unchanged_lists() {
+        let ast = parse("((a 1) (b c) (d (2 . 3)))");
+        let mut doubled = ast.clone();
+        Double.visit_mut(&mut doubled);
+        assert_eq!(doubled, parse("((a 2) (b c) (d (4 . 6)))"));
+        let (old, new) = (ast.get_list().unwrap(), doubled.get_list().unwrap());
+        assert!(Handle::ptr_eq(
+            &old.0[1].get_list().unwrap(),
+            &new.0[1].get_list().unwrap()
+        ));
+        assert!(!Handle::ptr_eq(
+            &old.0[0].get_list().unwrap(),
+            &new.0[0].get_list().unwrap()
+        ));
+
+        let folded = rewrite_bottom_up(&parse("(+ 1 (+ 2 3) x)"), &mut |x| {
+            let list = x.get_list()?;
+            if list.0.first()?.to_string() != "+" {
+                return None;
+            }
+            let args: Option<Vec<_>> = list.0[1..].iter().map(int).collect();
+            Some(GAst::Const(Constant::Int(args?.iter().sum())))
+        });
+        assert_eq!(folded, parse("(+ 1 5 x)"));
+
+        let unchanged = rewrite_bottom_up(&ast, &mut |_| None);
+        assert!(Handle::ptr_eq(
+            &unchanged.get_list().unwrap(),
+            &ast.get_list().unwrap()
+        ));
+
+        // top-down does not look inside replacements
+        let r = rewrite_top_down(&parse("(a (a b))"), &mut |x| {
+            if x.to_string() == "(a b)" {
+                Some(parse("(a b a)"))
+            } else if x.to_string() == "a" {
+                Some(parse("z"))
+            } else {
+                None
+            }
+        });
+        assert_eq!(r, parse("(z (a b a))"));
+    }
+
+    #[test]
+    fn relocated_symbols_are_changes() {
+        let ast = parse("(a (b))");
+        let moved = rewrite_bottom_up(&ast, &mut |x| match x {
+            GAst::Const(Constant::Sym(s)) if s.0.as_str() == "b" => {
+                Some(GAst::Const(Constant::Sym(Handle::new(Symbol::new("b")))))
+            }
+            _ => None,
+        });
+        let b = moved.get_list().unwrap().0[1].get_list().unwrap().0[0].clone();
+        match b {
+            GAst::Const(Constant::Sym(s)) => assert_eq!(s.1, Location::default()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod gast_zipper {
+    use crate::gast::constant::Constant;
+    use crate::gast::diff::Step;
+    use crate::gast::symbol::{Location, Symbol};
+    use crate::gast::zipper::Zipper;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    fn parse(input: &str) -> GAst {
+        fast::one_unit_parse(input, Handle::new("<test>".to_string())).unwrap()
+    }
+
+    #[test]
+    fn navigate_and_edit() {
+        let ast = parse("(a (b (c d)) (e f) . g)");
+        let mut z = Zipper::new(ast.clone());
+        assert!(!z.up() && !z.left() && !z.right());
+        assert!(z.down() && z.right() && z.down() && z.right() && z.down());
+        assert_eq!(z.focus().to_string(), "c");
+        assert_eq!(z.path(), [Step::Elem(1), Step::Elem(1), Step::Elem(0)]);
+        z.replace(parse("x"));
+        assert!(z.right() && !z.right());
+        assert!(z.insert_left(parse("1")) && z.insert_right(parse("2")));
+        assert_eq!(z.remove().unwrap().to_string(), "d");
+        assert_eq!(z.focus().to_string(), "2");
+        let edited = z.root();
+        assert_eq!(edited, parse("(a (b (x 1 2)) (e f) . g)"));
+        let (old, new) = (ast.get_list().unwrap(), edited.get_list().unwrap());
+        assert!(Handle::ptr_eq(
+            &old.0[2].get_list().unwrap(),
+            &new.0[2].get_list().unwrap()
+        ));
+
+        let mut z = Zipper::new(ast.clone());
+        z.down();
+        assert!(z.right() && z.right() && z.right());
+        assert_eq!(z.path(), [Step::Tail]);
+        assert!(!z.insert_left(parse("y")) && !z.splice(vec![parse("y")]));
+        z.remove();
+        assert_eq!(z.focus().to_string(), "(e f)");
+        assert!(z.splice(vec![parse("p"), parse("q")]));
+        assert_eq!(z.root(), parse("(a (b (c d)) p q)"));
+
+        let mut z = Zipper::new(parse("((a))"));
+        z.down();
+        z.down();
+        z.remove();
+        assert_eq!(z.focus().to_string(), "()");
+        assert!(Zipper::new(ast.clone()).root() == ast);
+    }
+
+    #[test]
+    fn slurp_and_barf() {
+        let mut z = Zipper::new(parse("((a) b c)"));
+        z.down();
+        assert!(z.slurp_forward() && z.slurp_forward() && !z.slurp_forward());
+        assert_eq!(z.clone().root(), parse("((a b c))"));
+        assert!(z.barf_forward() && z.barf_forward());
+        assert!(z.right());
+        assert_eq!(z.focus().to_string(), "b");
+        assert_eq!(z.root(), parse("((a) b c)"));
+    }
+
+    #[test]
+    fn relocated_symbols_are_stored() {
+        let mut z = Zipper::new(parse("(a b)"));
+        assert!(z.down() && z.right());
+        z.replace(GAst::Const(Constant::Sym(Handle::new(Symbol::new("b")))));
+        match &z.root().get_list().unwrap().0[1] {
+            GAst::Const(Constant::Sym(s)) => assert_eq!(s.1, Location::default()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod gast_query {
+    use crate::gast::diff::Step;
+    use crate::gast::{GAst, Handle};
+    use crate::syntax::sexpr::fast;
+
+    fn parse(input: &str) -> GAst {
+        fast::one_unit_parse(input, Handle::new("<test>".to_string())).unwrap()
+    }
+
+    fn query(ast: &GAst, q: &str) -> Vec<String> {
+        let r = ast.query(q).unwrap();
+        r.iter().map(|m| m.node.to_string()).collect()
+    }
+
+    #[test]
+    fn selects_nodes() {
+        let config = parse(
+            "(config
+               (server (name \"a\") (port 80) (tags web \"x]\"))
+               (group (server (name \"b\") (port 8080)))
+               (server (name \"c\") (port 443) . extra)
+               (limits 1 2.5 3u -4))",
+        );
+        assert_eq!(query(&config, "//server/port/#1"), ["80", "8080", "443"]);
+        assert_eq!(query(&config, "/config/server/name/#-1"), ["a", "c"]);
+        assert_eq!(
+            query(&config, "/config/server/#1"),
+            ["(name a)", "(name c)"]
+        );
+        assert_eq!(query(&config, "//port[#1 >= 443]/#1"), ["8080", "443"]);
+        assert_eq!(query(&config, "//server/name[#1 != \"a\"]/#1"), ["b", "c"]);
+        assert_eq!(query(&config, "//limits/@const[> 1]"), ["2.5", "3"]);
+        assert_eq!(query(&config, "//limits/@int"), ["1", "-4"]);
+        assert_eq!(query(&config, "//tags/*[= \"x]\"]"), ["x]"]);
+        assert_eq!(query(&config, "/config/#-1/#-1"), ["-4"]);
+        assert_eq!(query(&config, "/config/#3/@sym"), ["server", "extra"]);
+        assert_eq!(query(&config, "//group//port"), ["(port 8080)"]);
+        // descendants of nested matches are not repeated
+        assert_eq!(query(&config, "//*//port").len(), 3);
+        assert_eq!(query(&config, "/config").len(), 1);
+
+        let r = config.query("//group//name/#1").unwrap();
+        assert_eq!(
+            r[0].path,
+            [Step::Elem(2), Step::Elem(1), Step::Elem(1), Step::Elem(1)]
+        );
+
+        for bad in [
+            "",
+            "port",
+            "//",
+            "//@foo",
+            "//a[#x = 1]",
+            "//a[= 1",
+            "//a[~ 1]",
+            "//a[= (1)]",
+        ] {
+            assert!(config.query(bad).is_err(), "{}", bad);
+        }
+        let e = config.query("/a[#1 = 99999999999999999999]").unwrap_err();
+        assert_eq!(e.message, "integer literal out of range");
+    }
+
+    #[test]
+    fn equality_compares_numbers_by_value() {
+        let config = parse("(limits 1 1.0 1u 2 \"1\" one)");
+        assert_eq!(query(&config, "/limits/*[= 1]"), ["1", "1", "1"]);
+        assert_eq!(query(&config, "/limits/*[= 1.0]"), ["1", "1", "1"]);
+        assert_eq!(query(&config, "/limits/*[= 1u]"), ["1", "1", "1"]);
+        assert_eq!(query(&config, "/limits/@float[= 1u]"), ["1"]);
+        assert_eq!(query(&config, "/limits/@uint[= 1.0]"), ["1"]);
+        assert_eq!(query(&config, "/limits/@int[!= 1u]"), ["2"]);
+        assert_eq!(query(&config, "/limits/*[= \"1\"]"), ["1"]);
+        assert_eq!(query(&config, "/limits/@str[= 1]").len(), 0);
+        assert_eq!(query(&config, "/limits[#-1 = one]").len(), 1);
+    }
+
+    #[test]
+    fn invalid_predicate_literals() {
+        let config = parse("(a 1)");
+        let e = config.query("/a[#1 = 99999999999999999999]").unwrap_err();
+        assert_eq!(
+            (e.pos, e.message.as_str()),
+            (8, "integer literal out of range")
+        );
+        let e = config.query("/a[= 99999999999999999999u]").unwrap_err();
+        assert_eq!(
+            (e.pos, e.message.as_str()),
+            (5, "integer literal out of range")
+        );
+        let e = config.query("/a[= -99999999999999999999]").unwrap_err();
+        assert_eq!(e.message, "integer literal out of range");
+        let e = config.query("/a[= (1)]").unwrap_err();
+        assert_eq!((e.pos, e.message.as_str()), (5, "expected a constant"));
+        let e = config.query("/a[= 1 2]").unwrap_err();
+        assert_eq!(e.message, "expected a constant");
+    }
+}
+
+#[cfg(test)]
+mod gast_schema {
+    use crate::gast::diff::Step;
+    use crate::gast::schema::Schema;
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::fast;
+
+    const SCHEMA: &str = "
+        (define port (or int uint))
+        (define server
+          (form server
+            (alist (name str) (port port) (? tags (* sym)) (? mode (enum fast safe)))))
+        (define limits (list (+ number) . sym))
+        (root (form config (* server) (? limits)))";
+
+    fn messages(input: &str) -> Vec<(String, Vec<Step>, Option<usize>)> {
+        let schema = Schema::parse(SCHEMA, Handle::new("<schema>".to_string())).unwrap();
+        let forms = fast::parse(input, Handle::new("<test>".to_string())).unwrap();
+        schema
+            .validate_unit(&forms)
+            .into_iter()
+            .map(|v| (v.message, v.path, v.location.map(|x| x.line)))
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let valid = "(config
+            (server (name \"a\") (port 80) (tags web db))
+            (server (port . 443u) (mode safe) (name . \"b\"))
+            (1 2.5 . max))";
+        assert_eq!(messages(valid), []);
+
+        let invalid = "(config
+            (server (name a) (port 80) (port 81) (colour red))
+            (server (tags 1) (mode slow))
+            (1 x))
+            (other)";
+        let e = |s: &str, path: &[usize], line| {
+            let path = path.iter().map(|&i| Step::Elem(i)).collect();
+            (s.to_string(), path, Some(line))
+        };
+        assert_eq!(
+            messages(invalid),
+            [
+                e("expected str, found a", &[0, 1, 1, 1], 2),
+                e("duplicate key port", &[0, 1, 3], 2),
+                e("unknown key colour", &[0, 1, 4], 2),
+                e("expected sym, found 1", &[0, 2, 1, 1], 3),
+                e("expected (enum fast safe), found slow", &[0, 2, 2, 1], 3),
+                e("missing required key name", &[0, 2], 3),
+                e("missing required key port", &[0, 2], 3),
+                e("expected number, found x", &[0, 3, 1], 4),
+                e("missing dotted tail in (1 x)", &[0, 3], 4),
+                e("expected (config ...), found (other ...)", &[1], 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_schemas() {
+        let path = Handle::new("<schema>".to_string());
+        for bad in [
+            "(root foo)",
+            "(define a int)",
+            "(define a (or a int)) (root a)",
+            "(define int str) (root int)",
+            "(root (form server (alist (name str)) int))",
+            "(root (list (? int str)))",
+            "(root int) (root str)",
+        ] {
+            assert!(Schema::parse(bad, path.clone()).is_err(), "{}", bad);
+        }
+        let e = Schema::parse("(root (enum 99999999999999999999))", path).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "integer literal out of range at <schema>:1:13"
+        );
+    }
+
+    #[test]
+    fn messages_and_backtracking() {
+        let path = Handle::new("<schema>".to_string());
+        let schema = Schema::parse("(root (list (enum \"a\" 1.0 2u) . int))", path).unwrap();
+        let forms = fast::parse("(\"b\")", Handle::new("<test>".to_string())).unwrap();
+        let v = schema.validate_unit(&forms);
+        assert_eq!(
+            v[0].to_string(),
+            "expected (enum \"a\" 1.0 2u), found \"b\""
+        );
+        assert_eq!(v[1].to_string(), "missing dotted tail in (\"b\")");
+        let forms = fast::parse("\n  (x)", Handle::new("<test>".to_string())).unwrap();
+        let v = schema.validate_unit(&forms);
+        assert_eq!(
+            v[0].to_string(),
+            "<test>:2:4: expected (enum \"a\" 1.0 2u), found x"
+        );
+        // constants are written as the grammar reads them
+        let big = format!("1{}.0", "0".repeat(300));
+        let schema = format!("(root (enum {}))", big);
+        let schema = Schema::parse(&schema, Handle::new("<schema>".to_string())).unwrap();
+        let forms = fast::parse("x", Handle::new("<test>".to_string())).unwrap();
+        let message = &schema.validate_unit(&forms)[0].message;
+        assert_eq!(*message, format!("expected (enum {}), found x", big));
+
+        // every way of splitting the elements among the parts fails
+        let parts = "(* any) ".repeat(12);
+        let schema = format!("(root (list {}int))", parts);
+        let schema = Schema::parse(&schema, Handle::new("<schema>".to_string())).unwrap();
+        let list = format!("({})", "x ".repeat(40));
+        let forms = fast::parse(&list, Handle::new("<test>".to_string())).unwrap();
+        assert_eq!(schema.validate_unit(&forms).len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod diagnostics {
+    use crate::diagnostics::{Label, Report};
+    use crate::gast::Handle;
+    use crate::syntax::sexpr::{parse, recover};
+
+    #[test]
+    fn renders_labels_and_notes() {
+        let source = "(define x 1)\n(define\n  x\t2)\n";
+        let report = Report::error("`x` is defined twice")
+            .with_path("a.sexpr")
+            .with_label(Label::primary(23..24, "redefined here"))
+            .with_label(Label::secondary(8..9, "first defined here"))
+            .with_note("each name can only be defined once");
+        let expected = "\
+error: `x` is defined twice
+ --> a.sexpr:3:3
+  |
+1 | (define x 1)
+  |         - first defined here
+...
+3 |   x    2)
+  |   ^ redefined here
+  |
+  = note: each name can only be defined once
+";
+        assert_eq!(report.render(source, false), expected);
+        let colored = report.render(source, true);
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m"));
+    }
+
+    #[test]
+    fn widens_spans_to_whole_chars() {
+        let render = |span| {
+            let report = Report::error("bad").with_label(Label::primary(span, "here"));
+            let out = report.render("(éé)\n", false);
+            out.lines().last().unwrap().to_string()
+        };
+        // both ends inside a char
+        assert_eq!(render(2..4), "  |  ^^ here");
+        assert_eq!(render(1..2), "  |  ^ here");
+        assert_eq!(render(4..5), "  |   ^ here");
+        // past the end of the source, or ending before it starts
+        assert_eq!(render(4..100), "  |   ^^ here");
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = 4..2;
+        assert_eq!(render(inverted), "  |   ^ here");
+        let report = Report::error("bad").with_label(Label::primary(2..4, "here"));
+        assert!(report.render("(éé)", false).contains(" --> <input>:1:2\n"));
+    }
+
+    #[test]
+    fn converts_parse_errors() {
+        let source = "(a b\n  (c d]";
+        let e = parse(source, Handle::new("<test>".to_string())).unwrap_err();
+        let rendered = Report::from(&e).render(source, false);
+        assert!(rendered.starts_with("error: "));
+        assert!(rendered.contains("\n2 |   (c d]\n"));
+
+        let r = recover::parse_recovering(source, Handle::new("<test>".to_string()));
+        let rendered = Report::from(&r.diagnostics[0]).render(source, false);
+        assert!(rendered.contains(" --> <test>:2:"));
+        assert!(rendered.contains('^'));
+    }
+}
+
+#[cfg(test)]
+mod error {
+    use std::error::Error;
+    use std::io::ErrorKind;
+
+    use crate::error::{CompilerError, RuntimeError, SyntaxMatchError};
+    use crate::gast::binary::DecodeError;
+    use crate::gast::csexp::CsexpError;
+    use crate::gast::diff::PatchError;
+    use crate::gast::query::Query;
+    use crate::gast::schema::Schema;
+    use crate::gast::symbol::{Location, Symbol};
+    use crate::gast::Handle;
+    use crate::syntax::sexpr;
+
+    fn boxed(path: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(sexpr::file_parse(path)?.len())
+    }
+
+    #[test]
+    fn messages_and_sources() {
+        let e = boxed("no/such/file.sexpr").unwrap_err();
+        assert_eq!(e.to_string(), "cannot read `no/such/file.sexpr`");
+        let io = e
+            .source()
+            .unwrap()
+            .downcast_ref::<std::io::Error>()
+            .unwrap();
+        assert_eq!(io.kind(), ErrorKind::NotFound);
+
+        // wrapped errors are printed, not chained
+        let parse = sexpr::parse("(a", Handle::new("<test>".to_string())).unwrap_err();
+        let e: CompilerError<_> = CompilerError::ParseError(parse);
+        assert!(e.to_string().starts_with("parse error: "));
+        assert!(e.source().is_none());
+
+        let loc = Location::new(Handle::new("a.sexpr".to_string()), 3, 7, 40);
+        let sym = Handle::new(Symbol::from("if", &loc));
+        let e = RuntimeError::SyntaxError(SyntaxMatchError::SyntaxMatchError(sym));
+        assert_eq!(e.to_string(), "invalid syntax for `if` at a.sexpr:3:7");
+        assert!(e.source().is_none());
+
+        let errors: Vec<Box<dyn Error>> = vec![
+            Box::new(CsexpError::TooDeep(3)),
+            Box::new(DecodeError::InvalidTag(42)),
+            Box::new(PatchError::Mismatch(2)),
+            Box::new(Schema::parse("(root nope)", Handle::new("s".to_string())).unwrap_err()),
+            Box::new(Query::parse("port").unwrap_err()),
+        ];
+        let messages: Vec<_> = errors.iter().map(|x| x.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "lists nested too deeply at 3",
+                "invalid tag 42",
+                "edit 2: the tree does not hold the old value",
+                "unknown type at s:1:7",
+                "expected `/` or `//` at 0",
+            ]
+        );
+    }
+}
+
+#[cfg(all(test, feature = "lsp"))]
+mod lsp {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use crate::lsp::{read_message, run, Server};
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Value {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "sexpr", "version": 1, "text": text } },
+        });
+        server.handle(&msg).remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let msg = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        server.handle(&msg).remove(0)["result"].clone()
+    }
+
+    fn at(uri: &str, line: usize, character: usize) -> Value {
+        json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    fn document_features() {
+        let mut server = Server::new();
+        let caps = request(&mut server, "initialize", json!({}));
+        assert_eq!(caps["capabilities"]["definitionProvider"], true);
+        assert_eq!(caps["capabilities"]["textDocumentSync"]["change"], 1);
+
+        let uri = "file:///a.sexpr";
+        let published = open(&mut server, uri, "(define (f x)\n  (g x)\n(define y 1)");
+        let diagnostics = &published["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{
+                    "range": { "start": { "line": 1, "character": 7 }, "end": { "line": 1, "character": 7 } },
+                    "text": ")",
+                }],
+            },
+        });
+        let published = server.handle(&change).remove(0);
+        assert_eq!(published["params"]["diagnostics"], json!([]));
+        let text = server.document(uri).unwrap().text();
+        assert_eq!(text, "(define (f x)\n  (g x))\n(define y 1)");
+
+        // an inverted range is taken with its ends swapped
+        let mut inverted = change.clone();
+        inverted["params"]["contentChanges"] = json!([{
+            "range": { "start": { "line": 1, "character": 6 }, "end": { "line": 1, "character": 2 } },
+            "text": "(h x",
+        }]);
+        server.handle(&inverted);
+        let text = server.document(uri).unwrap().text();
+        assert_eq!(text, "(define (f x)\n  (h x))\n(define y 1)");
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", at(uri, 0, 0));
+        let names: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["name"].clone(), x["kind"].clone()))
+            .collect();
+        assert_eq!(names, [(json!("f"), json!(12)), (json!("y"), json!(13))]);
+
+        let folds = request(&mut server, "textDocument/foldingRange", at(uri, 0, 0));
+        assert_eq!(folds, json!([{ "startLine": 0, "endLine": 1 }]));
+
+        let pair = request(&mut server, "textDocument/documentHighlight", at(uri, 1, 8));
+        assert_eq!(
+            pair[0]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+        assert_eq!(
+            pair[1]["range"]["start"],
+            json!({ "line": 1, "character": 7 })
+        );
+        let commented = "file:///c.sexpr";
+        open(&mut server, commented, "(; (\n a)");
+        let pair = request(
+            &mut server,
+            "textDocument/documentHighlight",
+            at(commented, 1, 3),
+        );
+        assert_eq!(
+            pair[0]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+
+        let other = "file:///b.sexpr";
+        open(&mut server, other, "; uses f\n(f y)   ; call\n\n\n(g)");
+        let defs = request(&mut server, "textDocument/definition", at(other, 1, 1));
+        assert_eq!(
+            defs,
+            json!([{
+                "uri": uri,
+                "range": { "start": { "line": 0, "character": 9 }, "end": { "line": 0, "character": 10 } },
+            }])
+        );
+
+        let edits = request(&mut server, "textDocument/formatting", at(other, 0, 0));
+        assert_eq!(edits[0]["newText"], "; uses f\n(f y) ; call\n\n(g)\n");
+
+        // responses from the client get no answer
+        let response = json!({ "jsonrpc": "2.0", "id": 7, "result": null });
+        assert_eq!(server.handle(&response), Vec::<Value>::new());
+
+        let long = "file:///c.sexpr";
+        let text = format!("\"{}\" (a 99999999999999999999)", "x".repeat(100));
+        let published = open(&mut server, long, &text);
+        let diagnostics = &published["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["message"], "integer literal out of range");
+        open(&mut server, long, &text[..102]);
+        let symbols = request(&mut server, "textDocument/documentSymbol", at(long, 0, 0));
+        assert_eq!(symbols[0]["name"].as_str().unwrap().chars().count(), 40);
+
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///d.mexpr", "text": "f(99999999999999999999)" } },
+        });
+        let published = server.handle(&msg).remove(0);
+        assert_eq!(
+            published["params"]["diagnostics"].as_array().unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn stdio_transport() {
+        let mut input = String::new();
+        for (id, method) in [
+            (Some(1), "initialize"),
+            (Some(2), "shutdown"),
+            (None, "exit"),
+        ] {
+            let mut msg = json!({ "jsonrpc": "2.0", "method": method });
+            if let Some(id) = id {
+                msg["id"] = json!(id);
+            }
+            let body = msg.to_string();
+            input += &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        }
+        let mut output = vec![];
+        assert_eq!(run(Cursor::new(input), &mut output).unwrap(), 0);
+
+        let mut output = Cursor::new(output);
+        let mut ids = vec![];
+        while let Some(body) = read_message(&mut output).unwrap() {
+            ids.push(serde_json::from_str::<Value>(&body).unwrap()["id"].clone());
+        }
+        assert_eq!(ids, [json!(1), json!(2)]);
+    }
+}
+
+#[cfg(test)]
+mod unit_parse {
+    use crate::syntax::{mexpr, sexpr, Balance, UnitError};
+
+    fn closers<E>(r: Result<crate::gast::GAst, UnitError<E>>) -> Option<String> {
+        match r {
+            Err(UnitError::Incomplete { expected_closers }) => {
+                Some(expected_closers.into_iter().collect())
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert_eq!(closers(sexpr::repl_parse("(foo")).as_deref(), Some(")"));
+        assert_eq!(
+            closers(sexpr::repl_parse("(a [b \"c")).as_deref(),
+            Some("\"])")
+        );
+        // `;` in a list is a symbol, not a comment
+        assert_eq!(sexpr::repl_parse("(a ;)\n").unwrap().to_string(), "(a ;)");
+        assert_eq!(closers(sexpr::repl_parse("  ")).as_deref(), Some(""));
+        assert!(sexpr::repl_parse("(a]").is_err());
+        assert_eq!(closers(sexpr::repl_parse("(a]")), None);
+        assert_eq!(closers(sexpr::repl_parse("(a . b c")), None);
+        assert_eq!(closers(sexpr::repl_parse("a b")), None);
+        assert_eq!(sexpr::repl_parse("(a b)").unwrap().to_string(), "(a b)");
+
+        assert_eq!(
+            closers(mexpr::repl_parse("f(a, [b # )\n")).as_deref(),
+            Some("])")
+        );
+        assert_eq!(closers(mexpr::repl_parse("f(a))")), None);
+        assert_eq!(
+            sexpr::repl_parse("{x").unwrap_err().to_string(),
+            "needs more input, expected `}`"
+        );
+
+        let b = sexpr::balance("(a (b) \"s)\" [c");
+        assert_eq!(b.depth(), 2);
+        assert_eq!(b.expected_closers(), [']', ')']);
+        assert!(b.is_open());
+        let b = mexpr::balance("a)(\"b");
+        assert_eq!(
+            b,
+            Balance {
+                closers: vec![')'],
+                unterminated_string: Some(3),
+                unmatched: Some(1),
+            }
+        );
+        assert!(!b.is_open());
+    }
+
+    #[test]
+    fn comment_positions() {
+        // the grammar skips comments after an outermost open bracket or a
+        // quote prefix outside of lists
+        assert!(!sexpr::balance("(; (\n a)").is_open());
+        assert!(!sexpr::balance("[ ; (\n ; [\n a]").is_open());
+        assert!(!sexpr::balance("'; (\nbaz").is_open());
+        assert!(!sexpr::balance("`,@; (\n a").is_open());
+        assert!(!sexpr::balance("a'; (\n b").is_open());
+        // inside a list they are symbols
+        assert_eq!(sexpr::balance("(a ; (\n b)").depth(), 1);
+        assert_eq!(sexpr::balance("((; (\n b))").depth(), 1);
+        assert_eq!(sexpr::balance("('; (\n b)").depth(), 1);
+        assert_eq!(sexpr::balance("(a) ; (\n").depth(), 0);
+        assert_eq!(
+            sexpr::repl_parse("(; (\n a ; (\n b))").unwrap().to_string(),
+            "(a ; (b))"
+        );
+    }
+
+    #[test]
+    fn mexpr_number_literals() {
+        use crate::gast::constant::Constant;
+        use crate::gast::GAst;
+
+        // `int_lit` comes first, so unsigned literals are ints too
+        let ast = mexpr::one_unit_parse(
+            "f(0x1f, -0x1f, +7, 1., 0x1.8, 0x7fffffffffffffff)",
+            "<test>",
+        );
+        let elems = match ast.unwrap() {
+            GAst::List(x) => x.0.clone(),
+            x => panic!("{}", x),
+        };
+        let consts: Vec<_> = elems[1..]
+            .iter()
+            .map(|x| match x {
+                GAst::Const(c) => c.clone(),
+                x => panic!("{}", x),
+            })
+            .collect();
+        assert_eq!(
+            consts,
+            [
+                Constant::Int(31),
+                Constant::Int(-31),
+                Constant::Int(7),
+                Constant::Float(1.0),
+                Constant::Float(1.5),
+                Constant::Int(i64::MAX),
+            ]
+        );
+        for bad in ["f(0x8000000000000000)", "f(-0x8000000000000001)"] {
+            match mexpr::one_unit_parse(bad, "<test>") {
+                Err(UnitError::Syntax(e)) => {
+                    assert_eq!(e.variant.message(), "integer literal out of range")
+                }
+                x => panic!("{}: {:?}", bad, x),
+            }
+        }
+        assert!(mexpr::one_unit_parse("f(-0x8000000000000000)", "<test>").is_ok());
+    }
+}
+
+#[cfg(test)]
+mod format {
+    use crate::gast::Handle;
+    use crate::syntax::format::{format_mexpr, format_sexpr, Style};
+    use crate::syntax::mexpr;
+
+    fn path() -> Handle<String> {
+        Handle::new("<test>".to_string())
+    }
+
+    #[test]
+    fn keeps_comments_and_follows_style() {
+        let style = Style::parse("(width 30) (indent 4) (keep begin 0)", path()).unwrap();
+        assert_eq!(style.keep("begin"), Some(0));
+
+        let input = "; head\n\n\n(define (f x) (begin (display x) (g x x x x))) ; f\n'a 'b\n; end";
+        let expected = "\
+; head
+
+(define (f x)
+    (begin
+        (display x)
+        (g x x x x))) ; f
+'a
+'b
+; end
+";
+        let formatted = format_sexpr(input, path(), &style).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_sexpr(&formatted, path(), &style).unwrap(), formatted);
+
+        // comments the grammar skips inside forms
+        let input = "(; header comment\n foo bar)\n'; quoted comment\nbaz\n";
+        assert_eq!(format_sexpr(input, path(), &style).unwrap(), input);
+
+        let input = "f(a, [1; 2], {x, y}, (p, q), g())\nh(x, # note\n  y)\n";
+        let style = Style {
+            width: 12,
+            ..Style::default()
+        };
+        let formatted = format_mexpr(input, path(), &style).unwrap();
+        assert!(formatted.ends_with("\nh(x, # note\n  y)\n"));
+        assert!(formatted.starts_with("f(\n  a,\n  [1; 2],\n"));
+        assert_eq!(
+            mexpr::parse(&formatted, path()).unwrap(),
+            mexpr::parse(input, path()).unwrap()
+        );
+
+        // out of range literals are errors, not panics
+        assert!(format_sexpr("(a 99999999999999999999)", path(), &style).is_err());
+        assert!(format_mexpr("f(99999999999999999999)", path(), &style).is_err());
+
+        let e = Style::parse("(width wide)", path()).unwrap_err();
+        assert_eq!(e.location.unwrap().colum, 2);
+        assert!(Style::parse("(colour red)", path()).is_err());
+    }
+
+    #[test]
+    fn mexpr_flat_width() {
+        // a list breaks exactly when its flat form is one column too wide
+        let inputs = [
+            "f(a, [1; \"ü\\n\"], {x, y}, (p, q), g())",
+            "{1.5, -2}",
+            "f(g(h(a, b), [c; d]), {e})",
+        ];
+        for input in inputs.iter() {
+            let ast = mexpr::one_unit_parse(input, "<test>").unwrap();
+            let flat = mexpr::printer::to_source(&ast);
+            let width = flat.chars().count();
+            let fits = Style {
+                width,
+                ..Style::default()
+            };
+            assert_eq!(mexpr::printer::to_pretty(&ast, &fits), flat);
+            let over = Style {
+                width: width - 1,
+                ..Style::default()
+            };
+            assert_ne!(mexpr::printer::to_pretty(&ast, &over), flat);
+        }
+
+        // nested lists break by their own width, from their own indent
+        let ast = mexpr::one_unit_parse(inputs[2], "<test>").unwrap();
+        let style = |width| Style {
+            width,
+            ..Style::default()
+        };
+        assert_eq!(
+            mexpr::printer::to_pretty(&ast, &style(20)),
+            "f(\n  g(h(a, b), [c; d]),\n  {e}\n)"
+        );
+        assert_eq!(
+            mexpr::printer::to_pretty(&ast, &style(19)),
+            "f(\n  g(\n    h(a, b),\n    [c; d]\n  ),\n  {e}\n)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod lint {
+    use crate::diagnostics::Severity;
+    use crate::gast::list::List;
+    use crate::gast::{GAst, Handle};
+    use crate::lint::{Config, Context, Lint, Linter, Rule};
+
+    fn path() -> Handle<String> {
+        Handle::new("<test>".to_string())
+    }
+
+    struct EmptyList;
+
+    impl Rule for EmptyList {
+        fn name(&self) -> &'static str {
+            "empty-list"
+        }
+
+        fn description(&self) -> &'static str {
+            "empty lists"
+        }
+
+        fn check_node(&self, node: &GAst, parents: &[&List], cx: &mut Context) {
+            if matches!(node, GAst::List(x) if x.0.is_empty()) {
+                cx.report_node(node, parents, "empty list");
+            }
+        }
+    }
+
+    #[test]
+    fn rules_config_and_suppression() {
+        let input = "\
+(define (f x)
+  (let ((x 1) (y 2) (y 3)) ()))
+; lint: allow(mixed-brackets)
+[a b]
+[c] ; lint: allow(dotted-pair)
+'((k . 1) (k . 2))
+";
+        let config = Config::parse("(error duplicate-key) (allow dotted-pair)", path()).unwrap();
+        let lints = Linter::new(config)
+            .with_rule(EmptyList)
+            .check_sexpr(input, path())
+            .unwrap();
+        let found: Vec<_> = lints
+            .iter()
+            .map(|x| (x.rule, x.severity, x.location.line, x.location.colum))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("empty-list", Severity::Warning, 2, 4),
+                ("shadowed-binding", Severity::Warning, 2, 10),
+                ("duplicate-key", Severity::Error, 2, 22),
+                ("mixed-brackets", Severity::Warning, 5, 1),
+                ("duplicate-key", Severity::Error, 6, 12),
+            ]
+        );
+        assert_eq!(lints[2].notes, vec!["first given at <test>:2:16"]);
+
+        let input = "\
+define(f(x), lambda(x, x)) # lint: allow(shadowed-binding)
+# lint: allow(shadowed-binding)
+define(g(y),
+  lambda(y, y))
+define(h(z), lambda(z, z))
+";
+        let lints = Linter::new(Config::default()).check_mexpr(input, path());
+        let lines: Vec<_> = lints.unwrap().iter().map(|x| x.location.line).collect();
+        assert_eq!(lines, vec![5]);
+
+        let heads = Config::parse("(heads define)", path()).unwrap();
+        let lints = Linter::new(heads)
+            .check_sexpr("(define (f) (g)) (f) '(h)", path())
+            .unwrap();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].message, "unknown head `g`");
+
+        let input = "(a)\n[; lint: allow(mixed-brackets)\n b]\n[c]";
+        let lints = Linter::new(Config::default()).check_sexpr(input, path());
+        let lines: Vec<_> = lints.unwrap().iter().map(|x| x.location.line).collect();
+        assert_eq!(lines, vec![4]);
+
+        assert!(Config::parse("(warn x)", path()).is_err());
+    }
+
+    struct SecondByte;
+
+    impl Rule for SecondByte {
+        fn name(&self) -> &'static str {
+            "second-byte"
+        }
+
+        fn description(&self) -> &'static str {
+            "the second byte of the file"
+        }
+
+        fn check_source(&self, cx: &mut Context) {
+            cx.report_span(2..3, "here");
+        }
+    }
+
+    #[test]
+    fn locations_and_parse_errors() {
+        let lints = Linter::new(Config::default())
+            .with_rule(SecondByte)
+            .check_sexpr("(é)", path())
+            .unwrap();
+        assert_eq!(lints[0].location.pos, 1);
+        assert_eq!(lints[0].location.colum, 2);
+
+        let e = Linter::new(Config::default())
+            .check_sexpr("(a\n (b", path())
+            .unwrap_err();
+        let lint = Lint::parse_error(&e, path());
+        assert_eq!((lint.rule, lint.severity), ("parse-error", Severity::Error));
+        assert_eq!((lint.location.line, lint.location.colum), (2, 4));
+        assert_eq!(lint.span, 6..6);
+    }
+}
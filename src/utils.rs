@@ -1,10 +1,9 @@
-use std::collections::{HashMap, VecDeque};
-use std::{iter::FromIterator, sync::Mutex};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
 
 // use lazy_static::lazy_static;
 
-use crate::gast::Handle;
-
 // lazy_static! {
 //     static ref GLOBAL_INTERN_STRING_POOL: Mutex<HashMap<Handle<String>, Handle<String>>> =
 //         Mutex::new(HashMap::new());
@@ -69,10 +68,75 @@ pub fn state_machine(
 #[inline]
 pub fn escape_str(i: &str) -> String {
     let (char_string, is_escape) = i.chars().fold((VecDeque::new(), false), state_machine);
-    assert_eq!(is_escape, false);
+    assert!(!is_escape);
     String::from_iter(char_string.iter())
 }
 
+#[inline]
+fn split_sign(i: &str) -> (bool, &str) {
+    if let Some(x) = i.strip_prefix('-') {
+        (true, x)
+    } else {
+        (false, i.strip_prefix('+').unwrap_or(i))
+    }
+}
+
+#[inline]
+fn split_radix(i: &str) -> (u32, &str) {
+    if let Some(x) = i.strip_prefix("0x") {
+        (16, x)
+    } else if let Some(x) = i.strip_prefix("0o") {
+        (8, x)
+    } else if let Some(x) = i.strip_prefix("0b") {
+        (2, x)
+    } else {
+        (10, i)
+    }
+}
+
+/// Converts the text of an `int_lit` (`-0x1f`, `42i`, ...) to its value.
+pub fn parse_int_lit(i: &str) -> Option<i64> {
+    let (neg, i) = split_sign(i.strip_suffix('i').unwrap_or(i));
+    let (radix, digits) = split_radix(i);
+    let v = u64::from_str_radix(digits, radix).ok()?;
+    if !neg {
+        i64::try_from(v).ok()
+    } else if v == 1 << 63 {
+        Some(i64::MIN)
+    } else {
+        i64::try_from(v).ok().map(|x| -x)
+    }
+}
+
+/// Converts the text of a `uint_lit` (`42u`, `0b101u`, ...) to its value.
+pub fn parse_uint_lit(i: &str) -> Option<u64> {
+    let (radix, digits) = split_radix(i.strip_suffix('u').unwrap_or(i));
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// Converts the text of a `float_lit` (`-1.5`, `0x1.8f`, ...) to its value.
+pub fn parse_float_lit(i: &str) -> Option<f64> {
+    let (neg, rest) = split_sign(i);
+    let (radix, digits) = split_radix(rest);
+    // a trailing `f` of a hex float is a digit, not the suffix
+    if radix == 10 {
+        return i.strip_suffix('f').unwrap_or(i).parse().ok();
+    }
+    let digits = if radix == 16 {
+        digits
+    } else {
+        digits.strip_suffix('f').unwrap_or(digits)
+    };
+    let (int, frac) = digits.split_once('.')?;
+    let mut r = u64::from_str_radix(int, radix).ok()? as f64;
+    let mut scale = 1.0;
+    for c in frac.chars() {
+        scale /= radix as f64;
+        r += c.to_digit(radix)? as f64 * scale;
+    }
+    Some(if neg { -r } else { r })
+}
+
 /*
 #[inline]
 pub fn str2char(i: &str) -> char {